@@ -1,4 +1,4 @@
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Index {
     pub path: String,
     pub id: i64,
@@ -6,4 +6,8 @@ pub struct Index {
     pub version: String,
     pub lib_version: String,
     pub icon: String,
+    #[serde(default)]
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
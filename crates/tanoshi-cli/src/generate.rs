@@ -1,3 +1,4 @@
+use sha2::{Digest, Sha256};
 use tanoshi_vm::bus::ExtensionBus;
 
 use crate::data::Index;
@@ -10,13 +11,23 @@ pub async fn generate_json(bus: ExtensionBus) -> Result<(), Box<dyn std::error::
         .list()
         .await?
         .iter()
-        .map(|source| Index {
-            path: format!("library/{}.wasm", source.name),
-            id: source.id,
-            name: source.name.clone(),
-            version: source.version.to_string(),
-            lib_version: source.lib_version.to_string(),
-            icon: source.icon.clone(),
+        .map(|source| {
+            let wasm_path = format!("library/{}.wasm", source.name);
+            // wasm file may be copied to repo later, sign will fill it then
+            let sha256 = std::fs::read(path.join(&wasm_path))
+                .map(|raw| format!("{:x}", Sha256::digest(&raw)))
+                .unwrap_or_default();
+
+            Index {
+                path: wasm_path,
+                id: source.id,
+                name: source.name.clone(),
+                version: source.version.to_string(),
+                lib_version: source.lib_version.to_string(),
+                icon: source.icon.clone(),
+                sha256,
+                signature: None,
+            }
         })
         .collect::<Vec<Index>>();
 
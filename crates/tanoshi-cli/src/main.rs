@@ -2,10 +2,20 @@ extern crate log;
 
 mod data;
 mod generate;
+mod sign;
 mod test;
 
+use std::sync::Arc;
+
 use clap::{AppSettings, Clap};
-use tanoshi_vm::{bus::ExtensionBus, vm};
+use tanoshi_vm::{
+    bus::{ExtensionBus, Timeouts},
+    limits::Limits,
+    logs::ExtensionLogs,
+    storage::MemoryStorage,
+    vm,
+    watcher::LoadedSources,
+};
 
 #[derive(Clap)]
 #[clap(version = "0.1.1", author = "Muhammad Fadhlika <fadhlika@gmail.com>")]
@@ -23,6 +33,8 @@ enum SubCommand {
     Compile,
     GenerateJson,
     Test(TestOption),
+    Keygen(KeygenOption),
+    Sign(SignOption),
 }
 
 #[derive(Clap)]
@@ -31,6 +43,20 @@ struct TestOption {
     selector: Option<String>,
 }
 
+#[derive(Clap)]
+struct KeygenOption {
+    /// Name of generated key files, `<name>.key` and `<name>.pub`
+    #[clap(long, default_value = "tanoshi")]
+    name: String,
+}
+
+#[derive(Clap)]
+struct SignOption {
+    /// Path to secret key generated by keygen
+    #[clap(long)]
+    key: String,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
@@ -42,7 +68,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         None => "target/wasm32-wasi/release".to_string(),
     };
 
-    let (_, extension_tx) = vm::start();
+    let (_, extension_tx) = vm::start(
+        Limits::default(),
+        Arc::new(MemoryStorage::default()),
+        ExtensionLogs::default(),
+    );
     #[cfg(not(feature = "disable-compiler"))]
     if !matches!(opts.subcmd, SubCommand::Compile) {
         vm::load(&extension_path, extension_tx.clone()).await?;
@@ -51,7 +81,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(feature = "disable-compiler")]
     vm::load(&extension_path, extension_tx.clone()).await?;
 
-    let extension_bus = ExtensionBus::new("target/wasm32-wasi/release".to_string(), extension_tx);
+    let extension_bus = ExtensionBus::new(
+        "target/wasm32-wasi/release".to_string(),
+        extension_tx,
+        Timeouts::default(),
+        LoadedSources::default(),
+    );
 
     match opts.subcmd {
         #[cfg(not(feature = "disable-compiler"))]
@@ -64,6 +99,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         SubCommand::Test(config) => {
             test::test(extension_bus, config.selector).await?;
         }
+        SubCommand::Keygen(option) => {
+            sign::keygen(&option.name)?;
+        }
+        SubCommand::Sign(option) => {
+            sign::sign(&option.key)?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,74 @@
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+use anyhow::anyhow;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::data::Index;
+
+/// Write key with unix permission `mode`, existing key is never overwritten
+fn write_key(path: &str, contents: &str, mode: u32) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+pub fn keygen(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret = SecretKey::from_bytes(&bytes)?;
+    let public: PublicKey = (&secret).into();
+
+    let key_path = format!("{}.key", name);
+    let pub_path = format!("{}.pub", name);
+    // checked before either is written, so a failure never leaves keys that don't match
+    for path in [&key_path, &pub_path].iter() {
+        if Path::new(path).exists() {
+            return Err(anyhow!("{} already exists", path).into());
+        }
+    }
+
+    // secret key is readable only by its owner
+    write_key(&key_path, &base64::encode(secret.as_bytes()), 0o600)
+        .map_err(|e| anyhow!("failed to write {}: {}", key_path, e))?;
+    write_key(&pub_path, &base64::encode(public.as_bytes()), 0o644)
+        .map_err(|e| anyhow!("failed to write {}: {}", pub_path, e))?;
+
+    println!("public key: {}", base64::encode(public.as_bytes()));
+
+    Ok(())
+}
+
+/// Fill sha256 and signature of every extension in `repo/index.json`
+pub fn sign(key_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let key = base64::decode(std::fs::read_to_string(key_path)?.trim())?;
+    let secret = SecretKey::from_bytes(&key)?;
+    let public: PublicKey = (&secret).into();
+    let keypair = Keypair { secret, public };
+
+    let path = Path::new("repo");
+    let file = std::fs::File::open(path.join("index.json"))?;
+    let mut sources: Vec<Index> = serde_json::from_reader(file)?;
+
+    for source in sources.iter_mut() {
+        let raw = std::fs::read(path.join(&source.path))
+            .map_err(|e| anyhow!("failed to read {}: {}", source.path, e))?;
+
+        source.sha256 = format!("{:x}", Sha256::digest(&raw));
+        source.signature = Some(base64::encode(keypair.sign(&raw).to_bytes()));
+    }
+
+    let file = std::fs::File::create(path.join("index.json"))?;
+    serde_json::to_writer(&file, &sources)?;
+
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use tanoshi_lib::prelude::Param;
-use tanoshi_vm::bus::ExtensionBus;
+use tanoshi_vm::bus::{ExtensionBus, Session};
 
 pub async fn test(
     bus: ExtensionBus,
@@ -21,19 +21,27 @@ pub async fn test(
         let param = Param::default();
 
         print!("Test get_manga_list ");
-        let manga = bus.get_manga_list(detail.id, param).await?;
+        let manga = bus
+            .get_manga_list(detail.id, param, Session::default())
+            .await?;
         println!("ok");
 
         print!("Test get_manga_info {} ", manga[0].path.clone());
-        let _ = bus.get_manga_info(detail.id, manga[0].path.clone()).await?;
+        let _ = bus
+            .get_manga_info(detail.id, manga[0].path.clone(), Session::default())
+            .await?;
         println!("ok");
 
         print!("Test get_chapters {} ", manga[0].path.clone());
-        let chapters = bus.get_chapters(detail.id, manga[0].path.clone()).await?;
+        let chapters = bus
+            .get_chapters(detail.id, manga[0].path.clone(), Session::default())
+            .await?;
         println!("ok");
 
         print!("Test get_pages {} ", chapters[0].path.clone());
-        let _ = bus.get_pages(detail.id, chapters[0].path.clone()).await?;
+        let _ = bus
+            .get_pages(detail.id, chapters[0].path.clone(), Session::default())
+            .await?;
         println!("ok");
     }
 
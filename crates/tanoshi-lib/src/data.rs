@@ -40,6 +40,28 @@ impl std::fmt::Display for Version {
     }
 }
 
+impl Version {
+    /// Check an extension built against tanoshi-lib `self` can be run by host built against
+    /// `host`. Following semver, major must match, so does minor while major is 0, and
+    /// extension can't be built against a newer version than host's
+    pub fn check_compatible(&self, host: &Version) -> Result<(), String> {
+        let compatible = if host.major == 0 {
+            self.major == 0 && self.minor == host.minor && self.patch <= host.patch
+        } else {
+            self.major == host.major && self.minor <= host.minor
+        };
+
+        if compatible {
+            Ok(())
+        } else {
+            Err(format!(
+                "extension is built against tanoshi-lib {}, which isn't compatible with {}",
+                self, host
+            ))
+        }
+    }
+}
+
 // https://serde.rs/string-or-struct.html
 fn string_or_struct<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
@@ -99,6 +121,13 @@ pub struct Source {
     pub need_login: bool,
     #[serde(default = "Vec::new")]
     pub languages: Vec<String>,
+    /// Host patterns the extension may send http request to, e.g. `example.com` or
+    /// `*.example.com`. If empty, only host of `url` and its subdomains are allowed
+    #[serde(default = "Vec::new")]
+    pub allowed_hosts: Vec<String>,
+    /// Settings users can change, their values are set before every call
+    #[serde(default = "Vec::new")]
+    pub preferences: Vec<Preference>,
 }
 
 impl Default for Source {
@@ -112,10 +141,85 @@ impl Default for Source {
             icon: "".to_string(),
             need_login: false,
             languages: Vec::new(),
+            allowed_hosts: Vec::new(),
+            preferences: Vec::new(),
+        }
+    }
+}
+
+impl Source {
+    /// Value of every preference, default of each overridden by `layers` in order.
+    /// Unknown keys and invalid values are skipped
+    pub fn preference_values(&self, layers: &[&Preferences]) -> Preferences {
+        let mut values = Preferences::new();
+        for preference in self.preferences.iter() {
+            let value = layers
+                .iter()
+                .rev()
+                .filter_map(|layer| layer.get(&preference.key))
+                .find(|value| preference.is_valid(value))
+                .cloned()
+                .unwrap_or_else(|| preference.default_value());
+            values.insert(preference.key.clone(), value);
+        }
+        values
+    }
+}
+
+/// Value of each preference by its key, toggle is `true` or `false`
+pub type Preferences = BTreeMap<String, String>;
+
+/// A setting declared by extension, e.g. preferred language or image quality
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Preference {
+    pub key: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub field: PreferenceField,
+}
+
+impl Preference {
+    /// Value used when user hasn't set one
+    pub fn default_value(&self) -> String {
+        match &self.field {
+            PreferenceField::Select { default, .. } => default.clone(),
+            PreferenceField::Toggle { default } => default.to_string(),
+            PreferenceField::Text { default } => default.clone(),
+        }
+    }
+
+    /// Whether `value` can be set to this preference
+    pub fn is_valid(&self, value: &str) -> bool {
+        match &self.field {
+            PreferenceField::Select { options, .. } => {
+                options.iter().any(|option| option.value == value)
+            }
+            PreferenceField::Toggle { .. } => value.parse::<bool>().is_ok(),
+            PreferenceField::Text { .. } => true,
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PreferenceField {
+    Select {
+        options: Vec<PreferenceOption>,
+        default: String,
+    },
+    Toggle {
+        default: bool,
+    },
+    Text {
+        default: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PreferenceOption {
+    pub title: String,
+    pub value: String,
+}
+
 /// A type represent manga details, normalized across source
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Manga {
@@ -136,10 +240,32 @@ pub struct Chapter {
     pub title: String,
     pub path: String,
     pub number: f64,
+    /// Volume the chapter belongs to, if source tells it
+    #[serde(default)]
+    pub volume: Option<i64>,
     pub scanlator: String,
     pub uploaded: chrono::NaiveDateTime,
 }
 
+/// Path of a manga or chapter parsed from a url of the source
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum UrlPath {
+    Manga(String),
+    /// `manga_path` is needed to fetch the chapter if it not yet in database
+    Chapter {
+        path: String,
+        manga_path: Option<String>,
+    },
+}
+
+/// Image of a page, fetched by extension
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PageImage {
+    pub content_type: Option<String>,
+    #[serde(with = "tanoshi_util::encoding::base64_bytes")]
+    pub bytes: Vec<u8>,
+}
+
 /// Model to login to source that require login, like mangadex to search
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SourceLogin {
@@ -149,7 +275,10 @@ pub struct SourceLogin {
     pub two_factor: Option<String>,
 }
 
-/// Result of source login
+/// Result of source login, stored per user and sent with every request the user makes.
+/// `value` is passed in `Param::auth` and added to every http request of the extension
+/// depending on `auth_type`: `bearer` as `Authorization: Bearer <value>`, `cookie` as
+/// `Cookie: <value>`, any other `auth_type` is used as header name
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SourceLoginResult {
     pub source_name: String,
@@ -193,6 +322,9 @@ pub struct Param {
     pub sort_by: Option<SortByParam>,
     pub sort_order: Option<SortOrderParam>,
     pub auth: Option<String>,
+    /// Values of fields returned by `Extension::filters`, by field key
+    #[serde(default)]
+    pub filters: Option<ParamFilterValue>,
 }
 
 impl Default for Param {
@@ -204,10 +336,34 @@ impl Default for Param {
             sort_by: Some(SortByParam::Views),
             sort_order: Some(SortOrderParam::Desc),
             auth: None,
+            filters: None,
+        }
+    }
+}
+
+impl Param {
+    /// Recently updated manga, used when extension doesn't implement `get_latest_manga`
+    pub fn latest(page: i32) -> Self {
+        Param {
+            page: Some(page),
+            sort_by: Some(SortByParam::LastUpdated),
+            sort_order: Some(SortOrderParam::Desc),
+            ..Default::default()
+        }
+    }
+
+    /// Most viewed manga, used when extension doesn't implement `get_popular_manga`
+    pub fn popular(page: i32) -> Self {
+        Param {
+            page: Some(page),
+            sort_by: Some(SortByParam::Views),
+            sort_order: Some(SortOrderParam::Desc),
+            ..Default::default()
         }
     }
 }
 
+/// Values of each filter field, a field that isn't `multi` has at most one
 pub type ParamFilterValue = HashMap<String, Vec<String>>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -219,6 +375,7 @@ pub struct Filters {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilterField {
     pub name: String,
+    /// Values to choose from, field without values is a free text input
     pub values: Option<Vec<FilterValue>>,
     #[serde(default)]
     pub multi: bool,
@@ -229,6 +386,7 @@ pub struct FilterField {
 pub struct FilterValue {
     pub title: String,
     pub value: Option<String>,
+    /// Values of other fields set along when this value is chosen, by field key
     pub related: Option<HashMap<String, String>>,
 }
 
@@ -315,6 +473,62 @@ mod test {
         );
     }
 
+    fn values(pairs: &[(&str, &str)]) -> Preferences {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_preference_values() {
+        let source = Source {
+            preferences: vec![
+                Preference {
+                    key: "quality".to_string(),
+                    title: "Image quality".to_string(),
+                    description: None,
+                    field: PreferenceField::Select {
+                        options: vec![
+                            PreferenceOption {
+                                title: "High".to_string(),
+                                value: "high".to_string(),
+                            },
+                            PreferenceOption {
+                                title: "Low".to_string(),
+                                value: "low".to_string(),
+                            },
+                        ],
+                        default: "high".to_string(),
+                    },
+                },
+                Preference {
+                    key: "nsfw".to_string(),
+                    title: "Show NSFW".to_string(),
+                    description: None,
+                    field: PreferenceField::Toggle { default: false },
+                },
+            ],
+            ..Default::default()
+        };
+
+        let server = values(&[("quality", "low"), ("nsfw", "true")]);
+        let user = values(&[
+            ("quality", "medium"),
+            ("nsfw", "false"),
+            ("unknown", "value"),
+        ]);
+
+        assert_eq!(
+            source.preference_values(&[]),
+            values(&[("quality", "high"), ("nsfw", "false")])
+        );
+        assert_eq!(
+            source.preference_values(&[&server, &user]),
+            values(&[("quality", "low"), ("nsfw", "false")])
+        );
+    }
+
     #[test]
     fn test_invalid_version_from_str() {
         let version = Version::from_str("1.2.3.4");
@@ -326,6 +540,35 @@ mod test {
         assert_eq!(version, Err(crate::error::Error::InvalidVersion));
     }
 
+    #[test]
+    fn test_version_check_compatible() {
+        let cases = [
+            ("0.24.0", "0.24.0", true),
+            ("0.24.0", "0.24.3", true),
+            ("0.24.3", "0.24.0", false),
+            ("0.23.9", "0.24.0", false),
+            ("0.25.0", "0.24.0", false),
+            ("1.0.0", "0.24.0", false),
+            ("1.2.0", "1.2.5", true),
+            ("1.1.9", "1.2.0", true),
+            ("1.3.0", "1.2.0", false),
+            ("1.2.0", "2.0.0", false),
+            ("0.24.0", "1.0.0", false),
+        ];
+
+        for (lib_version, host, compatible) in cases.iter() {
+            let lib_version = Version::from_str(lib_version).unwrap();
+            let host = Version::from_str(host).unwrap();
+            assert_eq!(
+                lib_version.check_compatible(&host).is_ok(),
+                *compatible,
+                "{} on {}",
+                lib_version,
+                host
+            );
+        }
+    }
+
     #[test]
     fn test_parse_version_from_str() {
         let source = ron::from_str::<Source>(
@@ -1,6 +1,11 @@
 use std::fmt::Debug;
 
-use crate::data::{Chapter, Manga, Param, Source, ExtensionResult, Filters};
+use tanoshi_util::http::{Headers, Request};
+
+use crate::data::{
+    Chapter, ExtensionResult, Filters, Manga, PageImage, Param, Source, SourceLogin,
+    SourceLoginResult, UrlPath,
+};
 
 /// `Extension` trait is an implementation for building extensions
 pub trait Extension: Send + Sync {
@@ -21,8 +26,19 @@ pub trait Extension: Send + Sync {
     /// * `sort_by` - Sort results by SortByParam
     /// * `sort_order` - Sort ascending or descending
     /// * `auth` - If source need login to search, this param used to provide credentials
+    /// * `filters` - Values of fields returned by `filters`, by field key
     fn get_manga_list(&self, param: Param) -> ExtensionResult<Vec<Manga>>;
 
+    /// Returns recently updated manga, by default `get_manga_list` sorted by last updated
+    fn get_latest_manga(&self, page: i32) -> ExtensionResult<Vec<Manga>> {
+        self.get_manga_list(Param::latest(page))
+    }
+
+    /// Returns popular manga, by default `get_manga_list` sorted by views
+    fn get_popular_manga(&self, page: i32) -> ExtensionResult<Vec<Manga>> {
+        self.get_manga_list(Param::popular(page))
+    }
+
     /// Returns detail of manga
     fn get_manga_info(&self, path: String) -> ExtensionResult<Manga>;
 
@@ -32,24 +48,47 @@ pub trait Extension: Send + Sync {
     /// Returns list of pages from a chapter of a manga
     fn get_pages(&self, path: String) -> ExtensionResult<Vec<String>>;
 
-    // /// Proxy image
-    // fn get_page(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-    //     let bytes = {
-    //         let resp = ureq::get(url).call()?;
-    //         let mut reader = resp.into_reader();
-    //         let mut bytes = vec![];
-    //         if reader.read_to_end(&mut bytes).is_err() {
-    //             return Err(anyhow!("error read image"));
-    //         }
-    //         bytes
-    //     };
-    //     Ok(bytes)
-    // }
-
-    // /// Login to source
-    // fn login(&self, _: SourceLogin) -> Result<SourceLoginResult, Box<dyn Error>> {
-    //     Err(anyhow!("not implemented"))
-    // }
+    /// Returns headers to fetch image of a page, e.g. `Referer` for hotlink protected source
+    fn image_request_headers(&self, _url: String) -> ExtensionResult<Headers> {
+        ExtensionResult::ok(Headers::new())
+    }
+
+    /// Fetch image of a page, by default request `url` with `image_request_headers`
+    fn get_page(&self, url: String) -> ExtensionResult<PageImage> {
+        let mut req = Request::get(&url).binary();
+        match self.image_request_headers(url).result() {
+            Ok(headers) => {
+                for (name, values) in headers {
+                    for value in values {
+                        req = req.set(&name, &value);
+                    }
+                }
+            }
+            Err(e) => return ExtensionResult::err(&e.to_string()),
+        }
+
+        match req.call().ok() {
+            Ok(res) if (200..300).contains(&res.status) => ExtensionResult::ok(PageImage {
+                content_type: res
+                    .headers
+                    .get("content-type")
+                    .and_then(|values| values.first().cloned()),
+                bytes: res.bytes,
+            }),
+            Ok(res) => ExtensionResult::err(&format!("status {}", res.status)),
+            Err(e) => ExtensionResult::err(&e.to_string()),
+        }
+    }
+
+    /// Parse a url of the source into manga or chapter path, returns `None` if url not recognized
+    fn parse_url(&self, _url: String) -> ExtensionResult<Option<UrlPath>> {
+        ExtensionResult::ok(None)
+    }
+
+    /// Login to source, only needed if `Source::need_login` is true
+    fn login(&self, _: SourceLogin) -> ExtensionResult<SourceLoginResult> {
+        ExtensionResult::err("not implemented")
+    }
 }
 
 impl Debug for dyn Extension {
@@ -5,6 +5,7 @@ use data::Version;
 pub mod data;
 pub mod error;
 pub mod extensions;
+pub mod preferences;
 pub mod prelude;
 
 pub use tanoshi_util;
@@ -31,6 +32,13 @@ macro_rules! register_extension {
             tanoshi_util::shim::write_object(&res);
         }
 
+        #[no_mangle]
+        fn set_preferences() {
+            if let Ok(obj) = tanoshi_util::shim::read_object() {
+                $crate::preferences::set_preferences(obj);
+            }
+        }
+
         #[no_mangle]
         fn filters() {
             let res = EXT.with(|ext| ext.borrow_mut().filters());
@@ -45,6 +53,30 @@ macro_rules! register_extension {
             }
         }
 
+        #[no_mangle]
+        fn get_latest_manga() {
+            if let Ok(obj) = tanoshi_util::shim::read_object() {
+                let res = EXT.with(|ext| ext.borrow_mut().get_latest_manga(obj));
+                tanoshi_util::shim::write_object(&res);
+            }
+        }
+
+        #[no_mangle]
+        fn get_popular_manga() {
+            if let Ok(obj) = tanoshi_util::shim::read_object() {
+                let res = EXT.with(|ext| ext.borrow_mut().get_popular_manga(obj));
+                tanoshi_util::shim::write_object(&res);
+            }
+        }
+
+        #[no_mangle]
+        fn parse_url() {
+            if let Ok(obj) = tanoshi_util::shim::read_object() {
+                let res = EXT.with(|ext| ext.borrow_mut().parse_url(obj));
+                tanoshi_util::shim::write_object(&res);
+            }
+        }
+
         #[no_mangle]
         fn get_manga_info() {
             if let Ok(obj) = tanoshi_util::shim::read_object() {
@@ -69,11 +101,29 @@ macro_rules! register_extension {
             }
         }
 
-        // fn get_page(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-        // }
+        #[no_mangle]
+        fn login() {
+            if let Ok(obj) = tanoshi_util::shim::read_object() {
+                let res = EXT.with(|ext| ext.borrow_mut().login(obj));
+                tanoshi_util::shim::write_object(&res);
+            }
+        }
 
-        // fn login(&self, _: SourceLogin) -> Result<SourceLoginResult, Box<dyn Error>> {
-        // }
+        #[no_mangle]
+        fn image_request_headers() {
+            if let Ok(obj) = tanoshi_util::shim::read_object() {
+                let res = EXT.with(|ext| ext.borrow_mut().image_request_headers(obj));
+                tanoshi_util::shim::write_object(&res);
+            }
+        }
+
+        #[no_mangle]
+        fn get_page() {
+            if let Ok(obj) = tanoshi_util::shim::read_object() {
+                let res = EXT.with(|ext| ext.borrow_mut().get_page(obj));
+                tanoshi_util::shim::write_object(&res);
+            }
+        }
     };
 }
 
@@ -0,0 +1,23 @@
+use std::cell::RefCell;
+
+use crate::data::Preferences;
+
+thread_local! {
+    static PREFERENCES: RefCell<Preferences> = RefCell::new(Preferences::new());
+}
+
+/// Called by `register_extension!` with values set by host before each call
+pub fn set_preferences(preferences: Preferences) {
+    PREFERENCES.with(|current| *current.borrow_mut() = preferences);
+}
+
+/// Value of preference `key` declared in `Source::preferences`, host always sets every
+/// declared preference so `None` means the key isn't declared
+pub fn preference(key: &str) -> Option<String> {
+    PREFERENCES.with(|current| current.borrow().get(key).cloned())
+}
+
+/// Value of a toggle preference
+pub fn preference_bool(key: &str) -> Option<bool> {
+    preference(key).and_then(|value| value.parse().ok())
+}
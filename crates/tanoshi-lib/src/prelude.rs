@@ -1,5 +1,6 @@
 pub use crate::data::*;
 pub use crate::extensions::*;
+pub use crate::preferences::{preference, preference_bool};
 
 pub use crate::*;
 pub use tanoshi_util;
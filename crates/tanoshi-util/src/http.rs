@@ -1,84 +1,194 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, time::Duration};
 
 pub type Headers = HashMap<String, Vec<String>>;
+
+/// Redirects followed when request doesn't set its own limit
+pub const DEFAULT_REDIRECTS: u32 = 5;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Request {
     pub method: String,
     pub url: String,
     pub headers: Option<Headers>,
     pub body: Option<String>,
+    /// Binary body, sent instead of `body` if set
+    #[serde(default, with = "crate::encoding::base64_option")]
+    pub bytes: Option<Vec<u8>>,
+    /// Timeout in milliseconds, host default is used if not set
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Maximum redirects to follow, 0 return the redirect response as is
+    #[serde(default)]
+    pub redirects: Option<u32>,
+    /// Read response body into `Response::bytes` instead of `Response::body`
+    #[serde(default)]
+    pub binary: bool,
+    /// Other headers carrying credentials, removed along with `Authorization` and `Cookie`
+    /// when request is redirected to another origin. Set by host only
+    #[serde(skip)]
+    pub credentials: Vec<String>,
 }
 
 impl Request {
-    pub fn get(url: &str) -> Request {
+    pub fn new(method: &str, url: &str) -> Request {
         Request {
-            method: "GET".to_string(),
+            method: method.to_string(),
             url: url.to_string(),
             headers: None,
             body: None,
+            bytes: None,
+            timeout: None,
+            redirects: None,
+            binary: false,
+            credentials: vec![],
         }
     }
 
+    pub fn get(url: &str) -> Request {
+        Self::new("GET", url)
+    }
+
+    pub fn post(url: &str) -> Request {
+        Self::new("POST", url)
+    }
+
     pub fn body(self, body: &str) -> Request {
         Request {
-            method: self.method,
-            url: self.url,
-            headers: self.headers,
             body: Some(body.to_string()),
+            ..self
         }
     }
 
-    pub fn set(self, name: &str, key: &str) -> Request {
-        let headers = match self.headers {
-            Some(mut headers) => {
-                if let Some(header) = headers.get_mut(name) {
-                    header.push(key.to_string());
-                } else {
-                    headers.insert(name.to_string(), vec![key.to_string()]);
-                }
+    pub fn bytes(self, bytes: &[u8]) -> Request {
+        Request {
+            bytes: Some(bytes.to_vec()),
+            ..self
+        }
+    }
 
-                Some(headers)
-            }
-            None => None,
-        };
+    pub fn timeout(self, timeout: Duration) -> Request {
+        Request {
+            timeout: Some(timeout.as_millis() as u64),
+            ..self
+        }
+    }
+
+    pub fn redirects(self, redirects: u32) -> Request {
+        Request {
+            redirects: Some(redirects),
+            ..self
+        }
+    }
+
+    pub fn binary(self) -> Request {
+        Request {
+            binary: true,
+            ..self
+        }
+    }
+
+    pub fn set(self, name: &str, key: &str) -> Request {
+        let mut headers = self.headers.unwrap_or_default();
+        if let Some(header) = headers.get_mut(name) {
+            header.push(key.to_string());
+        } else {
+            headers.insert(name.to_string(), vec![key.to_string()]);
+        }
 
         Request {
-            method: self.method,
-            url: self.url,
-            headers,
-            body: self.body,
+            headers: Some(headers),
+            ..self
         }
     }
 
+    /// Set header carrying credentials, it is removed when request is redirected to another
+    /// origin
+    pub fn credential(self, name: &str, value: &str) -> Request {
+        let mut req = self.set(name, value);
+        req.credentials.push(name.to_string());
+        req
+    }
+
     pub fn call(self) -> Response {
         http_request(self)
     }
 }
 
+/// Reason a request failed before a response is received
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HttpError {
+    /// Host is not in extension's allowed hosts
+    NotAllowed(String),
+    InvalidUrl(String),
+    Timeout,
+    TooManyRedirects,
+    Connection(String),
+    Transport(String),
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpError::NotAllowed(url) => write!(f, "host of {} is not allowed", url),
+            HttpError::InvalidUrl(message) => write!(f, "invalid url: {}", message),
+            HttpError::Timeout => write!(f, "request timed out"),
+            HttpError::TooManyRedirects => write!(f, "too many redirects"),
+            HttpError::Connection(message) => write!(f, "connection failed: {}", message),
+            HttpError::Transport(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Response {
     pub headers: Headers,
+    /// Body as text, empty for binary request
     pub body: String,
+    /// Body as bytes, only set for binary request
+    #[serde(default, with = "crate::encoding::base64_bytes")]
+    pub bytes: Vec<u8>,
+    /// Status code, 0 if request failed
     pub status: i32,
+    #[serde(default)]
+    pub error: Option<HttpError>,
+}
+
+impl Response {
+    pub fn from_error(error: HttpError) -> Response {
+        Response {
+            headers: HashMap::new(),
+            body: error.to_string(),
+            bytes: vec![],
+            status: 0,
+            error: Some(error),
+        }
+    }
+
+    /// Turn transport error into `Err`, response with any status is `Ok`
+    pub fn ok(self) -> Result<Response, HttpError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && (200..300).contains(&self.status)
+    }
 }
 
 #[cfg(all(not(feature = "__test"), not(feature = "host")))]
 pub fn http_request(req: Request) -> Response {
     if let Err(err) = crate::shim::write_object(req) {
-        return Response {
-            headers: HashMap::new(),
-            body: format!("{}", err),
-            status: 9999,
-        };
+        return Response::from_error(HttpError::Transport(format!("{}", err)));
     }
 
     unsafe { host_http_request() };
-    crate::shim::read_object().unwrap_or_else(|err| Response {
-        headers: HashMap::new(),
-        body: format!("{}", err),
-        status: 9999,
-    })
+    crate::shim::read_object()
+        .unwrap_or_else(|err| Response::from_error(HttpError::Transport(format!("{}", err))))
 }
 
 #[cfg(all(not(feature = "__test"), not(feature = "host")))]
@@ -89,60 +199,356 @@ extern "C" {
 
 #[cfg(any(feature = "__test", feature = "host"))]
 pub fn http_request(req: Request) -> Response {
-    use log::debug;
+    Client::new(Duration::from_secs(30)).request(req, |_| true)
+}
 
-    let agent = ureq::builder().user_agent("Tanoshi/0.1.0").build();
+/// Http client used by host, cookies are kept across requests for as long as client lives
+#[cfg(any(feature = "__test", feature = "host"))]
+#[derive(Clone)]
+pub struct Client {
+    agent: ureq::Agent,
+}
 
-    let mut request = agent.request(&req.method, &req.url);
-    if let Some(headers) = req.headers.as_ref() {
-        for (key, values) in headers {
-            for value in values {
-                request = request.set(key, value);
-            }
+#[cfg(any(feature = "__test", feature = "host"))]
+impl Client {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            agent: Self::builder(timeout).build(),
         }
     }
 
-    let res = if let Some(body) = req.body {
-        debug!("request => {:?}", request);
-        request.send_string(&body)
-    } else {
-        debug!("request => {:?}", request);
-        request.call()
-    };
+    /// Client connecting to addresses returned by `resolver`, e.g. to refuse addresses
+    /// a host shouldn't resolve to
+    pub fn with_resolver(timeout: Duration, resolver: impl ureq::Resolver + 'static) -> Self {
+        Self {
+            agent: Self::builder(timeout).resolver(resolver).build(),
+        }
+    }
+
+    fn builder(timeout: Duration) -> ureq::AgentBuilder {
+        // redirects are followed by client to check every location and apply per request limit
+        ureq::builder()
+            .user_agent(&format!("Tanoshi/{}", env!("CARGO_PKG_VERSION")))
+            .timeout(timeout)
+            .redirects(0)
+    }
 
-    match res {
-        Ok(response) => {
-            debug!("response ok => {:?}", response);
+    /// Send request, `is_allowed` is checked for url of request and each redirect
+    pub fn request(&self, req: Request, is_allowed: impl Fn(&str) -> bool) -> Response {
+        use log::debug;
+
+        let mut req = req;
+        let redirects = req.redirects.unwrap_or(DEFAULT_REDIRECTS);
+        for _ in 0..=redirects {
+            if !is_allowed(&req.url) {
+                return Response::from_error(HttpError::NotAllowed(req.url));
+            }
+
+            debug!("request => {} {}", req.method, req.url);
+            let response = match self.send(&req) {
+                Ok(response) => response,
+                Err(err) => {
+                    debug!("response error => {:?}", err);
+                    return Response::from_error(err);
+                }
+            };
+            debug!("response => {:?}", response);
 
             let status = response.status();
-            Response {
-                headers: {
-                    let mut headers = Headers::new();
-                    for name in response.headers_names() {
-                        headers.insert(
-                            name.clone(),
-                            response
-                                .all(&name)
-                                .iter()
-                                .map(|all| all.to_string())
-                                .collect(),
-                        );
-                    }
+            let location = match response.header("location") {
+                Some(location) if (300..400).contains(&status) && redirects > 0 => location,
+                _ => return read_response(response, req.binary),
+            };
 
-                    headers
-                },
-                body: response.into_string().unwrap_or_else(|_| "".to_string()),
-                status: status as i32,
+            let (from, to) = match url::Url::parse(&req.url)
+                .and_then(|from| from.join(location).map(|to| (from, to)))
+            {
+                Ok(urls) => urls,
+                Err(err) => return Response::from_error(HttpError::InvalidUrl(err.to_string())),
+            };
+            // credentials are meant for the origin they are set for only
+            if from.origin() != to.origin() {
+                strip_credentials(&mut req);
+            }
+            req.url = to.to_string();
+            if matches!(status, 301..=303) && req.method != "HEAD" {
+                req.method = "GET".to_string();
+                req.body = None;
+                req.bytes = None;
             }
         }
-        Err(err) => {
-            debug!("response error => {:?}", err);
 
-            Response {
-                headers: HashMap::new(),
-                body: format!("{}", err),
-                status: 9999,
+        Response::from_error(HttpError::TooManyRedirects)
+    }
+
+    fn send(&self, req: &Request) -> Result<ureq::Response, HttpError> {
+        let mut request = self.agent.request(&req.method, &req.url);
+        if let Some(headers) = req.headers.as_ref() {
+            for (key, values) in headers {
+                for value in values {
+                    request = request.set(key, value);
+                }
             }
         }
+        if let Some(timeout) = req.timeout {
+            request = request.timeout(Duration::from_millis(timeout));
+        }
+
+        let res = if let Some(bytes) = req.bytes.as_ref() {
+            request.send_bytes(bytes)
+        } else if let Some(body) = req.body.as_ref() {
+            request.send_string(body)
+        } else {
+            request.call()
+        };
+
+        match res {
+            Ok(response) => Ok(response),
+            // error status is still a response for extension to handle
+            Err(ureq::Error::Status(_, response)) => Ok(response),
+            Err(ureq::Error::Transport(transport)) => Err(transport.into()),
+        }
+    }
+}
+
+/// Headers carrying credentials, removed when request is redirected to another origin
+#[cfg(any(feature = "__test", feature = "host"))]
+const CREDENTIAL_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+
+#[cfg(any(feature = "__test", feature = "host"))]
+fn strip_credentials(req: &mut Request) {
+    let credentials = &req.credentials;
+    if let Some(headers) = req.headers.as_mut() {
+        headers.retain(|name, _| {
+            !CREDENTIAL_HEADERS
+                .iter()
+                .copied()
+                .chain(credentials.iter().map(String::as_str))
+                .any(|credential| name.eq_ignore_ascii_case(credential))
+        });
+    }
+}
+
+#[cfg(any(feature = "__test", feature = "host"))]
+fn read_response(response: ureq::Response, binary: bool) -> Response {
+    use std::io::Read;
+
+    let status = response.status() as i32;
+    let mut headers = Headers::new();
+    for name in response.headers_names() {
+        headers.insert(
+            name.clone(),
+            response
+                .all(&name)
+                .iter()
+                .map(|all| all.to_string())
+                .collect(),
+        );
+    }
+
+    let mut bytes = vec![];
+    if let Err(err) = response.into_reader().read_to_end(&mut bytes) {
+        return Response::from_error(io_error(&err));
+    }
+
+    let (body, bytes) = if binary {
+        (String::new(), bytes)
+    } else {
+        (String::from_utf8_lossy(&bytes).to_string(), vec![])
+    };
+
+    Response {
+        headers,
+        body,
+        bytes,
+        status,
+        error: None,
+    }
+}
+
+#[cfg(any(feature = "__test", feature = "host"))]
+fn io_error(err: &std::io::Error) -> HttpError {
+    match err.kind() {
+        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => HttpError::Timeout,
+        _ => HttpError::Transport(err.to_string()),
+    }
+}
+
+#[cfg(any(feature = "__test", feature = "host"))]
+impl From<ureq::Transport> for HttpError {
+    fn from(transport: ureq::Transport) -> Self {
+        use std::error::Error;
+        use ureq::ErrorKind;
+
+        let message = transport.to_string();
+        match transport.kind() {
+            ErrorKind::InvalidUrl | ErrorKind::UnknownScheme => HttpError::InvalidUrl(message),
+            ErrorKind::Dns | ErrorKind::ConnectionFailed => HttpError::Connection(message),
+            ErrorKind::TooManyRedirects => HttpError::TooManyRedirects,
+            ErrorKind::Io => match transport
+                .source()
+                .and_then(|source| source.downcast_ref::<std::io::Error>())
+            {
+                Some(err) => io_error(err),
+                None => HttpError::Transport(message),
+            },
+            _ => HttpError::Transport(message),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "__test", feature = "host")))]
+mod test {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nConnection: close\r\n";
+
+    /// Head and body of a request received by server
+    type Received = (String, Vec<u8>);
+
+    /// Serve a response for each of `responses` with `{base}` replaced by url of the server,
+    /// `OK` responses echo body of the request. Returns url of the server and requests it
+    /// received, lowercased head and body of each
+    fn serve(responses: Vec<String>) -> (String, JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let url = base.clone();
+        let handle = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut head = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    head.push_str(&line.to_lowercase());
+                }
+                let length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length: "))
+                    .map_or(0, |length| length.trim().parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut stream = reader.into_inner();
+                if response == OK {
+                    write!(stream, "{}Content-Length: {}\r\n\r\n", OK, body.len()).unwrap();
+                    stream.write_all(&body).unwrap();
+                } else {
+                    let response = response.replace("{base}", &base);
+                    stream.write_all(response.as_bytes()).unwrap();
+                }
+                requests.push((head, body));
+            }
+            requests
+        });
+
+        (url, handle)
+    }
+
+    fn redirect(location: &str) -> String {
+        format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            location
+        )
+    }
+
+    fn client() -> Client {
+        Client::new(Duration::from_secs(5))
+    }
+
+    fn with_credentials(url: &str) -> Request {
+        Request::get(url)
+            .set("Authorization", "Bearer token")
+            .set("Cookie", "session=1")
+            .credential("X-Session", "token")
+            .set("Accept", "image/*")
+    }
+
+    #[test]
+    fn test_redirect_same_origin_keeps_credentials() {
+        let (url, server) = serve(vec![redirect("{base}/next"), OK.to_string()]);
+
+        let res = client().request(with_credentials(&format!("{}/", url)), |_| true);
+        assert_eq!(res.status, 200);
+
+        let requests = server.join().unwrap();
+        let (head, _) = &requests[1];
+        assert!(head.starts_with("get /next "));
+        assert!(head.contains("authorization: bearer token"));
+        assert!(head.contains("cookie: session=1"));
+        assert!(head.contains("x-session: token"));
+    }
+
+    #[test]
+    fn test_redirect_cross_origin_strips_credentials() {
+        let (other, other_server) = serve(vec![OK.to_string()]);
+        let (url, server) = serve(vec![redirect(&format!("{}/image", other))]);
+
+        let res = client().request(with_credentials(&format!("{}/", url)), |_| true);
+        assert_eq!(res.status, 200);
+        server.join().unwrap();
+
+        let requests = other_server.join().unwrap();
+        let (head, _) = &requests[0];
+        assert!(head.starts_with("get /image "));
+        assert!(!head.contains("authorization"));
+        assert!(!head.contains("cookie"));
+        assert!(!head.contains("x-session"));
+        assert!(head.contains("accept: image/*"));
+    }
+
+    #[test]
+    fn test_redirect_not_allowed() {
+        let (url, server) = serve(vec![redirect("{base}/blocked")]);
+
+        let res = client().request(Request::get(&format!("{}/", url)), |url| {
+            !url.ends_with("/blocked")
+        });
+        assert_eq!(
+            res.error,
+            Some(HttpError::NotAllowed(format!("{}/blocked", url)))
+        );
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_redirect_limit() {
+        let (url, server) = serve(vec![redirect("{base}/"); 3]);
+
+        let res = client().request(Request::get(&format!("{}/", url)).redirects(2), |_| true);
+        assert_eq!(res.error, Some(HttpError::TooManyRedirects));
+        assert_eq!(server.join().unwrap().len(), 3);
+
+        // redirect is returned as is when it isn't followed
+        let (url, server) = serve(vec![redirect("{base}/next")]);
+        let res = client().request(Request::get(&format!("{}/", url)).redirects(0), |_| true);
+        assert_eq!(res.status, 302);
+        assert_eq!(
+            res.headers.get("location"),
+            Some(&vec![format!("{}/next", url)])
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_binary_body() {
+        let bytes = vec![0, 159, 146, 150, 255, 10, 13];
+        let (url, server) = serve(vec![OK.to_string()]);
+
+        let res = client().request(Request::post(&url).bytes(&bytes).binary(), |_| true);
+        assert_eq!(res.status, 200);
+        assert_eq!(res.bytes, bytes);
+        assert!(res.body.is_empty());
+        assert_eq!(server.join().unwrap()[0].1, bytes);
     }
 }
@@ -0,0 +1,59 @@
+//! Private key-value storage of the extension, e.g. for session tokens or lookup tables.
+//! Values are kept by host per source and survive restart and reinstall
+
+#[cfg(all(not(feature = "__test"), not(feature = "host")))]
+pub fn get(key: &str) -> Result<Option<String>, String> {
+    crate::shim::write_object(key).map_err(|e| e.to_string())?;
+    unsafe { host_kv_get() };
+    crate::shim::read_object().map_err(|e| e.to_string())?
+}
+
+#[cfg(all(not(feature = "__test"), not(feature = "host")))]
+pub fn set(key: &str, value: &str) -> Result<(), String> {
+    crate::shim::write_object((key, value)).map_err(|e| e.to_string())?;
+    unsafe { host_kv_set() };
+    crate::shim::read_object().map_err(|e| e.to_string())?
+}
+
+#[cfg(all(not(feature = "__test"), not(feature = "host")))]
+pub fn delete(key: &str) -> Result<(), String> {
+    crate::shim::write_object(key).map_err(|e| e.to_string())?;
+    unsafe { host_kv_delete() };
+    crate::shim::read_object().map_err(|e| e.to_string())?
+}
+
+#[cfg(all(not(feature = "__test"), not(feature = "host")))]
+#[link(wasm_import_module = "tanoshi")]
+extern "C" {
+    fn host_kv_get();
+    fn host_kv_set();
+    fn host_kv_delete();
+}
+
+#[cfg(any(feature = "__test", feature = "host"))]
+thread_local! {
+    /// Values of extension running natively, e.g. in its tests
+    static VALUES: std::cell::RefCell<std::collections::HashMap<String, String>> =
+        Default::default();
+}
+
+#[cfg(any(feature = "__test", feature = "host"))]
+pub fn get(key: &str) -> Result<Option<String>, String> {
+    Ok(VALUES.with(|values| values.borrow().get(key).cloned()))
+}
+
+#[cfg(any(feature = "__test", feature = "host"))]
+pub fn set(key: &str, value: &str) -> Result<(), String> {
+    VALUES.with(|values| {
+        values
+            .borrow_mut()
+            .insert(key.to_string(), value.to_string())
+    });
+    Ok(())
+}
+
+#[cfg(any(feature = "__test", feature = "host"))]
+pub fn delete(key: &str) -> Result<(), String> {
+    VALUES.with(|values| values.borrow_mut().remove(key));
+    Ok(())
+}
@@ -1,3 +1,5 @@
+pub mod encoding;
 pub mod http;
+pub mod kv;
 pub mod log;
 pub mod shim;
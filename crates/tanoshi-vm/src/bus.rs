@@ -1,155 +1,367 @@
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use std::{
     path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
-use tanoshi_lib::prelude::{Chapter, Extension, ExtensionResult, Filters, Manga, Param, Source};
+use tanoshi_lib::prelude::{
+    Chapter, Extension, Filters, Manga, PageImage, Param, Preferences, Source, SourceLogin,
+    SourceLoginResult, UrlPath,
+};
 use tokio::{
     sync::{mpsc::UnboundedSender, oneshot::Sender},
     time::timeout,
 };
 
-use crate::prelude::ExtensionProxy;
+use crate::{
+    cache,
+    prelude::{ExtensionError, ExtensionProxy},
+    watcher::LoadedSources,
+};
 
-pub type ExtensionResultSender<T> = Sender<ExtensionResult<T>>;
+pub type ExtensionResultSender<T> = Sender<Result<T, ExtensionError>>;
 
 #[derive(Debug)]
 pub enum Command {
     Insert(i64, Arc<dyn Extension>),
-    Load(String),
+    Load(String, Option<ExtensionResultSender<Source>>),
     Unload(i64),
+    SetPreferences(i64, Preferences),
     Exist(i64, Sender<bool>),
     List(Sender<Vec<Source>>),
-    Detail(i64, Sender<Source>),
+    Detail(i64, ExtensionResultSender<Source>),
     Filters(i64, ExtensionResultSender<Option<Filters>>),
-    GetMangaList(i64, Param, ExtensionResultSender<Vec<Manga>>),
-    GetMangaInfo(i64, String, ExtensionResultSender<Manga>),
-    GetChapters(i64, String, ExtensionResultSender<Vec<Chapter>>),
-    GetPages(i64, String, ExtensionResultSender<Vec<String>>),
+    Login(
+        i64,
+        SourceLogin,
+        Session,
+        ExtensionResultSender<SourceLoginResult>,
+    ),
+    GetMangaList(i64, Param, Session, ExtensionResultSender<Vec<Manga>>),
+    GetLatestManga(i64, i32, Session, ExtensionResultSender<Vec<Manga>>),
+    GetPopularManga(i64, i32, Session, ExtensionResultSender<Vec<Manga>>),
+    GetMangaInfo(i64, String, Session, ExtensionResultSender<Manga>),
+    GetChapters(i64, String, Session, ExtensionResultSender<Vec<Chapter>>),
+    GetPages(i64, String, Session, ExtensionResultSender<Vec<String>>),
+    GetPage(i64, String, Session, ExtensionResultSender<PageImage>),
+    ParseUrl(i64, String, ExtensionResultSender<Option<UrlPath>>),
+}
+
+/// Login and preferences of the user a call is made for
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    /// User the call is made for, cookies received by extension are kept apart for each user.
+    /// `None` for calls not made for any user
+    pub user_id: Option<i64>,
+    /// Injected into http requests of the extension
+    pub auth: Option<SourceLoginResult>,
+    /// Values set by the user, override values set for the whole server
+    pub preferences: Preferences,
+}
+
+/// Timeout of calls to extension, in seconds
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    /// Timeout of calls fetching metadata, e.g. `get_manga_list` or `get_chapters`
+    pub call: u64,
+    /// Timeout of `get_page`, which downloads a whole image
+    pub page: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self { call: 30, page: 60 }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ExtensionBus {
     path: PathBuf,
     tx: UnboundedSender<Command>,
+    timeouts: Timeouts,
+    loaded: LoadedSources,
 }
 
 impl ExtensionBus {
-    pub fn new<P: AsRef<Path>>(path: P, tx: UnboundedSender<Command>) -> Self {
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        tx: UnboundedSender<Command>,
+        timeouts: Timeouts,
+        loaded: LoadedSources,
+    ) -> Self {
         Self {
             path: PathBuf::new().join(path),
             tx,
+            timeouts,
+            loaded,
+        }
+    }
+
+    /// Send command and wait for its reply for at most `secs` seconds
+    async fn request<T>(
+        &self,
+        secs: u64,
+        cmd: impl FnOnce(Sender<T>) -> Command,
+    ) -> Result<T, ExtensionError> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.send(cmd(tx))?;
+
+        match timeout(Duration::from_secs(secs), rx).await {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(_)) => Err(ExtensionError::Other("extension call dropped".to_string())),
+            Err(_) => Err(ExtensionError::Timeout),
         }
     }
 
+    fn send(&self, cmd: Command) -> Result<(), ExtensionError> {
+        self.tx
+            .send(cmd)
+            .map_err(|_| ExtensionError::Other("extension thread stopped".to_string()))
+    }
+
     pub async fn insert(
         &self,
         source_id: i64,
         proxy: Arc<dyn Extension>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        Ok(self.tx.send(Command::Insert(source_id, proxy))?)
+    ) -> Result<(), ExtensionError> {
+        self.send(Command::Insert(source_id, proxy))
     }
 
-    pub async fn install(
-        &self,
-        name: String,
-        contents: &Bytes,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let path = self.path.join(name).with_extension("tanoshi");
+    /// Name of wasm file of an extension, `name` comes from repository index so only a single
+    /// path segment of alphanumerics, `-` and `_` is accepted
+    fn file_name(name: &str) -> Result<PathBuf, ExtensionError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(ExtensionError::Other(format!(
+                "invalid extension name {:?}",
+                name
+            )));
+        }
+
+        Ok(Path::new(name).with_extension("wasm"))
+    }
+
+    pub async fn install(&self, name: String, contents: &Bytes) -> Result<(), ExtensionError> {
+        let wasm_path = self.path.join(Self::file_name(&name)?);
+        let path = cache::compiled_path(&wasm_path);
         ExtensionProxy::compile(contents, &path)?;
+        // written after compiled, so plugin watcher finds it up to date. It is renamed into place
+        // so the watcher never sees a partially written wasm and compiles it again
+        let part_path = wasm_path.with_extension("wasm.part");
+        tokio::fs::write(&part_path, contents).await?;
+        tokio::fs::rename(&part_path, &wasm_path).await?;
 
-        Ok(self.tx.send(Command::Load(
-            path.to_str().ok_or("path can't to string")?.to_string(),
-        ))?)
+        let compiled = path
+            .to_str()
+            .ok_or_else(|| ExtensionError::Other("path can't to string".to_string()))?
+            .to_string();
+        let res = self
+            .request(self.timeouts.call, |tx| Command::Load(compiled, Some(tx)))
+            .await?;
+        match res {
+            Ok(source) => {
+                // so plugin watcher unloads it when its wasm is removed
+                self.loaded.insert(&path, source.id);
+                Ok(())
+            }
+            Err(e) => {
+                // e.g. incompatible, it would be refused again on every start otherwise
+                let _ = tokio::fs::remove_file(&path).await;
+                let _ = tokio::fs::remove_file(&wasm_path).await;
+                Err(e)
+            }
+        }
     }
 
-    pub async fn unload(&self, source_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn unload(&self, source_id: i64) -> Result<(), ExtensionError> {
         let detail = self.detail(source_id).await?;
 
-        self.tx.send(Command::Unload(source_id))?;
+        self.send(Command::Unload(source_id))?;
 
-        let path = self.path.join(detail.name).with_extension("tanoshi");
+        // the file it was loaded from, name in index or detail may not match it
+        let path = match self.loaded.find(source_id) {
+            Some(file_name) => self.path.join(file_name),
+            None => cache::compiled_path(self.path.join(Self::file_name(&detail.name)?)),
+        };
+        // already unloaded, plugin watcher has nothing to do when the wasm is removed
+        self.loaded.remove(&path);
         info!("removing {}", path.display());
         tokio::fs::remove_file(&path).await?;
-        Ok(())
-    }
 
-    pub async fn exist(&self, source_id: i64) -> Result<bool, Box<dyn std::error::Error>> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.tx.send(Command::Exist(source_id, tx))?;
+        // extension installed before wasm is kept doesn't have one
+        match tokio::fs::remove_file(cache::wasm_path(&path)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
 
-        let exist = timeout(Duration::from_secs(30), rx).await??;
-        Ok(exist)
+    /// Set preferences of a source for the whole server, user's own preferences override them
+    pub async fn set_preferences(
+        &self,
+        source_id: i64,
+        preferences: Preferences,
+    ) -> Result<(), ExtensionError> {
+        self.send(Command::SetPreferences(source_id, preferences))
     }
 
-    pub async fn list(&self) -> Result<Vec<Source>, Box<dyn std::error::Error>> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.tx.send(Command::List(tx))?;
+    pub async fn exist(&self, source_id: i64) -> Result<bool, ExtensionError> {
+        self.request(self.timeouts.call, |tx| Command::Exist(source_id, tx))
+            .await
+    }
 
-        let sources = timeout(Duration::from_secs(30), rx).await??;
-        Ok(sources)
+    pub async fn list(&self) -> Result<Vec<Source>, ExtensionError> {
+        self.request(self.timeouts.call, Command::List).await
     }
 
-    pub async fn detail(&self, source_id: i64) -> Result<Source, Box<dyn std::error::Error>> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.tx.send(Command::Detail(source_id, tx))?;
+    pub async fn detail(&self, source_id: i64) -> Result<Source, ExtensionError> {
+        self.request(self.timeouts.call, |tx| Command::Detail(source_id, tx))
+            .await?
+    }
 
-        let source = timeout(Duration::from_secs(30), rx).await??;
-        Ok(source)
+    pub async fn filters(&self, source_id: i64) -> Result<Option<Filters>, ExtensionError> {
+        self.request(self.timeouts.call, |tx| Command::Filters(source_id, tx))
+            .await?
     }
 
-    pub async fn filters(
+    pub async fn login(
         &self,
         source_id: i64,
-    ) -> Result<Option<Filters>, Box<dyn std::error::Error>> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.tx.send(Command::Filters(source_id, tx))?;
-
-        timeout(Duration::from_secs(30), rx).await??.result()
+        login: SourceLogin,
+        session: Session,
+    ) -> Result<SourceLoginResult, ExtensionError> {
+        self.request(self.timeouts.call, |tx| {
+            Command::Login(source_id, login, session, tx)
+        })
+        .await?
     }
 
     pub async fn get_manga_list(
         &self,
         source_id: i64,
         param: Param,
-    ) -> Result<Vec<Manga>, Box<dyn std::error::Error>> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.tx.send(Command::GetMangaList(source_id, param, tx))?;
+        session: Session,
+    ) -> Result<Vec<Manga>, ExtensionError> {
+        let param = Param {
+            auth: session.auth.as_ref().map(|auth| auth.value.clone()),
+            ..param
+        };
+
+        self.request(self.timeouts.call, |tx| {
+            Command::GetMangaList(source_id, param, session, tx)
+        })
+        .await?
+    }
+
+    pub async fn get_latest_manga(
+        &self,
+        source_id: i64,
+        page: i32,
+        session: Session,
+    ) -> Result<Vec<Manga>, ExtensionError> {
+        self.request(self.timeouts.call, |tx| {
+            Command::GetLatestManga(source_id, page, session, tx)
+        })
+        .await?
+    }
 
-        timeout(Duration::from_secs(30), rx).await??.result()
+    pub async fn get_popular_manga(
+        &self,
+        source_id: i64,
+        page: i32,
+        session: Session,
+    ) -> Result<Vec<Manga>, ExtensionError> {
+        self.request(self.timeouts.call, |tx| {
+            Command::GetPopularManga(source_id, page, session, tx)
+        })
+        .await?
     }
 
     pub async fn get_manga_info(
         &self,
         source_id: i64,
         path: String,
-    ) -> Result<Manga, Box<dyn std::error::Error>> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.tx.send(Command::GetMangaInfo(source_id, path, tx))?;
-
-        timeout(Duration::from_secs(30), rx).await??.result()
+        session: Session,
+    ) -> Result<Manga, ExtensionError> {
+        self.request(self.timeouts.call, |tx| {
+            Command::GetMangaInfo(source_id, path, session, tx)
+        })
+        .await?
     }
 
     pub async fn get_chapters(
         &self,
         source_id: i64,
         path: String,
-    ) -> Result<Vec<Chapter>, Box<dyn std::error::Error>> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.tx.send(Command::GetChapters(source_id, path, tx))?;
-
-        timeout(Duration::from_secs(30), rx).await??.result()
+        session: Session,
+    ) -> Result<Vec<Chapter>, ExtensionError> {
+        self.request(self.timeouts.call, |tx| {
+            Command::GetChapters(source_id, path, session, tx)
+        })
+        .await?
     }
 
     pub async fn get_pages(
         &self,
         source_id: i64,
         path: String,
-    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        self.tx.send(Command::GetPages(source_id, path, tx))?;
+        session: Session,
+    ) -> Result<Vec<String>, ExtensionError> {
+        self.request(self.timeouts.call, |tx| {
+            Command::GetPages(source_id, path, session, tx)
+        })
+        .await?
+    }
+
+    /// Fetch image of a page with extension, so it can add headers the source require
+    pub async fn get_page(
+        &self,
+        source_id: i64,
+        url: String,
+        session: Session,
+    ) -> Result<PageImage, ExtensionError> {
+        self.request(self.timeouts.page, |tx| {
+            Command::GetPage(source_id, url, session, tx)
+        })
+        .await?
+    }
 
-        timeout(Duration::from_secs(30), rx).await??.result()
+    /// Ask extension which manga or chapter `url` points to
+    pub async fn parse_url(
+        &self,
+        source_id: i64,
+        url: String,
+    ) -> Result<Option<UrlPath>, ExtensionError> {
+        self.request(self.timeouts.call, |tx| {
+            Command::ParseUrl(source_id, url, tx)
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(
+            ExtensionBus::file_name("mangadex").unwrap(),
+            PathBuf::from("mangadex.wasm")
+        );
+        assert_eq!(
+            ExtensionBus::file_name("manga_see-2").unwrap(),
+            PathBuf::from("manga_see-2.wasm")
+        );
+
+        for name in &[
+            "", "../../x", "a/b", "a\\b", ".", "..", "/x", "c:x", "a.b", "a b",
+        ] {
+            assert!(ExtensionBus::file_name(name).is_err(), "{:?}", name);
+        }
     }
 }
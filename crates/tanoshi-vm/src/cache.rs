@@ -0,0 +1,192 @@
+use sha2::{Digest, Sha256};
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use wasmer::{Module, Target};
+
+/// First bytes of compiled extension, older ones are a bare serialized module
+const MAGIC: &str = "tanoshi-module";
+
+/// Serialized module read from compile cache
+pub enum Cached {
+    /// Compiled by this build on this CPU
+    Fresh(Vec<u8>),
+    /// Compiled by another engine, wasmer version or CPU, must be recompiled from wasm
+    Stale(String),
+    /// Compiled before the header existed, can only be loaded as is
+    Legacy(Vec<u8>),
+}
+
+/// Describe what a compiled module depends on, it is only loaded if this matches
+pub fn header() -> String {
+    #[cfg(feature = "universal")]
+    let engine = "universal";
+    #[cfg(all(feature = "dylib", not(feature = "universal")))]
+    let engine = "dylib";
+
+    let target = Target::default();
+    let mut features = target
+        .cpu_features()
+        .iter()
+        .map(|feature| feature.to_string())
+        .collect::<Vec<String>>();
+    features.sort();
+
+    format!(
+        "{} engine={} wasmer={} target={} features={}",
+        MAGIC,
+        engine,
+        wasmer::VERSION,
+        target.triple(),
+        features.join(",")
+    )
+}
+
+/// Path of compiled module of an extension
+pub fn compiled_path<P: AsRef<Path>>(wasm_path: P) -> PathBuf {
+    wasm_path.as_ref().with_extension("tanoshi")
+}
+
+/// Path of wasm source of an extension
+pub fn wasm_path<P: AsRef<Path>>(compiled_path: P) -> PathBuf {
+    compiled_path.as_ref().with_extension("wasm")
+}
+
+pub fn write<P: AsRef<Path>>(
+    path: P,
+    wasm_bytes: &[u8],
+    module: &Module,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_serialized(path, wasm_bytes, &module.serialize()?)
+}
+
+fn write_serialized<P: AsRef<Path>>(
+    path: P,
+    wasm_bytes: &[u8],
+    serialized: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = std::fs::File::create(path)?;
+    writeln!(file, "{}", header())?;
+    writeln!(file, "{}", checksum(wasm_bytes))?;
+    file.write_all(serialized)?;
+
+    Ok(())
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Cached, Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(path)?;
+    if !bytes.starts_with(MAGIC.as_bytes()) {
+        return Ok(Cached::Legacy(bytes));
+    }
+
+    // header, checksum of wasm, then the serialized module
+    let mut parts = bytes.splitn(3, |b| *b == b'\n');
+    let found = String::from_utf8_lossy(parts.next().unwrap_or_default()).to_string();
+    let serialized = parts
+        .nth(1)
+        .ok_or("compiled extension has no end of header")?;
+    if found != header() {
+        return Ok(Cached::Stale(found));
+    }
+
+    Ok(Cached::Fresh(serialized.to_vec()))
+}
+
+/// Whether wasm at `wasm_path` has no compiled module this build can load, or it is changed
+/// since compiled
+pub fn needs_compile<P: AsRef<Path>>(wasm_path: P) -> bool {
+    let wasm_bytes = match std::fs::read(&wasm_path) {
+        Ok(wasm_bytes) => wasm_bytes,
+        Err(_) => return true,
+    };
+    let file = match std::fs::File::open(compiled_path(&wasm_path)) {
+        Ok(file) => file,
+        Err(_) => return true,
+    };
+
+    let mut lines = BufReader::new(file).split(b'\n');
+    match (lines.next(), lines.next()) {
+        (Some(Ok(found)), Some(Ok(found_checksum))) => {
+            found != header().as_bytes() || found_checksum != checksum(&wasm_bytes).as_bytes()
+        }
+        _ => true,
+    }
+}
+
+fn checksum(wasm_bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(wasm_bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let dir = temp_dir("tanoshi_test_cache_round_trip");
+        let wasm_path = dir.join("source.wasm");
+        let path = compiled_path(&wasm_path);
+        // serialized module may contain newline, only the first two are part of header
+        let serialized = b"serialized\nmodule\n".to_vec();
+
+        std::fs::write(&wasm_path, b"wasm").unwrap();
+        assert!(needs_compile(&wasm_path));
+
+        write_serialized(&path, b"wasm", &serialized).unwrap();
+        assert!(matches!(read(&path).unwrap(), Cached::Fresh(bytes) if bytes == serialized));
+        assert!(!needs_compile(&wasm_path));
+
+        // wasm changed since compiled
+        std::fs::write(&wasm_path, b"new wasm").unwrap();
+        assert!(needs_compile(&wasm_path));
+    }
+
+    #[test]
+    fn test_read_stale() {
+        let dir = temp_dir("tanoshi_test_cache_stale");
+        let wasm_path = dir.join("source.wasm");
+        let path = compiled_path(&wasm_path);
+        std::fs::write(&wasm_path, b"wasm").unwrap();
+
+        let current = header();
+        let engine = current
+            .split(' ')
+            .find(|part| part.starts_with("engine="))
+            .unwrap();
+        let wasmer = format!("wasmer={}", wasmer::VERSION);
+        for stale in &[
+            current.replace(engine, "engine=other"),
+            current.replace(&wasmer, "wasmer=0.0.1"),
+        ] {
+            let mut bytes = format!("{}\n{}\n", stale, checksum(b"wasm")).into_bytes();
+            bytes.extend_from_slice(b"serialized");
+            std::fs::write(&path, &bytes).unwrap();
+
+            assert!(matches!(read(&path).unwrap(), Cached::Stale(found) if &found == stale));
+            assert!(needs_compile(&wasm_path));
+        }
+    }
+
+    #[test]
+    fn test_read_legacy() {
+        let dir = temp_dir("tanoshi_test_cache_legacy");
+        let wasm_path = dir.join("source.wasm");
+        let path = compiled_path(&wasm_path);
+        std::fs::write(&wasm_path, b"wasm").unwrap();
+        std::fs::write(&path, b"serialized module").unwrap();
+
+        assert!(
+            matches!(read(&path).unwrap(), Cached::Legacy(bytes) if bytes == b"serialized module")
+        );
+        assert!(needs_compile(&wasm_path));
+    }
+}
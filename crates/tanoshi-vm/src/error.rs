@@ -0,0 +1,76 @@
+use std::fmt;
+
+/// Error of a call to extension, so caller can tell a source that is down from one that isn't installed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtensionError {
+    /// No extension with this source id is loaded
+    NotFound(i64),
+    /// Call didn't finish within its timeout
+    Timeout,
+    /// Extension trapped or exceeded its execution budget
+    Trap(String),
+    /// Extension returned something that can't be deserialized
+    Deserialize(String),
+    /// Source responded with an error status, `0` if it can't be reached
+    Remote(i32),
+    /// Extension doesn't export the called function
+    Unsupported(String),
+    /// Extension is built against a tanoshi-lib this build can't run
+    Incompatible(String),
+    /// Any other error, usually message returned by the extension itself
+    Other(String),
+}
+
+impl ExtensionError {
+    /// Machine readable name of the variant
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExtensionError::NotFound(_) => "NOT_FOUND",
+            ExtensionError::Timeout => "TIMEOUT",
+            ExtensionError::Trap(_) => "TRAP",
+            ExtensionError::Deserialize(_) => "DESERIALIZE",
+            ExtensionError::Remote(_) => "REMOTE",
+            ExtensionError::Unsupported(_) => "UNSUPPORTED",
+            ExtensionError::Incompatible(_) => "INCOMPATIBLE",
+            ExtensionError::Other(_) => "OTHER",
+        }
+    }
+}
+
+impl fmt::Display for ExtensionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtensionError::NotFound(source_id) => {
+                write!(f, "extension with id {} not found", source_id)
+            }
+            ExtensionError::Timeout => write!(f, "extension call timed out"),
+            ExtensionError::Trap(msg) => write!(f, "extension trapped: {}", msg),
+            ExtensionError::Deserialize(msg) => {
+                write!(f, "error deserialize extension result: {}", msg)
+            }
+            ExtensionError::Remote(0) => write!(f, "source can't be reached"),
+            ExtensionError::Remote(status) => {
+                write!(f, "source responded with status {}", status)
+            }
+            ExtensionError::Unsupported(name) => {
+                write!(f, "extension doesn't support {}", name)
+            }
+            ExtensionError::Incompatible(msg) => write!(f, "{}", msg),
+            ExtensionError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ExtensionError {}
+
+impl From<std::io::Error> for ExtensionError {
+    fn from(e: std::io::Error) -> Self {
+        ExtensionError::Other(e.to_string())
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for ExtensionError {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        ExtensionError::Other(e.to_string())
+    }
+}
@@ -2,5 +2,12 @@
 extern crate log;
 
 pub mod bus;
+pub mod cache;
+pub mod error;
+pub mod limits;
+pub mod logs;
+pub mod pool;
+pub mod storage;
 pub mod vm;
+pub mod watcher;
 pub mod prelude;
\ No newline at end of file
@@ -0,0 +1,312 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    ptr::NonNull,
+    sync::Arc,
+};
+
+use loupe::MemoryUsage;
+use serde::{Deserialize, Serialize};
+use wasmer::{
+    vm::{self, MemoryError, MemoryStyle, TableStyle, VMMemoryDefinition, VMTableDefinition},
+    MemoryType, Pages, TableType, Tunables,
+};
+
+/// Resource limits applied to every extension
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Metering points an extension may spend in a single call
+    pub fuel: u64,
+    /// Maximum linear memory of an extension, in 64 KiB pages
+    pub memory_pages: u32,
+    /// Default timeout of http request made by an extension, in seconds
+    pub http_timeout: u64,
+    /// Maximum instances of each extension, calls beyond it wait for an idle instance
+    pub instances: usize,
+    /// Allow extensions to reach loopback, link-local and private addresses, e.g. a source
+    /// hosted on local network
+    pub allow_private_hosts: bool,
+    /// Maximum size of a key an extension stores, in bytes
+    pub storage_key_size: usize,
+    /// Maximum size of a value an extension stores, in bytes
+    pub storage_value_size: usize,
+    /// Maximum number of keys each extension stores
+    pub storage_entries: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: 5_000_000_000,
+            memory_pages: 2048,
+            http_timeout: 30,
+            instances: 2,
+            allow_private_hosts: false,
+            storage_key_size: 256,
+            storage_value_size: 64 * 1024,
+            storage_entries: 1024,
+        }
+    }
+}
+
+/// Tunables capping linear memory to `limit`, based on wasmer's tunables_limit_memory example
+#[derive(MemoryUsage)]
+pub struct LimitingTunables<T: Tunables> {
+    limit: Pages,
+    base: T,
+}
+
+impl<T: Tunables> LimitingTunables<T> {
+    pub fn new(base: T, limit: Pages) -> Self {
+        Self { limit, base }
+    }
+
+    fn adjust_memory(&self, requested: &MemoryType) -> MemoryType {
+        let mut adjusted = *requested;
+        if requested.maximum.is_none() {
+            adjusted.maximum = Some(self.limit);
+        }
+        adjusted
+    }
+
+    fn validate_memory(&self, ty: &MemoryType) -> Result<(), MemoryError> {
+        if ty.minimum > self.limit {
+            return Err(MemoryError::Generic(
+                "minimum exceeds the allowed memory limit".to_string(),
+            ));
+        }
+
+        match ty.maximum {
+            Some(max) if max > self.limit => Err(MemoryError::Generic(
+                "maximum exceeds the allowed memory limit".to_string(),
+            )),
+            Some(_) => Ok(()),
+            None => Err(MemoryError::Generic("maximum unset".to_string())),
+        }
+    }
+}
+
+impl<T: Tunables> Tunables for LimitingTunables<T> {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        let adjusted = self.adjust_memory(memory);
+        self.base.memory_style(&adjusted)
+    }
+
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.base.table_style(table)
+    }
+
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<Arc<dyn vm::Memory>, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base.create_host_memory(&adjusted, style)
+    }
+
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<Arc<dyn vm::Memory>, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base
+            .create_vm_memory(&adjusted, style, vm_definition_location)
+    }
+
+    fn create_host_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+    ) -> Result<Arc<dyn vm::Table>, String> {
+        self.base.create_host_table(ty, style)
+    }
+
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<Arc<dyn vm::Table>, String> {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
+
+/// Check if host of `url` match one of `patterns`, `*.example.com` match any subdomain
+/// of example.com. If there is no pattern, host of `source_url` and its subdomains are allowed.
+///
+/// Patterns are declared by extension itself, so unless `allow_private` is set, host resolving
+/// to a loopback, link-local or private address is refused whatever the patterns are
+pub fn is_host_allowed(
+    patterns: &[String],
+    source_url: &str,
+    url: &str,
+    allow_private: bool,
+) -> bool {
+    let url = match url::Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+    let host = match url.host_str() {
+        Some(host) => host.to_lowercase(),
+        None => return false,
+    };
+
+    let is_matched = if patterns.is_empty() {
+        match url::Url::parse(source_url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+        {
+            Some(source_host) => {
+                host == source_host || host.ends_with(&format!(".{}", source_host))
+            }
+            None => false,
+        }
+    } else {
+        patterns.iter().any(|pattern| {
+            let pattern = pattern.to_lowercase();
+            if pattern == "*" {
+                true
+            } else if let Some(domain) = pattern.strip_prefix("*.") {
+                host.ends_with(&format!(".{}", domain))
+            } else {
+                host == pattern
+            }
+        })
+    };
+
+    is_matched && (allow_private || !is_private_host(&url))
+}
+
+/// Check if host of `url` is an address, or localhost, that isn't reachable from internet.
+/// Domain is resolved when request connects, with `resolve_public`, so it can't pass a check
+/// here and resolve to another address later
+fn is_private_host(url: &url::Url) -> bool {
+    match url.host() {
+        Some(url::Host::Ipv4(ip)) => is_private_ip(IpAddr::V4(ip)),
+        Some(url::Host::Ipv6(ip)) => is_private_ip(IpAddr::V6(ip)),
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+        None => true,
+    }
+}
+
+/// Resolve `netloc` of http request, leaving out addresses that aren't reachable from internet
+pub fn resolve_public(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = netloc
+        .to_socket_addrs()?
+        .filter(|addr| !is_private_ip(addr.ip()))
+        .collect();
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not reachable from internet", netloc),
+        ));
+    }
+
+    Ok(addrs)
+}
+
+/// Check if `ip` is loopback, link-local, private or otherwise not reachable from internet
+pub fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // carrier-grade NAT, 100.64.0.0/10
+                || (ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64)
+                || ip.octets()[0] == 0
+        }
+        IpAddr::V6(ip) => {
+            let segment = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // unique local, fc00::/7
+                || (segment & 0xfe00) == 0xfc00
+                // link-local, fe80::/10
+                || (segment & 0xffc0) == 0xfe80
+                // ipv4 mapped or compatible address
+                || ip
+                    .to_ipv4()
+                    .map_or(false, |ip| is_private_ip(IpAddr::V4(ip)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn test_is_host_allowed_patterns() {
+        let source_url = "https://example.com";
+        let allowed = |patterns: &[String], url| is_host_allowed(patterns, source_url, url, true);
+
+        assert!(allowed(&[], "https://example.com/manga"));
+        assert!(allowed(&[], "https://cdn.example.com/1.jpg"));
+        assert!(!allowed(&[], "https://notexample.com"));
+        assert!(!allowed(&[], "https://example.org"));
+
+        let cdn = patterns(&["*.cdn.net", "Images.Example.org"]);
+        assert!(allowed(&cdn, "https://a.cdn.net/1.jpg"));
+        assert!(!allowed(&cdn, "https://cdn.net/1.jpg"));
+        assert!(allowed(&cdn, "https://images.example.org/1.jpg"));
+        assert!(!allowed(&cdn, "https://example.com"));
+
+        assert!(allowed(&patterns(&["*"]), "https://anything.net"));
+        assert!(!allowed(&patterns(&["*"]), "not a url"));
+    }
+
+    #[test]
+    fn test_is_host_allowed_private() {
+        let any = patterns(&["*"]);
+        let allowed = |url| is_host_allowed(&any, "https://example.com", url, false);
+
+        assert!(!allowed("http://127.0.0.1:8080/graphql"));
+        assert!(!allowed("http://localhost/"));
+        assert!(!allowed("http://10.0.0.1/"));
+        assert!(!allowed("http://172.16.5.4/"));
+        assert!(!allowed("http://192.168.1.1/"));
+        assert!(!allowed("http://169.254.169.254/latest/meta-data"));
+        assert!(!allowed("http://0.0.0.0/"));
+        assert!(!allowed("http://[::1]/"));
+        assert!(!allowed("http://[fd00::1]/"));
+        assert!(!allowed("http://[fe80::1]/"));
+        assert!(!allowed("http://[::ffff:192.168.1.1]/"));
+        assert!(allowed("http://93.184.216.34/"));
+        assert!(allowed("http://[2606:2800:220:1:248:1893:25c8:1946]/"));
+
+        assert!(is_host_allowed(
+            &any,
+            "https://example.com",
+            "http://192.168.1.1/",
+            true
+        ));
+    }
+
+    #[test]
+    fn test_resolve_public() {
+        assert!(resolve_public("localhost:80").is_err());
+        assert!(resolve_public("127.0.0.1:8080").is_err());
+        assert!(resolve_public("[::1]:80").is_err());
+        assert!(resolve_public("192.168.1.1:80").is_err());
+        assert_eq!(
+            resolve_public("93.184.216.34:80").unwrap(),
+            vec!["93.184.216.34:80".parse::<SocketAddr>().unwrap()]
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use log::Level;
+
+/// Number of lines kept for each extension in `ExtensionLogs`
+const LOG_SIZE: usize = 200;
+
+/// Line logged by an extension
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub level: Level,
+    pub message: String,
+    pub at: SystemTime,
+}
+
+/// Latest lines logged by each extension, by source id
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionLogs(Arc<RwLock<BTreeMap<i64, VecDeque<LogLine>>>>);
+
+impl ExtensionLogs {
+    /// Lines of an extension at `level` or more severe, newest first
+    pub fn list(&self, source_id: i64, level: Level) -> Vec<LogLine> {
+        self.0
+            .read()
+            .map(|logs| {
+                logs.get(&source_id)
+                    .map(|lines| {
+                        lines
+                            .iter()
+                            .filter(|line| line.level <= level)
+                            .cloned()
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Keep line of an extension and forward it to server log, tagged with its source id
+    pub fn push(&self, source_id: i64, level: Level, message: String) {
+        let message = message.trim_end().to_string();
+        log::log!(target: "extension", level, "[source {}] {}", source_id, message);
+
+        if let Ok(mut logs) = self.0.write() {
+            let lines = logs.entry(source_id).or_default();
+            lines.push_front(LogLine {
+                level,
+                message,
+                at: SystemTime::now(),
+            });
+            lines.truncate(LOG_SIZE);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::{Arc, Mutex};
+
+use tanoshi_lib::prelude::{Extension, Source};
+use tokio::sync::Semaphore;
+
+use crate::error::ExtensionError;
+
+pub type ExtensionFactory =
+    Box<dyn Fn() -> Result<Arc<dyn Extension>, Box<dyn std::error::Error>> + Send + Sync>;
+
+/// Instances of one extension. Each call takes an idle instance, so concurrent calls never
+/// share an instance and its stdin/stdout pipe. Instances are created on demand up to `size`
+pub struct ExtensionPool {
+    source: Source,
+    idle: Mutex<Vec<Arc<dyn Extension>>>,
+    factory: ExtensionFactory,
+    permits: Arc<Semaphore>,
+}
+
+impl ExtensionPool {
+    pub fn new(first: Arc<dyn Extension>, size: usize, factory: ExtensionFactory) -> Self {
+        Self {
+            source: first.detail(),
+            idle: Mutex::new(vec![first]),
+            factory,
+            permits: Arc::new(Semaphore::new(size.max(1))),
+        }
+    }
+
+    /// Pool of an extension safe to call concurrently, every call use the same instance
+    pub fn shared(extension: Arc<dyn Extension>, size: usize) -> Self {
+        let instance = extension.clone();
+        Self::new(extension, size, Box::new(move || Ok(instance.clone())))
+    }
+
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    /// Run `f` with an idle instance on blocking thread, waits if all instances are busy
+    pub async fn run<F, T>(self: Arc<Self>, f: F) -> Result<T, ExtensionError>
+    where
+        F: FnOnce(Arc<dyn Extension>) -> Result<T, ExtensionError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| ExtensionError::Other(e.to_string()))?;
+
+        let pool = self.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            let instance = pool.checkout().map_err(ExtensionError::Other)?;
+            let res = f(instance.clone());
+            // trapped instance may be left in any state, e.g. with its memory partly written, so
+            // it is dropped and a new one is created on demand
+            if !matches!(res, Err(ExtensionError::Trap(_))) {
+                pool.checkin(instance);
+            }
+            res
+        })
+        .await;
+
+        match res {
+            Ok(res) => res,
+            // call panicked before its instance is checked in, so it is dropped as well
+            Err(e) => Err(ExtensionError::Trap(format!(
+                "extension call failed: {}",
+                e
+            ))),
+        }
+    }
+
+    fn checkout(&self) -> Result<Arc<dyn Extension>, String> {
+        if let Some(instance) = self.idle.lock().map_err(|e| e.to_string())?.pop() {
+            return Ok(instance);
+        }
+
+        debug!("create new instance of {}", self.source.name);
+        (self.factory)().map_err(|e| format!("error instantiate extension: {}", e))
+    }
+
+    fn checkin(&self, instance: Arc<dyn Extension>) {
+        if let Ok(mut idle) = self.idle.lock() {
+            idle.push(instance);
+        }
+    }
+}
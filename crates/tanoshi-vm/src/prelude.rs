@@ -1,2 +1,8 @@
 pub use crate::bus::*;
-pub use crate::vm::*;
\ No newline at end of file
+pub use crate::error::*;
+pub use crate::limits::*;
+pub use crate::logs::*;
+pub use crate::pool::*;
+pub use crate::storage::*;
+pub use crate::vm::*;
+pub use crate::watcher::*;
\ No newline at end of file
@@ -0,0 +1,110 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::limits::Limits;
+
+/// Private key-value storage of extensions, namespaced by source id. Called by host functions
+/// on the blocking thread of an extension call, so implementation may block
+pub trait Storage: Send + Sync {
+    fn get(&self, source_id: i64, key: &str) -> Result<Option<String>, String>;
+
+    fn set(&self, source_id: i64, key: &str, value: &str) -> Result<(), String>;
+
+    fn delete(&self, source_id: i64, key: &str) -> Result<(), String>;
+
+    /// Number of keys stored by `source_id`
+    fn count(&self, source_id: i64) -> Result<usize, String>;
+}
+
+/// Set `key` unless it or `value` is larger than `limits` allow, or it is a new key and
+/// `source_id` already stores as many as allowed
+pub fn set_limited(
+    storage: &dyn Storage,
+    limits: &Limits,
+    source_id: i64,
+    key: &str,
+    value: &str,
+) -> Result<(), String> {
+    if key.len() > limits.storage_key_size {
+        return Err(format!(
+            "key is larger than {} bytes",
+            limits.storage_key_size
+        ));
+    }
+    if value.len() > limits.storage_value_size {
+        return Err(format!(
+            "value is larger than {} bytes",
+            limits.storage_value_size
+        ));
+    }
+    let is_new = storage.get(source_id, key)?.is_none();
+    if is_new && storage.count(source_id)? >= limits.storage_entries {
+        return Err(format!(
+            "storage already has {} keys",
+            limits.storage_entries
+        ));
+    }
+
+    storage.set(source_id, key, value)
+}
+
+/// Storage kept in memory, lost when process exits
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    values: Arc<Mutex<HashMap<(i64, String), String>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, source_id: i64, key: &str) -> Result<Option<String>, String> {
+        let values = self.values.lock().map_err(|e| e.to_string())?;
+        Ok(values.get(&(source_id, key.to_string())).cloned())
+    }
+
+    fn set(&self, source_id: i64, key: &str, value: &str) -> Result<(), String> {
+        let mut values = self.values.lock().map_err(|e| e.to_string())?;
+        values.insert((source_id, key.to_string()), value.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, source_id: i64, key: &str) -> Result<(), String> {
+        let mut values = self.values.lock().map_err(|e| e.to_string())?;
+        values.remove(&(source_id, key.to_string()));
+        Ok(())
+    }
+
+    fn count(&self, source_id: i64) -> Result<usize, String> {
+        let values = self.values.lock().map_err(|e| e.to_string())?;
+        Ok(values.keys().filter(|(id, _)| *id == source_id).count())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_set_limited() {
+        let storage = MemoryStorage::default();
+        let limits = Limits {
+            storage_key_size: 4,
+            storage_value_size: 8,
+            storage_entries: 2,
+            ..Default::default()
+        };
+
+        assert!(set_limited(&storage, &limits, 1, "key1", "value").is_ok());
+        assert!(set_limited(&storage, &limits, 1, "key12", "value").is_err());
+        assert!(set_limited(&storage, &limits, 1, "key2", "too long value").is_err());
+        assert!(set_limited(&storage, &limits, 1, "key2", "value").is_ok());
+        // full, but existing key can still be replaced and other source has its own
+        assert!(set_limited(&storage, &limits, 1, "key3", "value").is_err());
+        assert!(set_limited(&storage, &limits, 1, "key1", "other").is_ok());
+        assert!(set_limited(&storage, &limits, 2, "key3", "value").is_ok());
+
+        assert_eq!(storage.get(1, "key1").unwrap(), Some("other".to_string()));
+        assert_eq!(storage.get(1, "key3").unwrap(), None);
+        assert_eq!(storage.count(1).unwrap(), 2);
+    }
+}
@@ -1,35 +1,200 @@
+use log::Level;
 use serde::{de::DeserializeOwned, Serialize};
-use std::{collections::BTreeMap, fmt::Debug, path::Path, sync::Arc};
-use tanoshi_lib::prelude::{Chapter, Extension, ExtensionResult, Filters, Manga, Param, Source};
-use tanoshi_util::http::Request;
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+use tanoshi_lib::prelude::{
+    Chapter, Extension, ExtensionResult, Filters, Manga, PageImage, Param, Preferences, Source,
+    SourceLogin, SourceLoginResult, UrlPath,
+};
+use tanoshi_lib::VERSION;
+use tanoshi_util::http::{Client, Headers, HttpError, Request};
 use tokio::{
     sync::mpsc::{UnboundedReceiver, UnboundedSender},
     task::JoinHandle,
     time::Instant,
 };
-use wasmer::{imports, ChainableNamedResolver, Function, Instance, Module, Store, WasmerEnv};
+use wasmer::{
+    imports, BaseTunables, ChainableNamedResolver, Function, Instance, Module, Pages, Store,
+    Target, WasmerEnv,
+};
+use wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, MeteringPoints};
 
 use wasmer_wasi::{Pipe, WasiEnv, WasiState};
 
-use crate::bus::{Command, ExtensionResultSender};
+use crate::{
+    bus::{Command, ExtensionResultSender, Session},
+    cache::{self, Cached},
+    error::ExtensionError,
+    limits::{self, LimitingTunables, Limits},
+    logs::ExtensionLogs,
+    pool::ExtensionPool,
+    storage::{self, Storage},
+};
+
+thread_local! {
+    /// State of the call running on this thread, shared with host functions
+    static CALL: RefCell<CallContext> = RefCell::new(CallContext::default());
+}
+
+#[derive(Default)]
+struct CallContext {
+    /// User the call is made for, picks the http client of `host_http_request`
+    user_id: Option<i64>,
+    /// Login of the user, read by `host_http_request`
+    auth: Option<SourceLoginResult>,
+    /// Values of every preference declared by the source, set into instance before the call
+    preferences: Preferences,
+    /// Error of the proxy itself, e.g. trap or invalid result
+    error: Option<ExtensionError>,
+    /// Status of the last failed http request, reset by a successful one
+    remote_status: Option<i32>,
+}
+
+impl CallContext {
+    /// Classify error returned by extension from what happened during the call
+    fn into_result<T>(self, res: ExtensionResult<T>) -> Result<T, ExtensionError> {
+        let remote_status = self.remote_status;
+        match res {
+            ExtensionResult {
+                data: Some(data), ..
+            } => Ok(data),
+            ExtensionResult { error, .. } => Err(self
+                .error
+                .or_else(|| remote_status.map(ExtensionError::Remote))
+                .unwrap_or_else(|| {
+                    ExtensionError::Other(
+                        error.unwrap_or_else(|| "extension returned no data".to_string()),
+                    )
+                })),
+        }
+    }
+}
+
+/// Http clients of an extension, one for each user so cookies set while a call is made for a
+/// user are never sent for another. Calls not made for any user share their own client
+#[derive(Clone)]
+struct HttpClients {
+    timeout: Duration,
+    /// Clients resolve host to any address, otherwise to public ones only
+    allow_private_hosts: bool,
+    clients: Arc<Mutex<BTreeMap<Option<i64>, Client>>>,
+}
+
+impl HttpClients {
+    fn new(timeout: Duration, allow_private_hosts: bool) -> Self {
+        Self {
+            timeout,
+            allow_private_hosts,
+            clients: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    fn get(&self, user_id: Option<i64>) -> Client {
+        let mut clients = match self.clients.lock() {
+            Ok(clients) => clients,
+            Err(e) => e.into_inner(),
+        };
+        clients
+            .entry(user_id)
+            .or_insert_with(|| {
+                if self.allow_private_hosts {
+                    Client::new(self.timeout)
+                } else {
+                    Client::with_resolver(self.timeout, limits::resolve_public)
+                }
+            })
+            .clone()
+    }
+}
 
 #[derive(WasmerEnv, Clone)]
 struct ExtensionEnv {
     wasi_env: WasiEnv,
+    source: Arc<RwLock<Source>>,
+    /// Each extension has its own clients, so cookies aren't shared between extensions
+    http: HttpClients,
+    /// Set by admin, e.g. allows requests to loopback, link-local and private addresses and
+    /// caps size of storage
+    limits: Limits,
+    storage: Arc<dyn Storage>,
+    logs: ExtensionLogs,
 }
 
 pub struct ExtensionProxy {
     instance: Instance,
     env: ExtensionEnv,
+    /// Metering points for each call, `None` if module is compiled without metering
+    fuel: Option<u64>,
+    /// Preferences last set into this instance
+    preferences: Mutex<Preferences>,
 }
 
 impl ExtensionProxy {
+    /// Load compiled extension into a pool of `limits.instances` instances
     pub fn load<P: AsRef<Path>>(
         store: &Store,
         path: P,
-    ) -> Result<Arc<dyn Extension>, Box<dyn std::error::Error>> {
-        let module = unsafe { Module::deserialize_from_file(store, path)? };
+        limits: &Limits,
+        storage: Arc<dyn Storage>,
+        logs: ExtensionLogs,
+    ) -> Result<ExtensionPool, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let module = match cache::read(path)? {
+            Cached::Fresh(bytes) => unsafe { Module::deserialize(store, &bytes)? },
+            Cached::Legacy(bytes) => {
+                warn!(
+                    "{} has no compile header, reinstall it if it fails to load",
+                    path.display()
+                );
+                unsafe { Module::deserialize(store, &bytes)? }
+            }
+            Cached::Stale(header) => Self::recompile(store, path, &header)?,
+        };
+        // instances share http clients, so cookies of a user are the same on every instance
+        let http = HttpClients::new(
+            Duration::from_secs(limits.http_timeout),
+            limits.allow_private_hosts,
+        );
+
+        let first = Self::instantiate(
+            store,
+            &module,
+            limits,
+            http.clone(),
+            storage.clone(),
+            logs.clone(),
+        )?;
+        let (store, limits) = (store.clone(), *limits);
+        Ok(ExtensionPool::new(
+            first,
+            limits.instances,
+            Box::new(move || {
+                Self::instantiate(
+                    &store,
+                    &module,
+                    &limits,
+                    http.clone(),
+                    storage.clone(),
+                    logs.clone(),
+                )
+            }),
+        ))
+    }
 
+    fn instantiate(
+        store: &Store,
+        module: &Module,
+        limits: &Limits,
+        http: HttpClients,
+        storage: Arc<dyn Storage>,
+        logs: ExtensionLogs,
+    ) -> Result<Arc<dyn Extension>, Box<dyn std::error::Error>> {
         let stdin = Pipe::new();
         let stdout = Pipe::new();
         let stderr = Pipe::new();
@@ -40,9 +205,16 @@ impl ExtensionProxy {
             .stderr(Box::new(stderr))
             .finalize()?;
 
-        let import_object = wasi_env.import_object(&module)?;
+        let import_object = wasi_env.import_object(module)?;
 
-        let env = ExtensionEnv { wasi_env };
+        let env = ExtensionEnv {
+            wasi_env,
+            source: Arc::new(RwLock::new(Source::default())),
+            http,
+            limits: *limits,
+            storage,
+            logs,
+        };
 
         let tanoshi = imports! {
             "tanoshi" => {
@@ -52,20 +224,56 @@ impl ExtensionProxy {
                 "host_info" => Function::new_native_with_env(store, env.clone(), host_info),
                 "host_trace" => Function::new_native_with_env(store, env.clone(), host_trace),
                 "host_warn" => Function::new_native_with_env(store, env.clone(), host_warn),
+                "host_kv_get" => Function::new_native_with_env(store, env.clone(), host_kv_get),
+                "host_kv_set" => Function::new_native_with_env(store, env.clone(), host_kv_set),
+                "host_kv_delete" => Function::new_native_with_env(store, env.clone(), host_kv_delete),
             }
         };
 
-        let instance = Instance::new(&module, &tanoshi.chain_back(import_object))?;
+        let instance = Instance::new(module, &tanoshi.chain_back(import_object))?;
 
-        Ok(Arc::new(ExtensionProxy { instance, env }))
+        let fuel = if instance
+            .exports
+            .get_global("wasmer_metering_remaining_points")
+            .is_ok()
+        {
+            Some(limits.fuel)
+        } else {
+            warn!("extension compiled without metering, reinstall to limit its execution");
+            None
+        };
+
+        let proxy = ExtensionProxy {
+            instance,
+            env,
+            fuel,
+            preferences: Mutex::new(Preferences::new()),
+        };
+
+        // detail is needed to check http request, so it has to be set after instantiation
+        let source = proxy.detail();
+        *proxy
+            .env
+            .source
+            .write()
+            .map_err(|e| format!("failed to set source: {}", e))? = source;
+
+        Ok(Arc::new(proxy))
     }
 
     #[cfg(not(feature = "disable-compiler"))]
     fn init_store() -> Store {
+        use wasmer::CompilerConfig;
+
         #[cfg(feature = "cranelift")]
-        let compiler = wasmer_compiler_cranelift::Cranelift::new();
+        let mut compiler = wasmer_compiler_cranelift::Cranelift::new();
         #[cfg(all(feature = "llvm", not(feature = "cranelift")))]
-        let compiler = wasmer_compiler_llvm::LLVM::new();
+        let mut compiler = wasmer_compiler_llvm::LLVM::new();
+
+        compiler.push_middleware(Arc::new(wasmer_middlewares::Metering::new(
+            Limits::default().fuel,
+            |_: &wasmer::wasmparser::Operator| -> u64 { 1 },
+        )));
 
         #[cfg(feature = "universal")]
         let engine = wasmer::Universal::new(compiler).engine();
@@ -75,26 +283,70 @@ impl ExtensionProxy {
         Store::new(&engine)
     }
 
-    fn init_store_headless() -> Store {
+    fn init_store_headless(limits: &Limits) -> Store {
         #[cfg(feature = "dylib")]
         let engine = wasmer_engine_dylib::Dylib::headless().engine();
         #[cfg(feature = "universal")]
         let engine = wasmer::Universal::headless().engine();
 
-        Store::new(&engine)
+        let tunables = LimitingTunables::new(
+            BaseTunables::for_target(&Target::default()),
+            Pages(limits.memory_pages),
+        );
+
+        Store::new_with_tunables(&engine, tunables)
+    }
+
+    /// Compile module at `path` again from its wasm, as it was compiled for `header`
+    #[cfg(not(feature = "disable-compiler"))]
+    fn recompile(
+        store: &Store,
+        path: &Path,
+        header: &str,
+    ) -> Result<Module, Box<dyn std::error::Error>> {
+        let wasm_path = cache::wasm_path(path);
+        if !wasm_path.exists() {
+            return Err(format!(
+                "{} is compiled for \"{}\" and has no wasm to recompile it from, reinstall it",
+                path.display(),
+                header
+            )
+            .into());
+        }
+
+        info!(
+            "{} is compiled for \"{}\", recompiling from {}",
+            path.display(),
+            header,
+            wasm_path.display()
+        );
+        Self::compile_from_file(&wasm_path)?;
+        match cache::read(path)? {
+            Cached::Fresh(bytes) => Ok(unsafe { Module::deserialize(store, &bytes)? }),
+            _ => Err(format!("{} is not up to date after recompiled", path.display()).into()),
+        }
+    }
+
+    #[cfg(feature = "disable-compiler")]
+    fn recompile(
+        _store: &Store,
+        path: &Path,
+        header: &str,
+    ) -> Result<Module, Box<dyn std::error::Error>> {
+        Err(format!(
+            "{} is compiled for \"{}\", recompile it from its wasm",
+            path.display(),
+            header
+        )
+        .into())
     }
 
     #[cfg(not(feature = "disable-compiler"))]
     pub fn compile_from_file<P: AsRef<Path>>(path: P) -> Result<(), Box<dyn std::error::Error>> {
         let wasm_bytes = std::fs::read(&path)?;
 
-        let output = std::path::PathBuf::new()
-            .join(&path)
-            .with_extension("tanoshi");
-        Self::compile(&wasm_bytes, output)?;
-
-        std::fs::remove_file(path)?;
-        Ok(())
+        // wasm is kept, so it can be recompiled when the engine changes
+        Self::compile(&wasm_bytes, cache::compiled_path(&path))
     }
 
     #[cfg(not(feature = "disable-compiler"))]
@@ -108,33 +360,90 @@ impl ExtensionProxy {
         let module = Module::new(&store, wasm_bytes)?;
         debug!("done");
 
-        debug!("remove wasm file");
-        Ok(module.serialize_to_file(output)?)
+        cache::write(output, wasm_bytes, &module)
+    }
+    /// Call exported function with metering points reset to fuel budget
+    fn call_metered(&self, name: &str) -> Result<(), ExtensionError> {
+        let res = self
+            .instance
+            .exports
+            .get_function(name)
+            .map_err(|_| ExtensionError::Unsupported(name.to_string()))?;
+        if let Some(fuel) = self.fuel {
+            set_remaining_points(&self.instance, fuel);
+        }
+
+        if let Err(e) = res.call(&[]) {
+            // discard leftover so it won't be read by next call
+            let _ = wasi_read(&self.env);
+            let _ = wasi_clear_stdin(&self.env);
+
+            if self.fuel.is_some()
+                && matches!(
+                    get_remaining_points(&self.instance),
+                    MeteringPoints::Exhausted
+                )
+            {
+                return Err(ExtensionError::Trap(format!(
+                    "{} exceeded execution budget",
+                    name
+                )));
+            }
+            return Err(ExtensionError::Trap(e.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Set preferences of the running call into instance, unless it already has them or
+    /// extension is built before preferences exist
+    fn apply_preferences(&self) -> Result<(), ExtensionError> {
+        let preferences = CALL.with(|call| call.borrow().preferences.clone());
+        let mut applied = self
+            .preferences
+            .lock()
+            .map_err(|e| ExtensionError::Other(e.to_string()))?;
+        if *applied == preferences
+            || self
+                .instance
+                .exports
+                .get_function("set_preferences")
+                .is_err()
+        {
+            return Ok(());
+        }
+
+        wasi_write(&self.env, &preferences)?;
+        self.call_metered("set_preferences")?;
+        *applied = preferences;
+
+        Ok(())
     }
-    fn call<T>(&self, name: &str) -> Result<T, Box<dyn std::error::Error>>
+
+    fn call<T>(&self, name: &str) -> Result<T, ExtensionError>
     where
         T: DeserializeOwned,
     {
-        let res = self.instance.exports.get_function(name)?;
-        res.call(&[])?;
+        self.apply_preferences()?;
+        self.call_metered(name)?;
         let object_str = wasi_read(&self.env)?;
         debug!("call {} => {}", name, object_str);
-        Ok(ron::from_str(&object_str)?)
+        ron::from_str(&object_str).map_err(|e| ExtensionError::Deserialize(e.to_string()))
     }
 
-    fn call_with_args<T, U>(&self, name: &str, param: &U) -> Result<T, Box<dyn std::error::Error>>
+    fn call_with_args<T, U>(&self, name: &str, param: &U) -> Result<T, ExtensionError>
     where
         T: DeserializeOwned,
         U: Serialize + Debug,
     {
-        let res = self.instance.exports.get_function(name)?;
+        self.apply_preferences()?;
         if let Err(e) = wasi_write(&self.env, &param) {
             error!("error write to wasi: {}", e);
         }
-        res.call(&[])?;
+        self.call_metered(name)?;
         let object_str = wasi_read(&self.env)?;
         debug!("call {}({:?}) => {}", name, param, object_str);
-        Ok(ron::from_str(&object_str)?)
+        ron::from_str(&object_str).map_err(|e| ExtensionError::Deserialize(e.to_string()))
     }
 }
 
@@ -144,54 +453,106 @@ impl Extension for ExtensionProxy {
     }
 
     fn filters(&self) -> ExtensionResult<Option<Filters>> {
-        match self.call("filters") {
-            Ok(res) => res,
-            Err(e) => ExtensionResult::err(format!("{}", e).as_str()),
-        }
+        extension_result(self.call("filters"))
+    }
+
+    fn login(&self, login: SourceLogin) -> ExtensionResult<SourceLoginResult> {
+        extension_result(self.call_with_args("login", &login))
     }
 
     fn get_manga_list(&self, param: Param) -> ExtensionResult<Vec<Manga>> {
-        match self.call_with_args("get_manga_list", &param) {
-            Ok(res) => res,
-            Err(e) => ExtensionResult::err(format!("{}", e).as_str()),
+        extension_result(self.call_with_args("get_manga_list", &param))
+    }
+
+    fn get_latest_manga(&self, page: i32) -> ExtensionResult<Vec<Manga>> {
+        match self.call_with_args("get_latest_manga", &page) {
+            // built before it exists, fall back the same way as the trait does
+            Err(ExtensionError::Unsupported(_)) => self.get_manga_list(Param {
+                auth: session_auth(),
+                ..Param::latest(page)
+            }),
+            res => extension_result(res),
         }
     }
 
-    fn get_manga_info(&self, path: String) -> ExtensionResult<Manga> {
-        match self.call_with_args("get_manga_info", &path) {
-            Ok(res) => res,
-            Err(e) => ExtensionResult::err(format!("{}", e).as_str()),
+    fn get_popular_manga(&self, page: i32) -> ExtensionResult<Vec<Manga>> {
+        match self.call_with_args("get_popular_manga", &page) {
+            Err(ExtensionError::Unsupported(_)) => self.get_manga_list(Param {
+                auth: session_auth(),
+                ..Param::popular(page)
+            }),
+            res => extension_result(res),
         }
     }
 
+    fn get_manga_info(&self, path: String) -> ExtensionResult<Manga> {
+        extension_result(self.call_with_args("get_manga_info", &path))
+    }
+
     fn get_chapters(&self, path: String) -> ExtensionResult<Vec<Chapter>> {
-        match self.call_with_args("get_chapters", &path) {
-            Ok(res) => res,
-            Err(e) => ExtensionResult::err(format!("{}", e).as_str()),
-        }
+        extension_result(self.call_with_args("get_chapters", &path))
     }
 
     fn get_pages(&self, path: String) -> ExtensionResult<Vec<String>> {
-        match self.call_with_args("get_pages", &path) {
-            Ok(res) => res,
-            Err(e) => ExtensionResult::err(format!("{}", e).as_str()),
+        extension_result(self.call_with_args("get_pages", &path))
+    }
+
+    fn image_request_headers(&self, url: String) -> ExtensionResult<Headers> {
+        extension_result(self.call_with_args("image_request_headers", &url))
+    }
+
+    fn get_page(&self, url: String) -> ExtensionResult<PageImage> {
+        extension_result(self.call_with_args("get_page", &url))
+    }
+
+    fn parse_url(&self, url: String) -> ExtensionResult<Option<UrlPath>> {
+        match self.call_with_args("parse_url", &url) {
+            // extension can't recognize any url
+            Err(ExtensionError::Unsupported(_)) => ExtensionResult::ok(None),
+            res => extension_result(res),
         }
     }
 }
 
-pub fn start() -> (JoinHandle<()>, UnboundedSender<Command>) {
+/// Login of the running call as `Param::auth`, as `ExtensionBus::get_manga_list` sets it
+fn session_auth() -> Option<String> {
+    CALL.with(|call| call.borrow().auth.as_ref().map(|auth| auth.value.clone()))
+}
+
+/// Turn error of the proxy into `ExtensionResult`, keeping it for the caller to classify
+fn extension_result<T: Clone>(
+    res: Result<ExtensionResult<T>, ExtensionError>,
+) -> ExtensionResult<T> {
+    match res {
+        Ok(res) => res,
+        Err(e) => {
+            let message = e.to_string();
+            CALL.with(|call| call.borrow_mut().error = Some(e));
+            ExtensionResult::err(&message)
+        }
+    }
+}
+
+/// Start extension thread, `storage` keeps values extensions set with `host_kv_set` and
+/// `logs` keeps lines they log
+pub fn start(
+    limits: Limits,
+    storage: Arc<dyn Storage>,
+    logs: ExtensionLogs,
+) -> (JoinHandle<()>, UnboundedSender<Command>) {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-    let handle = tokio::spawn(async {
-        thread(rx).await;
+    let handle = tokio::spawn(async move {
+        thread(rx, limits, storage, logs).await;
     });
 
     (handle, tx)
 }
 
+/// Compile and load every extension in `path`, returns source id of each loaded compiled module
 pub async fn load<P: AsRef<Path>>(
     path: P,
     tx: UnboundedSender<Command>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<BTreeMap<PathBuf, i64>, Box<dyn std::error::Error>> {
     match std::fs::read_dir(&path) {
         Ok(_) => {}
         Err(_) => {
@@ -202,6 +563,7 @@ pub async fn load<P: AsRef<Path>>(
     #[cfg(not(feature = "disable-compiler"))]
     compile(&path).await?;
 
+    let mut loaded = BTreeMap::new();
     for entry in std::fs::read_dir(&path)?
         .into_iter()
         .filter_map(Result::ok)
@@ -213,12 +575,18 @@ pub async fn load<P: AsRef<Path>>(
     {
         let path = entry.path();
         info!("found compiled plugin at {:?}", path.clone());
+        let (load_tx, load_rx) = tokio::sync::oneshot::channel();
         tx.send(Command::Load(
             path.to_str().ok_or("no path str")?.to_string(),
+            Some(load_tx),
         ))?;
+        // error is already logged by extension thread
+        if let Ok(Ok(source)) = load_rx.await {
+            loaded.insert(path, source.id);
+        }
     }
 
-    Ok(())
+    Ok(loaded)
 }
 
 #[cfg(not(feature = "disable-compiler"))]
@@ -236,6 +604,11 @@ pub async fn compile<P: AsRef<Path>>(path: P) -> Result<(), Box<dyn std::error::
         .filter(move |path| path.path().extension().map_or(false, |ext| ext == "wasm"))
     {
         let path = entry.path();
+        if !cache::needs_compile(&path) {
+            debug!("{:?} is already compiled", path);
+            continue;
+        }
+
         info!("found wasm file at {:?}", path.clone());
         ExtensionProxy::compile_from_file(path).map_err(|e| format!("{}", e))?;
     }
@@ -243,37 +616,60 @@ pub async fn compile<P: AsRef<Path>>(path: P) -> Result<(), Box<dyn std::error::
     Ok(())
 }
 
-async fn thread(extension_receiver: UnboundedReceiver<Command>) {
+async fn thread(
+    extension_receiver: UnboundedReceiver<Command>,
+    limits: Limits,
+    storage: Arc<dyn Storage>,
+    logs: ExtensionLogs,
+) {
     let mut recv = extension_receiver;
-    let mut extension_map: BTreeMap<i64, (Source, Arc<dyn Extension>)> = BTreeMap::new();
+    let mut extension_map: BTreeMap<i64, Arc<ExtensionPool>> = BTreeMap::new();
+    // kept apart from the pools, so they survive reload of an extension
+    let mut server_preferences: BTreeMap<i64, Preferences> = BTreeMap::new();
 
-    let store = ExtensionProxy::init_store_headless();
+    let store = ExtensionProxy::init_store_headless(&limits);
 
     loop {
         let cmd = recv.recv().await;
         if let Some(cmd) = cmd {
             match cmd {
                 Command::Insert(source_id, proxy) => {
-                    let source = proxy.detail();
-                    extension_map.insert(source_id, (source, proxy));
+                    let pool = ExtensionPool::shared(proxy, limits.instances);
+                    extension_map.insert(source_id, Arc::new(pool));
                 }
-                Command::Load(path) => {
+                Command::Load(path, tx) => {
                     info!("load plugin from {:?}", path.clone());
                     let now = Instant::now();
-                    match ExtensionProxy::load(&store, path) {
-                        Ok(proxy) => {
-                            let source = proxy.detail();
+                    let res =
+                        ExtensionProxy::load(&store, path, &limits, storage.clone(), logs.clone())
+                            .map_err(|e| ExtensionError::Other(e.to_string()))
+                            .and_then(|pool| check_compatible(pool.source()).map(|_| pool));
+                    let res = match res {
+                        Ok(pool) => {
+                            let source = pool.source().clone();
                             info!("loaded in {} ms: {:?}", now.elapsed().as_millis(), source);
-                            extension_map.insert(source.id, (source, proxy));
+                            // a loaded source with the same id is replaced, its running calls
+                            // finish on the old instances
+                            extension_map.insert(source.id, Arc::new(pool));
+                            Ok(source)
                         }
                         Err(e) => {
                             error!("error load extension: {}", e);
+                            Err(e)
+                        }
+                    };
+                    if let Some(tx) = tx {
+                        if tx.send(res).is_err() {
+                            error!("[Command::Load] receiver dropped");
                         }
                     }
                 }
                 Command::Unload(source_id) => {
                     extension_map.remove(&source_id);
                 }
+                Command::SetPreferences(source_id, preferences) => {
+                    server_preferences.insert(source_id, preferences);
+                }
                 Command::Exist(source_id, tx) => {
                     let exist = extension_map.get(&source_id).is_some();
                     if tx.send(exist).is_err() {
@@ -283,53 +679,140 @@ async fn thread(extension_receiver: UnboundedReceiver<Command>) {
                 Command::List(tx) => {
                     let sources = extension_map
                         .values()
-                        .cloned()
-                        .map(|(source, _)| source)
+                        .map(|pool| pool.source().clone())
                         .collect::<Vec<Source>>();
 
                     if tx.send(sources).is_err() {
                         error!("[Command::List] receiver dropped");
                     }
                 }
-                Command::Detail(source_id, tx) => match extension_map.get(&source_id) {
-                    Some((detail, _)) => {
-                        if tx.send(detail.clone()).is_err() {
-                            error!("[Command::Detail] receiver dropped");
-                        }
-                    }
-                    None => {
-                        error!("extension with id {} not found", source_id);
+                Command::Detail(source_id, tx) => {
+                    let source = extension_map
+                        .get(&source_id)
+                        .map(|pool| pool.source().clone())
+                        .ok_or(ExtensionError::NotFound(source_id));
+                    if tx.send(source).is_err() {
+                        error!("[Command::Detail] receiver dropped");
                     }
-                },
+                }
                 Command::Filters(source_id, tx) => {
-                    process(&extension_map, source_id, tx, |proxy| proxy.filters());
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        Session::default(),
+                        tx,
+                        |proxy| proxy.filters(),
+                    );
+                }
+                Command::Login(source_id, login, session, tx) => {
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        session,
+                        tx,
+                        |proxy| proxy.login(login),
+                    );
+                }
+                Command::GetMangaList(source_id, param, session, tx) => {
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        session,
+                        tx,
+                        |proxy| proxy.get_manga_list(param),
+                    );
                 }
-                Command::GetMangaList(source_id, param, tx) => {
-                    process(&extension_map, source_id, tx, |proxy| {
-                        proxy.get_manga_list(param)
-                    });
+                Command::GetLatestManga(source_id, page, session, tx) => {
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        session,
+                        tx,
+                        move |proxy| proxy.get_latest_manga(page),
+                    );
                 }
-                Command::GetMangaInfo(source_id, path, tx) => {
-                    process(&extension_map, source_id, tx, |proxy| {
-                        proxy.get_manga_info(path)
-                    });
+                Command::GetPopularManga(source_id, page, session, tx) => {
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        session,
+                        tx,
+                        move |proxy| proxy.get_popular_manga(page),
+                    );
                 }
-                Command::GetChapters(source_id, path, tx) => {
-                    process(&extension_map, source_id, tx, |proxy| {
-                        proxy.get_chapters(path)
-                    });
+                Command::GetMangaInfo(source_id, path, session, tx) => {
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        session,
+                        tx,
+                        |proxy| proxy.get_manga_info(path),
+                    );
                 }
-                Command::GetPages(source_id, path, tx) => {
-                    process(&extension_map, source_id, tx, |proxy| proxy.get_pages(path));
+                Command::GetChapters(source_id, path, session, tx) => {
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        session,
+                        tx,
+                        |proxy| proxy.get_chapters(path),
+                    );
+                }
+                Command::GetPages(source_id, path, session, tx) => {
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        session,
+                        tx,
+                        |proxy| proxy.get_pages(path),
+                    );
+                }
+                Command::GetPage(source_id, url, session, tx) => {
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        session,
+                        tx,
+                        |proxy| proxy.get_page(url),
+                    );
+                }
+                Command::ParseUrl(source_id, url, tx) => {
+                    process(
+                        &extension_map,
+                        &server_preferences,
+                        source_id,
+                        Session::default(),
+                        tx,
+                        |proxy| proxy.parse_url(url),
+                    );
                 }
             }
         }
     }
 }
 
+/// Refuse extension built against a tanoshi-lib this build can't run
+fn check_compatible(source: &Source) -> Result<(), ExtensionError> {
+    source
+        .lib_version
+        .check_compatible(&VERSION)
+        .map_err(|e| ExtensionError::Incompatible(format!("{}: {}", source.name, e)))
+}
+
 fn process<F, T>(
-    extension_map: &BTreeMap<i64, (Source, Arc<dyn Extension>)>,
+    extension_map: &BTreeMap<i64, Arc<ExtensionPool>>,
+    server_preferences: &BTreeMap<i64, Preferences>,
     source_id: i64,
+    session: Session,
     tx: ExtensionResultSender<T>,
     f: F,
 ) where
@@ -337,17 +820,40 @@ fn process<F, T>(
     T: Send + 'static,
 {
     match extension_map.get(&source_id) {
-        Some(proxy) => {
-            let (_, proxy) = proxy.clone();
+        Some(pool) => {
+            let pool = pool.clone();
+            // user's own values override values set for the whole server
+            let preferences = match server_preferences.get(&source_id) {
+                Some(server) => pool
+                    .source()
+                    .preference_values(&[server, &session.preferences]),
+                None => pool.source().preference_values(&[&session.preferences]),
+            };
             tokio::spawn(async move {
-                let res = f(proxy);
+                let res = pool
+                    .run(move |proxy| {
+                        // extension call is synchronous, so host functions run on this same thread
+                        CALL.with(|call| {
+                            *call.borrow_mut() = CallContext {
+                                user_id: session.user_id,
+                                auth: session.auth,
+                                preferences,
+                                ..Default::default()
+                            }
+                        });
+                        let res = f(proxy);
+                        CALL.with(|call| call.take()).into_result(res)
+                    })
+                    .await;
                 if tx.send(res).is_err() {
                     error!("[process] receiver dropped");
                 }
             });
         }
         None => {
-            error!("extension with id {} not found", source_id);
+            if tx.send(Err(ExtensionError::NotFound(source_id))).is_err() {
+                error!("[process] receiver dropped");
+            }
         }
     }
 }
@@ -368,6 +874,14 @@ fn wasi_read(env: &ExtensionEnv) -> Result<String, Box<dyn std::error::Error>> {
     Ok(buf)
 }
 
+fn wasi_clear_stdin(env: &ExtensionEnv) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = env.wasi_env.state();
+    let wasm_stdin = state.fs.stdin_mut()?.as_mut().ok_or("no wasi stdin")?;
+    let mut buf = String::new();
+    wasm_stdin.read_to_string(&mut buf)?;
+    Ok(())
+}
+
 fn wasi_write(
     env: &ExtensionEnv,
     param: &impl Serialize,
@@ -398,7 +912,45 @@ fn host_http_request(env: &ExtensionEnv) {
         }
     };
 
-    let http_res = tanoshi_util::http::http_request(http_req);
+    let (allowed_hosts, source_url) = match env.source.read() {
+        Ok(source) => (source.allowed_hosts.clone(), source.url.clone()),
+        Err(e) => {
+            error!("error read source: {}", e);
+            (vec![], String::new())
+        }
+    };
+
+    let (http_req, user_id) = CALL.with(|call| {
+        let call = call.borrow();
+        // login is for the source itself, not for every host it is allowed to reach
+        let http_req = match call.auth.as_ref() {
+            Some(auth) if is_same_origin(&http_req.url, &source_url) => with_auth(http_req, auth),
+            _ => http_req,
+        };
+        (http_req, call.user_id)
+    });
+
+    let http_res = env.http.get(user_id).request(http_req, |url| {
+        let is_allowed = limits::is_host_allowed(
+            &allowed_hosts,
+            &source_url,
+            url,
+            env.limits.allow_private_hosts,
+        );
+        if !is_allowed {
+            warn!("request to {} is not allowed", url);
+        }
+        is_allowed
+    });
+
+    CALL.with(|call| {
+        call.borrow_mut().remote_status = match &http_res.error {
+            Some(HttpError::NotAllowed(_)) | Some(HttpError::InvalidUrl(_)) => None,
+            Some(_) => Some(0),
+            None if http_res.status >= 400 => Some(http_res.status),
+            None => None,
+        }
+    });
 
     match wasi_write(env, &http_res) {
         Ok(_) => {}
@@ -408,19 +960,33 @@ fn host_http_request(env: &ExtensionEnv) {
     }
 }
 
-fn host_debug(env: &ExtensionEnv) {
-    let message = match wasi_read_err(env) {
-        Ok(message) => message,
-        Err(e) => {
-            error!("error wasi_read: {}", e);
-            return;
-        }
+/// Add login of user to request, unless extension already set the header itself
+fn with_auth(req: Request, auth: &SourceLoginResult) -> Request {
+    let (name, value) = match auth.auth_type.to_lowercase().as_str() {
+        "bearer" => ("Authorization", format!("Bearer {}", auth.value)),
+        "cookie" => ("Cookie", auth.value.clone()),
+        _ => (auth.auth_type.as_str(), auth.value.clone()),
     };
 
-    tanoshi_util::log::print_debug(message);
+    let is_set = req.headers.as_ref().map_or(false, |headers| {
+        headers.keys().any(|key| key.eq_ignore_ascii_case(name))
+    });
+    if is_set || name.is_empty() {
+        req
+    } else {
+        req.credential(name, &value)
+    }
 }
 
-fn host_error(env: &ExtensionEnv) {
+fn is_same_origin(url: &str, other: &str) -> bool {
+    match (url::Url::parse(url), url::Url::parse(other)) {
+        (Ok(url), Ok(other)) => url.origin() == other.origin(),
+        _ => false,
+    }
+}
+
+/// Keep message extension wrote to stderr in its log
+fn host_log(env: &ExtensionEnv, level: Level) {
     let message = match wasi_read_err(env) {
         Ok(message) => message,
         Err(e) => {
@@ -429,41 +995,108 @@ fn host_error(env: &ExtensionEnv) {
         }
     };
 
-    tanoshi_util::log::print_error(message);
+    match env.source.read() {
+        Ok(source) => env.logs.push(source.id, level, message),
+        Err(e) => error!("error read source: {}", e),
+    }
 }
 
-fn host_info(env: &ExtensionEnv) {
-    let message = match wasi_read_err(env) {
-        Ok(message) => message,
-        Err(e) => {
-            error!("error wasi_read: {}", e);
-            return;
-        }
-    };
+fn host_debug(env: &ExtensionEnv) {
+    host_log(env, Level::Debug);
+}
 
-    tanoshi_util::log::print_info(message);
+fn host_error(env: &ExtensionEnv) {
+    host_log(env, Level::Error);
 }
 
-fn host_trace(env: &ExtensionEnv) {
-    let message = match wasi_read_err(env) {
-        Ok(message) => message,
-        Err(e) => {
-            error!("error wasi_read: {}", e);
-            return;
-        }
-    };
+fn host_info(env: &ExtensionEnv) {
+    host_log(env, Level::Info);
+}
 
-    tanoshi_util::log::print_trace(message);
+fn host_trace(env: &ExtensionEnv) {
+    host_log(env, Level::Trace);
 }
 
 fn host_warn(env: &ExtensionEnv) {
-    let message = match wasi_read_err(env) {
-        Ok(message) => message,
-        Err(e) => {
-            error!("error wasi_read: {}", e);
-            return;
-        }
-    };
+    host_log(env, Level::Warn);
+}
+
+/// Read arguments of a storage call, along with source id the storage is namespaced by
+fn read_kv_args<T: DeserializeOwned>(env: &ExtensionEnv) -> Result<(i64, T), String> {
+    let args = wasi_read(env).map_err(|e| e.to_string())?;
+    let args = ron::from_str(&args).map_err(|e| e.to_string())?;
+    let source_id = env.source.read().map_err(|e| e.to_string())?.id;
+    // source is set after `detail` returns, until then every extension would share namespace 0
+    if source_id == 0 {
+        return Err("storage can't be used before source is known".to_string());
+    }
+    Ok((source_id, args))
+}
+
+fn write_kv_result<T: Serialize>(env: &ExtensionEnv, res: Result<T, String>) {
+    if let Err(e) = &res {
+        error!("error storage: {}", e);
+    }
+    if let Err(e) = wasi_write(env, &res) {
+        error!("error wasi_write: {}", e);
+    }
+}
 
-    tanoshi_util::log::print_warn(message);
+fn host_kv_get(env: &ExtensionEnv) {
+    let res =
+        read_kv_args::<String>(env).and_then(|(source_id, key)| env.storage.get(source_id, &key));
+    write_kv_result(env, res);
+}
+
+fn host_kv_set(env: &ExtensionEnv) {
+    let res = read_kv_args::<(String, String)>(env).and_then(|(source_id, (key, value))| {
+        storage::set_limited(env.storage.as_ref(), &env.limits, source_id, &key, &value)
+    });
+    write_kv_result(env, res);
+}
+
+fn host_kv_delete(env: &ExtensionEnv) {
+    let res = read_kv_args::<String>(env)
+        .and_then(|(source_id, key)| env.storage.delete(source_id, &key));
+    write_kv_result(env, res);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_with_auth() {
+        let auth = SourceLoginResult {
+            source_name: "source".to_string(),
+            auth_type: "X-Session".to_string(),
+            value: "token".to_string(),
+        };
+
+        let req = with_auth(Request::get("https://example.com/manga"), &auth);
+        assert_eq!(
+            req.headers.unwrap().get("X-Session"),
+            Some(&vec!["token".to_string()])
+        );
+        assert_eq!(req.credentials, vec!["X-Session".to_string()]);
+
+        // header set by extension is kept
+        let req = with_auth(
+            Request::get("https://example.com/manga").set("x-session", "other"),
+            &auth,
+        );
+        assert_eq!(req.headers.unwrap().len(), 1);
+        assert!(req.credentials.is_empty());
+    }
+
+    #[test]
+    fn test_is_same_origin() {
+        let source_url = "https://example.com";
+        assert!(is_same_origin("https://example.com/manga/1", source_url));
+        assert!(is_same_origin("https://EXAMPLE.com:443/", source_url));
+        assert!(!is_same_origin("http://example.com/manga/1", source_url));
+        assert!(!is_same_origin("https://cdn.example.com/1.jpg", source_url));
+        assert!(!is_same_origin("https://example.com:8443/", source_url));
+        assert!(!is_same_origin("not a url", source_url));
+    }
 }
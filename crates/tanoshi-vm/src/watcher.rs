@@ -0,0 +1,231 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use tanoshi_lib::prelude::Source;
+
+/// Number of reloads kept in `ReloadLog`
+const RELOAD_LOG_SIZE: usize = 50;
+
+#[derive(Debug, Clone)]
+pub enum ReloadResult {
+    Loaded(Source),
+    /// Wasm is removed, so is the source with this id
+    Unloaded(i64),
+    Failed(String),
+}
+
+/// Reload of an extension after its wasm changed
+#[derive(Debug, Clone)]
+pub struct Reload {
+    pub path: PathBuf,
+    pub result: ReloadResult,
+    pub at: SystemTime,
+}
+
+/// Latest reloads done by plugin watcher
+#[derive(Debug, Clone, Default)]
+pub struct ReloadLog(Arc<RwLock<VecDeque<Reload>>>);
+
+impl ReloadLog {
+    /// Reloads, newest first
+    pub fn list(&self) -> Vec<Reload> {
+        self.0
+            .read()
+            .map(|log| log.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn push(&self, path: PathBuf, result: ReloadResult) {
+        match &result {
+            ReloadResult::Loaded(source) => {
+                info!("reloaded {} from {}", source.name, path.display())
+            }
+            ReloadResult::Unloaded(source_id) => {
+                info!("unloaded {} as {} is removed", source_id, path.display())
+            }
+            ReloadResult::Failed(e) => error!("error reload {}: {}", path.display(), e),
+        }
+
+        if let Ok(mut log) = self.0.write() {
+            log.push_front(Reload {
+                path,
+                result,
+                at: SystemTime::now(),
+            });
+            log.truncate(RELOAD_LOG_SIZE);
+        }
+    }
+}
+
+/// Source id by file name of its compiled module, shared by plugin watcher and `ExtensionBus`
+/// so sources installed from either are unloaded when their wasm is removed
+#[derive(Debug, Clone, Default)]
+pub struct LoadedSources(Arc<RwLock<BTreeMap<OsString, i64>>>);
+
+impl LoadedSources {
+    /// `loaded` is source id of each compiled module already loaded, as returned by `vm::load`
+    pub fn new(loaded: BTreeMap<PathBuf, i64>) -> Self {
+        // watcher reports canonical path, so only file name is compared
+        Self(Arc::new(RwLock::new(
+            loaded
+                .into_iter()
+                .filter_map(|(path, source_id)| Some((path.file_name()?.to_owned(), source_id)))
+                .collect(),
+        )))
+    }
+
+    /// Set source loaded from compiled module at `path`, returns the one previously loaded from it
+    pub fn insert<P: AsRef<Path>>(&self, path: P, source_id: i64) -> Option<i64> {
+        let name = path.as_ref().file_name()?.to_owned();
+        self.0.write().ok()?.insert(name, source_id)
+    }
+
+    /// File name of compiled module `source_id` is loaded from
+    pub fn find(&self, source_id: i64) -> Option<OsString> {
+        self.0
+            .read()
+            .ok()?
+            .iter()
+            .find(|(_, id)| **id == source_id)
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Forget source loaded from compiled module at `path`, returns its id
+    pub fn remove<P: AsRef<Path>>(&self, path: P) -> Option<i64> {
+        let name = path.as_ref().file_name()?;
+        self.0.write().ok()?.remove(name)
+    }
+}
+
+#[cfg(not(feature = "disable-compiler"))]
+pub use self::compiler::watch;
+
+#[cfg(not(feature = "disable-compiler"))]
+mod compiler {
+    use super::*;
+    use notify::{DebouncedEvent, RecursiveMode, Watcher};
+    use std::time::Duration;
+    use tokio::{runtime::Handle, sync::mpsc::UnboundedSender};
+
+    use crate::{bus::Command, cache, vm::ExtensionProxy};
+
+    /// Watch `path` for new or changed wasm, then compile and swap it into extension thread
+    pub fn watch<P: AsRef<Path>>(
+        path: P,
+        tx: UnboundedSender<Command>,
+        loaded: LoadedSources,
+        log: ReloadLog,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (event_tx, event_rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::watcher(event_tx, Duration::from_secs(1))?;
+        watcher.watch(&path, RecursiveMode::NonRecursive)?;
+        info!("watching {} for extension changes", path.as_ref().display());
+
+        let plugins = PluginWatcher {
+            tx,
+            loaded,
+            log,
+            handle: Handle::current(),
+        };
+
+        std::thread::spawn(move || {
+            // events stop when watcher is dropped
+            let _watcher = watcher;
+            for event in event_rx {
+                match event {
+                    DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
+                        plugins.reload(&path)
+                    }
+                    DebouncedEvent::Rename(from, to) => {
+                        plugins.remove(&from);
+                        plugins.reload(&to);
+                    }
+                    DebouncedEvent::Remove(path) => plugins.remove(&path),
+                    DebouncedEvent::Error(e, path) => {
+                        error!("error watching {:?}: {}", path, e);
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    struct PluginWatcher {
+        tx: UnboundedSender<Command>,
+        loaded: LoadedSources,
+        log: ReloadLog,
+        handle: Handle,
+    }
+
+    impl PluginWatcher {
+        fn reload(&self, wasm_path: &Path) {
+            if !is_wasm(wasm_path) || !cache::needs_compile(wasm_path) {
+                return;
+            }
+
+            let path = cache::compiled_path(wasm_path);
+            let res = ExtensionProxy::compile_from_file(wasm_path)
+                .map_err(|e| e.to_string())
+                .and_then(|_| self.load(&path));
+
+            let result = match res {
+                Ok(source) => {
+                    let previous = self.loaded.insert(&path, source.id);
+                    // source id changed, the old one is no longer backed by a file
+                    if let Some(previous) = previous.filter(|id| *id != source.id) {
+                        let _ = self.tx.send(Command::Unload(previous));
+                    }
+                    ReloadResult::Loaded(source)
+                }
+                Err(e) => ReloadResult::Failed(e),
+            };
+            self.log.push(wasm_path.to_path_buf(), result);
+        }
+
+        fn load(&self, path: &Path) -> Result<Source, String> {
+            let path = path.to_str().ok_or("path can't to string")?.to_string();
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            self.tx
+                .send(Command::Load(path, Some(tx)))
+                .map_err(|_| "extension thread stopped".to_string())?;
+
+            self.handle
+                .block_on(rx)
+                .map_err(|e| e.to_string())?
+                .map_err(|e| e.to_string())
+        }
+
+        fn remove(&self, wasm_path: &Path) {
+            if !is_wasm(wasm_path) {
+                return;
+            }
+
+            let path = cache::compiled_path(wasm_path);
+            let source_id = match self.loaded.remove(&path) {
+                Some(source_id) => source_id,
+                None => return,
+            };
+
+            let _ = self.tx.send(Command::Unload(source_id));
+            // it would be loaded again on next start otherwise
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    error!("error remove {}: {}", path.display(), e);
+                }
+            }
+            self.log
+                .push(wasm_path.to_path_buf(), ReloadResult::Unloaded(source_id));
+        }
+    }
+
+    fn is_wasm(path: &Path) -> bool {
+        path.extension().map_or(false, |ext| ext == "wasm")
+    }
+}
@@ -1,6 +1,6 @@
-use std::rc::Rc;
+use std::{collections::{BTreeMap, HashMap}, rc::Rc};
 
-use dominator::{Dom, clone, events, html, link, with_node};
+use dominator::{Dom, clone, events, html, link, routing, with_node};
 use futures_signals::signal::{Mutable, SignalExt};
 use futures_signals::signal_vec::{MutableVec, SignalVecExt};
 use wasm_bindgen::prelude::*;
@@ -13,6 +13,7 @@ use crate::{
     query::{
         self,
         browse_source::{SortByParam, SortOrderParam},
+        resolve_url::ResolveUrlResolveUrl,
     },
     utils::local_storage,
 };
@@ -30,19 +31,54 @@ pub struct Source {
     need_login: bool,
 }
 
+/// Field of source filters, as defined by `tanoshi_lib::data::FilterField`
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilterField {
+    name: String,
+    /// Values to choose from, field without values is a free text input
+    values: Option<Vec<FilterValue>>,
+    #[serde(default)]
+    multi: bool,
+    description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilterValue {
+    title: String,
+    /// `None` is for no value, e.g. "Any"
+    value: Option<String>,
+    /// Values of other fields set along when this value is chosen
+    related: Option<HashMap<String, String>>,
+}
+
+fn is_selected(filters: &BTreeMap<String, Vec<String>>, key: &str, value: &Option<String>) -> bool {
+    match value {
+        Some(value) => filters.get(key).map_or(false, |values| values.contains(value)),
+        None => !filters.contains_key(key),
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(default)]
 pub struct Catalogue {
     pub source_id: Mutable<i64>,
     keyword: Mutable<String>,
     page: Mutable<i64>,
+    /// Whether latest updates are listed instead of popular manga, unless searching
+    latest: Mutable<bool>,
     sort_by: Mutable<SortByParam>,
     sort_order: Mutable<SortOrderParam>,
+    /// Values of source filters, by field key
+    filters: Mutable<BTreeMap<String, Vec<String>>>,
     is_search: Mutable<bool>,
     cover_list: MutableVec<Cover>,
     #[serde(skip)]
     sources: MutableVec<Source>,
     #[serde(skip)]
+    filter_fields: MutableVec<(String, FilterField)>,
+    #[serde(skip)]
+    filter_loader: AsyncLoader,
+    #[serde(skip)]
     loader: AsyncLoader,
     #[serde(skip)]
     spinner: Rc<Spinner>,
@@ -54,12 +90,16 @@ impl Default for Catalogue {
             source_id: Mutable::new(0),
             keyword: Mutable::new("".to_string()),
             page: Mutable::new(1),
+            latest: Mutable::new(false),
             sort_by: Mutable::new(SortByParam::VIEWS),
             sort_order: Mutable::new(SortOrderParam::DESC),
+            filters: Mutable::new(BTreeMap::new()),
             is_search: Mutable::new(false),
             cover_list: MutableVec::new(),
             spinner: Spinner::new(),
             sources: MutableVec::new(),
+            filter_fields: MutableVec::new(),
+            filter_loader: AsyncLoader::new(),
             loader: AsyncLoader::new(),
         }
     }
@@ -82,11 +122,52 @@ impl Catalogue {
         self.source_id.set(0);
         self.keyword.set("".to_string());
         self.page.set(1);
+        self.latest.set(false);
         self.sort_by.set(SortByParam::VIEWS);
         self.sort_order.set(SortOrderParam::DESC);
+        self.filters.lock_mut().clear();
         self.is_search.set(false);
         self.cover_list.lock_mut().clear();
         self.sources.lock_mut().clear();
+        self.filter_fields.lock_mut().clear();
+    }
+
+    /// Choose a value of a filter field, a value of `None` clears the field
+    fn select_filter(&self, key: &str, multi: bool, value: &FilterValue) {
+        let mut filters = self.filters.lock_mut();
+        match value.value.as_ref() {
+            Some(value) => {
+                let values = filters.entry(key.to_string()).or_default();
+                if values.contains(value) {
+                    values.retain(|v| v != value);
+                } else if multi {
+                    values.push(value.clone());
+                } else {
+                    *values = vec![value.clone()];
+                }
+                if values.is_empty() {
+                    filters.remove(key);
+                }
+            }
+            None => {
+                filters.remove(key);
+            }
+        }
+
+        if let Some(related) = value.related.as_ref() {
+            for (key, value) in related {
+                filters.insert(key.clone(), vec![value.clone()]);
+            }
+        }
+    }
+
+    fn set_filter_text(&self, key: &str, text: String) {
+        let mut filters = self.filters.lock_mut();
+        if text.is_empty() {
+            filters.remove(key);
+        } else {
+            filters.insert(key.to_string(), vec![text]);
+        }
     }
 
     pub fn serialize_into_json(&self) -> String {
@@ -96,7 +177,19 @@ impl Catalogue {
     pub fn fetch_mangas(catalogue: Rc<Self>) {
         catalogue.spinner.set_active(true);
         catalogue.loader.load(clone!(catalogue => async move {
-            match query::fetch_manga_from_source(*catalogue.source_id.lock_ref(), catalogue.page.get(), Some(catalogue.keyword.get_cloned()), catalogue.sort_by.get_cloned(), catalogue.sort_order.get_cloned()).await {
+            let source_id = catalogue.source_id.get();
+            let page = catalogue.page.get();
+            let keyword = catalogue.keyword.get_cloned();
+            let filters = catalogue.filters.get_cloned();
+            let res = if !keyword.is_empty() || !filters.is_empty() {
+                let filters = if filters.is_empty() { None } else { Some(filters) };
+                query::fetch_manga_from_source(source_id, page, Some(keyword), catalogue.sort_by.get_cloned(), catalogue.sort_order.get_cloned(), filters).await
+            } else if catalogue.latest.get() {
+                query::fetch_latest_manga(source_id, page).await
+            } else {
+                query::fetch_popular_manga(source_id, page).await
+            };
+            match res {
                 Ok(covers) => {
                     let mut cover_list = catalogue.cover_list.lock_mut();
                     if catalogue.page.get() == 1 {
@@ -117,6 +210,46 @@ impl Catalogue {
         }));
     }
 
+    pub fn resolve_url(catalogue: Rc<Self>, url: String) {
+        catalogue.spinner.set_active(true);
+        catalogue.loader.load(clone!(catalogue => async move {
+            match query::resolve_url(url).await {
+                Ok(ResolveUrlResolveUrl::Manga(manga)) => {
+                    routing::go_to_url(&Route::Manga(manga.id).url());
+                }
+                Ok(ResolveUrlResolveUrl::Chapter(chapter)) => {
+                    routing::go_to_url(&Route::Chapter(chapter.id, 0).url());
+                }
+                Err(e) => {
+                    snackbar::show(format!("Open url failed: {}", e));
+                }
+            }
+            catalogue.spinner.set_active(false);
+        }));
+    }
+
+    pub fn fetch_filters(catalogue: Rc<Self>) {
+        let source_id = catalogue.source_id.get();
+        catalogue.filter_loader.load(clone!(catalogue => async move {
+            match query::fetch_source_filters(source_id).await {
+                Ok(Some(filters)) => match serde_json::from_value::<BTreeMap<String, FilterField>>(filters.fields) {
+                    Ok(fields) => {
+                        catalogue.filter_fields.lock_mut().replace_cloned(fields.into_iter().collect());
+                    }
+                    Err(e) => {
+                        snackbar::show(format!("Invalid source filters: {}", e));
+                    }
+                },
+                Ok(None) => {
+                    catalogue.filter_fields.lock_mut().clear();
+                }
+                Err(e) => {
+                    snackbar::show(format!("Fetch source filters failed: {}", e));
+                }
+            }
+        }));
+    }
+
     pub fn fetch_sources(catalogue: Rc<Self>) {
         catalogue.loader.load(clone!(catalogue => async move {
             match query::fetch_sources().await {
@@ -145,6 +278,9 @@ impl Catalogue {
                 } else {
                     Some(html!("button", {
                         .text("Filter")
+                        .event(clone!(catalogue => move |_: events::Click| {
+                            catalogue.is_search.set_neq(true);
+                        }))
                     }))
                 }
             }))
@@ -152,7 +288,7 @@ impl Catalogue {
                 if is_search {
                     Some(html!("input" => HtmlInputElement, {
                         .style("width", "100%")
-                        .attribute("placeholder", "Search")
+                        .attribute("placeholder", "Search or paste url")
                         .attribute("type", "text")
                         .property_signal("value", catalogue.keyword.signal_cloned())
                         .with_node!(input => {
@@ -162,6 +298,11 @@ impl Catalogue {
                             .event_preventable(clone!(catalogue => move |event: events::KeyDown| {
                                 if event.key() == "Enter" {
                                     event.prevent_default();
+                                    let keyword = catalogue.keyword.get_cloned();
+                                    if keyword.starts_with("http://") || keyword.starts_with("https://") {
+                                        Self::resolve_url(catalogue.clone(), keyword);
+                                        return;
+                                    }
                                     catalogue.cover_list.lock_mut().clear();
                                     catalogue.page.set_neq(1);
                                     Self::fetch_mangas(catalogue.clone());
@@ -181,8 +322,9 @@ impl Catalogue {
                         .text("Cancel")
                         .event(clone!(catalogue => move |_: events::Click| {
                             catalogue.is_search.set_neq(false);
-                            if catalogue.keyword.get_cloned() != "" {
+                            if catalogue.keyword.get_cloned() != "" || !catalogue.filters.lock_ref().is_empty() {
                                 catalogue.keyword.set_neq("".to_string());
+                                catalogue.filters.lock_mut().clear();
                                 catalogue.cover_list.lock_mut().clear();
                                 catalogue.page.set_neq(1);
                                 Self::fetch_mangas(catalogue.clone());
@@ -201,66 +343,91 @@ impl Catalogue {
         })
     }
 
-    #[allow(dead_code)]
-    pub fn render_search(catalogue: Rc<Self>) -> Dom {
+    pub fn render_filter_field(catalogue: Rc<Self>, key: String, field: FilterField) -> Dom {
+        let multi = field.multi;
         html!("div", {
-            .class("search-box")
-            .class([
-                "w-full",
-                "mb-2",
-                "ml-0",
-                "xl:ml-48",
-                "p-2",
-                "inline-flex",
-                "transition-all",
+            .class("search-filter")
+            .children(&mut [
+                html!("label", {
+                    .text(&field.name)
+                }),
+                html!("span", {
+                    .style("font-size", "small")
+                    .visible(field.description.is_some())
+                    .text(field.description.as_deref().unwrap_or_default())
+                }),
+                match field.values {
+                    Some(values) => html!("div", {
+                        .children(&mut values.into_iter().map(|value| html!("button", {
+                            .class("chip")
+                            .class_signal("active", catalogue.filters.signal_ref(clone!(key, value => move |filters| is_selected(filters, &key, &value.value))))
+                            .text(&value.title)
+                            .event(clone!(catalogue, key, value => move |_: events::Click| {
+                                catalogue.select_filter(&key, multi, &value);
+                            }))
+                        })).collect::<Vec<Dom>>())
+                    }),
+                    None => html!("input" => HtmlInputElement, {
+                        .attribute("type", "text")
+                        .property_signal("value", catalogue.filters.signal_ref(clone!(key => move |filters| {
+                            filters.get(&key).and_then(|values| values.first().cloned()).unwrap_or_default()
+                        })))
+                        .with_node!(input => {
+                            .event(clone!(catalogue, key => move |_: events::Input| {
+                                catalogue.set_filter_text(&key, input.value());
+                            }))
+                        })
+                    }),
+                }
             ])
+        })
+    }
+
+    pub fn render_search(catalogue: Rc<Self>) -> Dom {
+        html!("div", {
+            .class("search-filters")
             .visible_signal(catalogue.is_search.signal())
             .children(&mut [
-                html!("input" => HtmlInputElement, {
-                    .class([
-                        "border",
-                        "rounded",
-                        "focus:outline-none",
-                        "w-full",
-                        "mr-2",
-                        "p-1"
-                    ])
-                    .attribute("placeholder", "Search")
-                    .attribute("type", "text")
-                    .property_signal("value", catalogue.keyword.signal_cloned())
-                    .with_node!(input => {
-                        .event(clone!(catalogue => move |_: events::Input| {
-                            catalogue.keyword.set_neq(input.value());
-                        }))
-                        .event_preventable(clone!(catalogue => move |event: events::KeyDown| {
-                            if event.key() == "Enter" {
-                                event.prevent_default();
-                                catalogue.cover_list.lock_mut().clear();
-                                catalogue.page.set_neq(1);
-                                Self::fetch_mangas(catalogue.clone());
-                            }
-                        }))
-                    })
+                html!("div", {
+                    .children_signal_vec(catalogue.filter_fields.signal_vec_cloned().map(clone!(catalogue => move |(key, field)| {
+                        Self::render_filter_field(catalogue.clone(), key, field)
+                    })))
                 }),
                 html!("button", {
-                    .text("Cancel")
+                    .visible_signal(catalogue.filter_fields.signal_vec_cloned().is_empty().map(|is_empty| !is_empty))
+                    .text("Apply")
                     .event(clone!(catalogue => move |_: events::Click| {
-                        catalogue.is_search.set_neq(false);
-                        if catalogue.keyword.get_cloned() != "" {
-                            catalogue.keyword.set_neq("".to_string());
-                            catalogue.cover_list.lock_mut().clear();
-                            catalogue.page.set_neq(1);
-                            Self::fetch_mangas(catalogue.clone());
-                        }
+                        catalogue.cover_list.lock_mut().clear();
+                        catalogue.page.set_neq(1);
+                        Self::fetch_mangas(catalogue.clone());
                     }))
                 })
             ])
         })
     }
 
+    pub fn render_listing(catalogue: Rc<Self>) -> Dom {
+        html!("div", {
+            .class("catalogue-listing")
+            .visible_signal(catalogue.is_search.signal().map(|is_search| !is_search))
+            .children(&mut [
+                link!(Route::Catalogue{id: catalogue.source_id.get(), latest: false}.url(), {
+                    .class_signal("active", catalogue.latest.signal().map(|latest| !latest))
+                    .text("Popular")
+                }),
+                link!(Route::Catalogue{id: catalogue.source_id.get(), latest: true}.url(), {
+                    .class_signal("active", catalogue.latest.signal())
+                    .text("Latest")
+                }),
+            ])
+        })
+    }
+
     pub fn render_main(catalogue: Rc<Self>) -> Dom {
         html!("div", {
             .children(&mut [
+                Self::render_search(catalogue.clone()),
+                Self::render_listing(catalogue.clone()),
                 html!("div", {
                     .class("manga-grid")
                     .children_signal_vec(catalogue.cover_list.signal_vec_cloned().map(|cover| cover.render()))
@@ -312,14 +479,17 @@ impl Catalogue {
 
     pub fn render(catalogue: Rc<Self>, source_id: i64, latest: bool) -> Dom {
         if source_id > 0 && source_id != catalogue.source_id.get() {
+            // filters of another source don't apply
+            catalogue.filters.lock_mut().clear();
+            catalogue.filter_fields.lock_mut().clear();
+        }
+        if source_id > 0 {
+            Self::fetch_filters(catalogue.clone());
+        }
+        if source_id > 0 && (source_id != catalogue.source_id.get() || latest != catalogue.latest.get()) {
             catalogue.source_id.set(source_id);
-            let (sort_by, sort_order) = if latest {
-                (SortByParam::LAST_UPDATED, SortOrderParam::DESC)
-            } else {
-                (SortByParam::VIEWS, SortOrderParam::DESC)
-            };
-            catalogue.sort_by.set(sort_by);
-            catalogue.sort_order.set(sort_order);
+            catalogue.latest.set(latest);
+            catalogue.page.set(1);
             Self::fetch_mangas(catalogue.clone());
         } else if source_id == 0 {
             catalogue.reset();
@@ -7,6 +7,7 @@ pub struct Source {
     pub need_login: bool,
     pub has_update: bool,
     pub installed: bool,
+    pub repository: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -4,6 +4,7 @@ use dominator::{clone, html, Dom};
 use dominator::{routing, with_node};
 use futures_signals::signal::Mutable;
 use futures_signals::signal::SignalExt;
+use futures_signals::signal_vec::{MutableVec, SignalVecExt};
 use web_sys::HtmlInputElement;
 
 use crate::common::{events, snackbar, Route, SettingCategory};
@@ -15,6 +16,11 @@ pub struct Profile {
     new_password: Mutable<String>,
     confirm_password: Mutable<String>,
     telegram_chat_id: Mutable<Option<String>>,
+    notifier: Mutable<Option<String>>,
+    discord_webhook: Mutable<Option<String>>,
+    webhook_url: Mutable<Option<String>>,
+    email: Mutable<Option<String>>,
+    notifiers: MutableVec<String>,
     pub loader: AsyncLoader,
 }
 
@@ -25,14 +31,39 @@ impl Profile {
             new_password: Mutable::new("".to_string()),
             confirm_password: Mutable::new("".to_string()),
             telegram_chat_id: Mutable::new(None),
+            notifier: Mutable::new(None),
+            discord_webhook: Mutable::new(None),
+            webhook_url: Mutable::new(None),
+            email: Mutable::new(None),
+            notifiers: MutableVec::new(),
             loader: AsyncLoader::new(),
         })
     }
 
     fn fetch_me(profile: Rc<Self>) {
         profile.loader.load(clone!(profile => async move {
+            match query::fetch_notifiers().await {
+                Ok(result) => profile.notifiers.lock_mut().replace_cloned(result),
+                Err(err) => {
+                    snackbar::show(format!("{}", err));
+                }
+            }
+
             match query::fetch_me().await {
-                Ok(result) => profile.telegram_chat_id.set(result.settings.telegram_chat_id.map(|id| id.to_string())),
+                Ok(result) => {
+                    let settings = result.settings;
+                    profile.telegram_chat_id.set(settings.telegram_chat_id.map(|id| id.to_string()));
+                    profile.notifier.set(settings.notifier.map(|kind| match kind {
+                        query::fetch_me::NotifierKind::TELEGRAM => "TELEGRAM".to_string(),
+                        query::fetch_me::NotifierKind::DISCORD => "DISCORD".to_string(),
+                        query::fetch_me::NotifierKind::WEBHOOK => "WEBHOOK".to_string(),
+                        query::fetch_me::NotifierKind::EMAIL => "EMAIL".to_string(),
+                        query::fetch_me::NotifierKind::Other(kind) => kind,
+                    }));
+                    profile.discord_webhook.set(settings.discord_webhook);
+                    profile.webhook_url.set(settings.webhook_url);
+                    profile.email.set(settings.email);
+                }
                 Err(err) => {
                     snackbar::show(format!("{}", err));
                 }
@@ -40,21 +71,33 @@ impl Profile {
         }));
     }
 
-    fn test_telegram(profile: Rc<Self>) {
-        if let Some(chat_id) = profile
+    async fn save_profile(profile: Rc<Self>) -> Result<(), Box<dyn std::error::Error>> {
+        let telegram_chat_id = profile
             .telegram_chat_id
             .get_cloned()
-            .map(|id| id.parse().unwrap_or_default())
-        {
-            profile.loader.load(async move {
-                match query::test_telegram(chat_id).await {
-                    Ok(_) => {}
-                    Err(err) => {
-                        snackbar::show(format!("{}", err));
-                    }
-                }
-            });
-        }
+            .and_then(|telegram_chat_id| telegram_chat_id.parse().ok());
+        let non_empty = |value: Option<String>| value.filter(|value| !value.is_empty());
+        query::update_profile(
+            telegram_chat_id,
+            profile.notifier.get_cloned(),
+            non_empty(profile.discord_webhook.get_cloned()),
+            non_empty(profile.webhook_url.get_cloned()),
+            non_empty(profile.email.get_cloned()),
+        )
+        .await
+    }
+
+    fn test_notification(profile: Rc<Self>) {
+        profile.loader.load(clone!(profile => async move {
+            if let Err(err) = Self::save_profile(profile.clone()).await {
+                snackbar::show(format!("{}", err));
+                return;
+            }
+
+            if let Err(err) = query::test_notification().await {
+                snackbar::show(format!("{}", err));
+            }
+        }));
     }
 
     fn change_password(profile: Rc<Self>) {
@@ -74,13 +117,12 @@ impl Profile {
 
     fn update_profile(profile: Rc<Self>) {
         profile.loader.load(clone!(profile => async move {
-            let telegram_chat_id = profile.telegram_chat_id.get_cloned().and_then(|telegram_chat_id| telegram_chat_id.parse().ok());
-            match query::update_profile(telegram_chat_id).await {
+            match Self::save_profile(profile.clone()).await {
                 Ok(_) => {
                     routing::go_to_url(Route::Settings(SettingCategory::None).url().as_str());
                 },
                 Err(e) => {
-                    snackbar::show(format!("change password error: {}", e));
+                    snackbar::show(format!("update profile error: {}", e));
                 }
             };
         }));
@@ -168,7 +210,20 @@ impl Profile {
         })
     }
 
-    pub fn render_telegram_setting(profile: Rc<Self>) -> Dom {
+    fn render_text_input(placeholder: &str, value: &Mutable<Option<String>>) -> Dom {
+        html!("input" => HtmlInputElement, {
+            .attribute("type", "text")
+            .attribute("placeholder", placeholder)
+            .property_signal("value", value.signal_cloned().map(|value| value.unwrap_or_else(|| "".to_string())))
+            .with_node!(input => {
+                .event(clone!(value => move |_: events::Input| {
+                    value.set(Some(input.value()));
+                }))
+            })
+        })
+    }
+
+    pub fn render_notification_setting(profile: Rc<Self>) -> Dom {
         Self::fetch_me(profile.clone());
 
         html!("form", {
@@ -179,16 +234,42 @@ impl Profile {
             .style("margin-right", "auto")
             .style("border-radius", "0.5rem")
             .children(&mut [
-                html!("input" => HtmlInputElement, {
-                    .attribute("type", "text")
-                    .attribute("placeholder", "Telegram chat id, get from telegram bot")
-                    .property_signal("value", profile.telegram_chat_id.signal_cloned().map(|id| id.unwrap_or_else(|| "".to_string())))
-                    .with_node!(input => {
-                        .event(clone!(profile => move |_: events::Input| {
-                            profile.telegram_chat_id.set(Some(input.value()));
-                        }))
-                    })
+                html!("label", {
+                    .style("margin", "0.5rem")
+                    .text("Notification")
+                }),
+                html!("div", {
+                    .class("reader-settings-row")
+                    .children(&mut [
+                        html!("button", {
+                            .attribute("type", "button")
+                            .class_signal("active", profile.notifier.signal_cloned().map(|notifier| notifier.is_none()))
+                            .text("None")
+                            .event(clone!(profile => move |_: events::Click| profile.notifier.set_neq(None)))
+                        })
+                    ])
+                    .children_signal_vec(profile.notifiers.signal_vec_cloned().map(clone!(profile => move |kind| html!("button", {
+                        .attribute("type", "button")
+                        .class_signal("active", profile.notifier.signal_cloned().map(clone!(kind => move |notifier| notifier.as_ref() == Some(&kind))))
+                        .text(match kind.as_str() {
+                            "TELEGRAM" => "Telegram",
+                            "DISCORD" => "Discord",
+                            "WEBHOOK" => "Webhook",
+                            "EMAIL" => "Email",
+                            kind => kind,
+                        })
+                        .event(clone!(profile, kind => move |_: events::Click| profile.notifier.set_neq(Some(kind.clone()))))
+                    }))))
                 }),
+            ])
+            .child_signal(profile.notifier.signal_cloned().map(clone!(profile => move |notifier| match notifier.as_deref() {
+                Some("TELEGRAM") => Some(Self::render_text_input("Telegram chat id, get from telegram bot", &profile.telegram_chat_id)),
+                Some("DISCORD") => Some(Self::render_text_input("Discord webhook url", &profile.discord_webhook)),
+                Some("WEBHOOK") => Some(Self::render_text_input("Webhook url", &profile.webhook_url)),
+                Some("EMAIL") => Some(Self::render_text_input("Email address", &profile.email)),
+                _ => None,
+            })))
+            .children(&mut [
                 html!("div", {
                     .style("display", "flex")
                     .style("justify-content", "flex-end")
@@ -197,10 +278,11 @@ impl Profile {
                         html!("input", {
                             .attribute("type", "button")
                             .attribute("value", "Test")
+                            .visible_signal(profile.notifier.signal_cloned().map(|notifier| notifier.is_some()))
                             .text("Test")
                             .event_preventable(clone!(profile => move |e: events::Click| {
                                 e.prevent_default();
-                                Self::test_telegram(profile.clone());
+                                Self::test_notification(profile.clone());
                             }))
                         }),
                         html!("input", {
@@ -221,7 +303,7 @@ impl Profile {
         html!("div", {
             .children(&mut [
                 Self::render_change_password(profile.clone()),
-                Self::render_telegram_setting(profile)
+                Self::render_notification_setting(profile)
             ])
         })
     }
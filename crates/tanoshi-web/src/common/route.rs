@@ -11,6 +11,7 @@ pub enum SettingCategory {
     General,
     Reader,
     Source(i64),
+    SourcePreferences(i64),
     Users,
     CreateUser,
     User,
@@ -120,6 +121,13 @@ impl Route {
                             Route::NotFound
                         }
                     }
+                    ["settings", "sources", id, "preferences"] => {
+                        if let Ok(id) = id.parse() {
+                            Route::Settings(SettingCategory::SourcePreferences(id))
+                        } else {
+                            Route::NotFound
+                        }
+                    }
                     _ => Route::NotFound,
                 }
             })
@@ -166,6 +174,9 @@ impl Route {
                     "/settings/sources".to_string()
                 }
             }
+            Route::Settings(SettingCategory::SourcePreferences(source_id)) => {
+                format!("/settings/sources/{}/preferences", source_id)
+            }
             Route::Settings(SettingCategory::Users) => "/settings/users".to_string(),
             Route::Settings(SettingCategory::CreateUser) => "/settings/users/create".to_string(),
             Route::Settings(SettingCategory::User) => "/settings/user".to_string(),
@@ -1,8 +1,19 @@
+use futures::{
+    channel::mpsc::{unbounded, UnboundedReceiver},
+    Stream, StreamExt,
+};
 use graphql_client::GraphQLQuery;
-use std::error::Error;
-use wasm_bindgen::prelude::*;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{MessageEvent, WebSocket};
 
 type NaiveDateTime = String;
+type JSON = serde_json::Value;
 
 use crate::{
     common::Cover,
@@ -50,6 +61,123 @@ where
     }
 }
 
+/// Data pushed by server for a subscription, its websocket is closed when dropped
+pub struct Subscription<T> {
+    ws: WebSocket,
+    rx: UnboundedReceiver<T>,
+    _on_open: Closure<dyn FnMut()>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut()>,
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.rx.poll_next_unpin(cx)
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        // closures are dropped with this, so they must not be called afterward
+        self.ws.set_onopen(None);
+        self.ws.set_onmessage(None);
+        self.ws.set_onclose(None);
+        let _ = self.ws.close();
+    }
+}
+
+/// Subscribe over websocket with `graphql-ws` protocol, stream ends when server closes it
+fn subscribe_graphql<Q>(var: Q::Variables) -> Result<Subscription<Q::ResponseData>, Box<dyn Error>>
+where
+    Q: GraphQLQuery + 'static,
+    Q::ResponseData: 'static,
+{
+    let location = window().document().unwrap_throw().location().unwrap_throw();
+    let scheme = if location.protocol().unwrap_throw() == "https:" {
+        "wss"
+    } else {
+        "ws"
+    };
+    let url = format!("{}://{}/graphql", scheme, location.host().unwrap_throw());
+    let ws = WebSocket::new_with_str(&url, "graphql-ws").map_err(|e| format!("{:?}", e))?;
+
+    let token = local_storage()
+        .get("token")
+        .unwrap_throw()
+        .unwrap_or_else(|| "".to_string());
+    let init = serde_json::json!({
+        "type": "connection_init",
+        "payload": { "token": token },
+    })
+    .to_string();
+    let start = serde_json::json!({
+        "id": "1",
+        "type": "start",
+        "payload": Q::build_query(var),
+    })
+    .to_string();
+
+    let on_open = Closure::wrap(Box::new({
+        let ws = ws.clone();
+        move || {
+            let _ = ws.send_with_str(&init);
+            let _ = ws.send_with_str(&start);
+        }
+    }) as Box<dyn FnMut()>);
+    ws.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+
+    let (tx, rx) = unbounded();
+    let on_message = Closure::wrap(Box::new({
+        let tx = tx.clone();
+        move |e: MessageEvent| {
+            let message = match e
+                .data()
+                .as_string()
+                .and_then(|data| serde_json::from_str::<serde_json::Value>(&data).ok())
+            {
+                Some(message) => message,
+                None => return,
+            };
+
+            match message["type"].as_str() {
+                Some("data") => match serde_json::from_value::<
+                    graphql_client::Response<Q::ResponseData>,
+                >(message["payload"].clone())
+                {
+                    Ok(graphql_client::Response {
+                        data: Some(data), ..
+                    }) => {
+                        let _ = tx.unbounded_send(data);
+                    }
+                    Ok(graphql_client::Response { errors, .. }) => {
+                        error!("subscription error: {:?}", errors);
+                    }
+                    Err(e) => error!("error parse subscription data: {}", e),
+                },
+                Some("error") | Some("connection_error") => {
+                    error!("subscription error: {}", message["payload"]);
+                }
+                // e.g. keep alive
+                _ => {}
+            }
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    ws.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+    let on_close = Closure::wrap(Box::new(move || tx.close_channel()) as Box<dyn FnMut()>);
+    ws.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+    Ok(Subscription {
+        ws,
+        rx,
+        _on_open: on_open,
+        _on_message: on_message,
+        _on_close: on_close,
+    })
+}
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
@@ -64,6 +192,7 @@ pub async fn fetch_manga_from_source(
     keyword: Option<String>,
     sort_by: browse_source::SortByParam,
     sort_order: browse_source::SortOrderParam,
+    filters: Option<BTreeMap<String, Vec<String>>>,
 ) -> Result<Vec<Cover>, Box<dyn Error>> {
     let var = browse_source::Variables {
         source_id: Some(source_id),
@@ -71,6 +200,7 @@ pub async fn fetch_manga_from_source(
         page: Some(page),
         sort_by: Some(sort_by),
         sort_order: Some(sort_order),
+        filters: filters.map(serde_json::to_value).transpose()?,
     };
     let data: browse_source::ResponseData = post_graphql::<BrowseSource>(var).await?;
 
@@ -92,6 +222,72 @@ pub async fn fetch_manga_from_source(
     Ok(covers)
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/browse_latest.graphql",
+    response_derives = "Debug, Clone"
+)]
+pub struct BrowseLatest;
+
+pub async fn fetch_latest_manga(source_id: i64, page: i64) -> Result<Vec<Cover>, Box<dyn Error>> {
+    let var = browse_latest::Variables {
+        source_id: Some(source_id),
+        page: Some(page),
+    };
+    let data: browse_latest::ResponseData = post_graphql::<BrowseLatest>(var).await?;
+
+    let covers = data
+        .browse_latest
+        .iter()
+        .map(|item| {
+            Cover::new(
+                item.id,
+                source_id,
+                item.path.clone(),
+                item.title.clone(),
+                item.cover_url.clone(),
+                item.is_favorite,
+                0,
+            )
+        })
+        .collect();
+    Ok(covers)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/browse_popular.graphql",
+    response_derives = "Debug, Clone"
+)]
+pub struct BrowsePopular;
+
+pub async fn fetch_popular_manga(source_id: i64, page: i64) -> Result<Vec<Cover>, Box<dyn Error>> {
+    let var = browse_popular::Variables {
+        source_id: Some(source_id),
+        page: Some(page),
+    };
+    let data: browse_popular::ResponseData = post_graphql::<BrowsePopular>(var).await?;
+
+    let covers = data
+        .browse_popular
+        .iter()
+        .map(|item| {
+            Cover::new(
+                item.id,
+                source_id,
+                item.path.clone(),
+                item.title.clone(),
+                item.cover_url.clone(),
+                item.is_favorite,
+                0,
+            )
+        })
+        .collect();
+    Ok(covers)
+}
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
@@ -144,6 +340,21 @@ pub async fn fetch_manga_by_source_path(
     Ok(data.manga_by_source_path)
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/resolve_url.graphql",
+    response_derives = "Debug"
+)]
+pub struct ResolveUrl;
+
+pub async fn resolve_url(url: String) -> Result<resolve_url::ResolveUrlResolveUrl, Box<dyn Error>> {
+    let var = resolve_url::Variables { url };
+    let data = post_graphql::<ResolveUrl>(var).await?;
+
+    Ok(data.resolve_url)
+}
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
@@ -322,6 +533,86 @@ pub async fn fetch_source(
     Ok(data.source)
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/fetch_source_preferences.graphql",
+    response_derives = "Debug, Clone"
+)]
+pub struct FetchSourcePreferences;
+
+pub async fn fetch_source_preferences(
+    source_id: i64,
+) -> Result<fetch_source_preferences::ResponseData, Box<dyn Error>> {
+    let var = fetch_source_preferences::Variables {
+        source_id: Some(source_id),
+    };
+    let data = post_graphql::<FetchSourcePreferences>(var).await?;
+    Ok(data)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/fetch_extension_logs.graphql",
+    response_derives = "Debug, Clone"
+)]
+pub struct FetchExtensionLogs;
+
+pub async fn fetch_extension_logs(
+    source_id: i64,
+    level: fetch_extension_logs::LogLevel,
+) -> Result<Vec<fetch_extension_logs::FetchExtensionLogsExtensionLogs>, Box<dyn Error>> {
+    let var = fetch_extension_logs::Variables {
+        source_id,
+        level: Some(level),
+    };
+    let data = post_graphql::<FetchExtensionLogs>(var).await?;
+    Ok(data.extension_logs)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/set_source_preference.graphql",
+    response_derives = "Debug"
+)]
+pub struct SetSourcePreference;
+
+pub async fn set_source_preference(
+    source_id: i64,
+    key: String,
+    value: Option<String>,
+    server: bool,
+) -> Result<bool, Box<dyn Error>> {
+    let var = set_source_preference::Variables {
+        source_id: Some(source_id),
+        key: Some(key),
+        value,
+        server: Some(server),
+    };
+    let data = post_graphql::<SetSourcePreference>(var).await?;
+    Ok(data.set_source_preference)
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/fetch_source_filters.graphql",
+    response_derives = "Debug"
+)]
+pub struct FetchSourceFilters;
+
+pub async fn fetch_source_filters(
+    source_id: i64,
+) -> Result<Option<fetch_source_filters::FetchSourceFiltersSourceFilters>, Box<dyn Error>> {
+    let var = fetch_source_filters::Variables {
+        source_id: Some(source_id),
+    };
+    let data = post_graphql::<FetchSourceFilters>(var).await?;
+    Ok(data.source.filters)
+}
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
@@ -471,12 +762,55 @@ pub async fn change_password(
 )]
 pub struct UpdateProfile;
 
-pub async fn update_profile(telegram_chat_id: Option<i64>) -> Result<(), Box<dyn Error>> {
-    let var = update_profile::Variables { telegram_chat_id };
+pub async fn update_profile(
+    telegram_chat_id: Option<i64>,
+    notifier: Option<String>,
+    discord_webhook: Option<String>,
+    webhook_url: Option<String>,
+    email: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let notifier = notifier.map(|kind| match kind.as_str() {
+        "TELEGRAM" => update_profile::NotifierKind::TELEGRAM,
+        "DISCORD" => update_profile::NotifierKind::DISCORD,
+        "WEBHOOK" => update_profile::NotifierKind::WEBHOOK,
+        "EMAIL" => update_profile::NotifierKind::EMAIL,
+        _ => update_profile::NotifierKind::Other(kind),
+    });
+    let var = update_profile::Variables {
+        telegram_chat_id,
+        notifier,
+        discord_webhook,
+        webhook_url,
+        email,
+    };
     let _ = post_graphql::<UpdateProfile>(var).await?;
     Ok(())
 }
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/fetch_notifiers.graphql",
+    response_derives = "Debug"
+)]
+pub struct FetchNotifiers;
+
+pub async fn fetch_notifiers() -> Result<Vec<String>, Box<dyn Error>> {
+    let var = fetch_notifiers::Variables {};
+    let data = post_graphql::<FetchNotifiers>(var).await?;
+    Ok(data
+        .notifiers
+        .into_iter()
+        .map(|kind| match kind {
+            fetch_notifiers::NotifierKind::TELEGRAM => "TELEGRAM".to_string(),
+            fetch_notifiers::NotifierKind::DISCORD => "DISCORD".to_string(),
+            fetch_notifiers::NotifierKind::WEBHOOK => "WEBHOOK".to_string(),
+            fetch_notifiers::NotifierKind::EMAIL => "EMAIL".to_string(),
+            fetch_notifiers::NotifierKind::Other(kind) => kind,
+        })
+        .collect())
+}
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
@@ -495,16 +829,14 @@ pub async fn server_status(
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "graphql/schema.graphql",
-    query_path = "graphql/test_telegram.graphql",
+    query_path = "graphql/test_notification.graphql",
     response_derives = "Debug"
 )]
-pub struct TestTelegram;
+pub struct TestNotification;
 
-pub async fn test_telegram(chat_id: i64) -> Result<(), Box<dyn Error>> {
-    let var = test_telegram::Variables {
-        chat_id: Some(chat_id),
-    };
-    let _ = post_graphql::<TestTelegram>(var).await?;
+pub async fn test_notification() -> Result<(), Box<dyn Error>> {
+    let var = test_notification::Variables {};
+    let _ = post_graphql::<TestNotification>(var).await?;
     Ok(())
 }
 
@@ -541,3 +873,18 @@ pub async fn mark_chapter_as_unread(chapter_ids: &[i64]) -> Result<(), Box<dyn E
     let _ = post_graphql::<MarkChapterAsUnread>(var).await?;
     Ok(())
 }
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/chapter_updates.graphql",
+    response_derives = "Debug"
+)]
+pub struct ChapterUpdates;
+
+/// New chapters of manga in library, pushed by server as they are found
+pub fn subscribe_chapter_updates(
+) -> Result<impl Stream<Item = chapter_updates::ChapterUpdatesChapterUpdates>, Box<dyn Error>> {
+    let subscription = subscribe_graphql::<ChapterUpdates>(chapter_updates::Variables {})?;
+    Ok(subscription.map(|data| data.chapter_updates))
+}
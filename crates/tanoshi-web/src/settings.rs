@@ -1,20 +1,28 @@
-use crate::{common::{AppearanceSettings, ChapterSettings, Login, Profile, ReaderSettings, Route, SettingCategory, Source, Spinner, User, events, snackbar}, query, utils::{AsyncLoader, window}};
+use crate::{common::{AppearanceSettings, ChapterSettings, Login, Profile, ReaderSettings, Route, SettingCategory, Source, Spinner, User, events, snackbar}, query::{self, fetch_extension_logs::{FetchExtensionLogsExtensionLogs as ExtensionLog, LogLevel}, fetch_source_preferences::{FetchSourcePreferencesSourcePreferences as SourcePreference, PreferenceKind}}, utils::{AsyncLoader, window}};
 use dominator::svg;
-use dominator::{clone, html, link, routing, Dom};
-use futures_signals::{signal::{Mutable, SignalExt}, signal_vec::{MutableSignalVec, MutableVec}, signal_vec::SignalVecExt};
+use dominator::{clone, html, link, routing, Dom, with_node};
+use futures_signals::{signal::{self, Mutable, SignalExt}, signal_vec::{MutableSignalVec, MutableVec}, signal_vec::SignalVecExt};
 use std::rc::Rc;
+use web_sys::HtmlInputElement;
 
 pub struct Settings {
     server_version: String,
     page: Mutable<SettingCategory>,
     installed_sources: MutableVec<Source>,
     available_sources: MutableVec<Source>,
+    source_preferences: MutableVec<SourcePreference>,
+    /// Whether preferences are edited for the whole server instead of requesting user
+    server_preferences: Mutable<bool>,
+    extension_logs: MutableVec<ExtensionLog>,
+    /// Minimum level of extension logs shown
+    log_level: Mutable<&'static str>,
     me: Mutable<Option<User>>,
     users: MutableVec<User>,
     appearance_settings: Rc<AppearanceSettings>,
     reader_settings: Rc<ReaderSettings>,
     chapter_settings: Rc<ChapterSettings>,
     loader: AsyncLoader,
+    log_loader: AsyncLoader,
 }
 
 impl Settings {
@@ -24,12 +32,17 @@ impl Settings {
             page: Mutable::new(SettingCategory::None),
             installed_sources: MutableVec::new(),
             available_sources: MutableVec::new(),
+            source_preferences: MutableVec::new(),
+            server_preferences: Mutable::new(false),
+            extension_logs: MutableVec::new(),
+            log_level: Mutable::new("TRACE"),
             me: Mutable::new(None),
             users: MutableVec::new(),
             appearance_settings: AppearanceSettings::new(),
             reader_settings: ReaderSettings::new(true, false),
             chapter_settings: ChapterSettings::new(true, false),
             loader: AsyncLoader::new(),
+            log_loader: AsyncLoader::new(),
         })
     }
 
@@ -44,6 +57,7 @@ impl Settings {
                         icon: s.icon.clone(),
                         need_login: s.need_login,
                         has_update: s.has_update,
+                        repository: s.repository.clone(),
                         installed: true,
                     }).collect());
 
@@ -54,6 +68,7 @@ impl Settings {
                         icon: s.icon.clone(),
                         need_login: s.need_login,
                         has_update: s.has_update,
+                        repository: s.repository.clone(),
                         installed: false,
                     }).collect());                    
                 },
@@ -126,6 +141,7 @@ impl Settings {
                         icon: s.icon.clone(),
                         need_login: s.need_login,
                         has_update: s.has_update,
+                        repository: s.repository.clone(),
                         installed: true,
                     }).collect());
 
@@ -136,6 +152,7 @@ impl Settings {
                         icon: s.icon.clone(),
                         need_login: s.need_login,
                         has_update: s.has_update,
+                        repository: s.repository.clone(),
                         installed: false,
                     }).collect());
                 },
@@ -165,6 +182,7 @@ impl Settings {
                         icon: s.icon.clone(),
                         need_login: s.need_login,
                         has_update: s.has_update,
+                        repository: s.repository.clone(),
                         installed: true,
                     }).collect());
 
@@ -175,6 +193,7 @@ impl Settings {
                         icon: s.icon.clone(),
                         need_login: s.need_login,
                         has_update: s.has_update,
+                        repository: s.repository.clone(),
                         installed: false,
                     }).collect());
                 },
@@ -185,6 +204,85 @@ impl Settings {
         }));
     }
 
+    async fn load_source_preferences(settings: &Rc<Self>, source_id: i64) {
+        match query::fetch_source_preferences(source_id).await {
+            Ok(result) => {
+                settings.me.set(Some(User{
+                    id: result.me.id,
+                    username: result.me.username,
+                    is_admin: result.me.is_admin,
+                    telegram_chat_id: result.me.settings.telegram_chat_id
+                }));
+                settings.source_preferences.lock_mut().replace_cloned(result.source.preferences);
+            },
+            Err(err) => {
+                snackbar::show(format!("{}", err));
+            }
+        }
+    }
+
+    fn fetch_source_preferences(settings: Rc<Self>, source_id: i64) {
+        settings.loader.load(clone!(settings => async move {
+            Self::load_source_preferences(&settings, source_id).await;
+        }));
+    }
+
+    fn set_source_preference(settings: Rc<Self>, source_id: i64, key: String, value: Option<String>) {
+        let server = settings.server_preferences.get();
+        settings.loader.load(clone!(settings => async move {
+            if let Err(err) = query::set_source_preference(source_id, key, value, server).await {
+                snackbar::show(format!("{}", err));
+                return;
+            }
+
+            Self::load_source_preferences(&settings, source_id).await;
+        }));
+    }
+
+    fn fetch_extension_logs(settings: Rc<Self>, source_id: i64) {
+        let level = match settings.log_level.get() {
+            "ERROR" => LogLevel::ERROR,
+            "WARN" => LogLevel::WARN,
+            "INFO" => LogLevel::INFO,
+            "DEBUG" => LogLevel::DEBUG,
+            _ => LogLevel::TRACE,
+        };
+        settings.log_loader.load(clone!(settings => async move {
+            // logs are only for admin, so don't ask for them otherwise
+            let is_admin = match settings.me.get_cloned() {
+                Some(me) => me.is_admin,
+                None => match query::fetch_me().await {
+                    Ok(result) => {
+                        let is_admin = result.is_admin;
+                        settings.me.set(Some(User{
+                            id: result.id,
+                            username: result.username,
+                            is_admin: result.is_admin,
+                            telegram_chat_id: result.settings.telegram_chat_id
+                        }));
+                        is_admin
+                    },
+                    Err(err) => {
+                        snackbar::show(format!("{}", err));
+                        return;
+                    }
+                }
+            };
+            if !is_admin {
+                return;
+            }
+
+            match query::fetch_extension_logs(source_id, level).await {
+                Ok(logs) => {
+                    settings.extension_logs.lock_mut().replace_cloned(logs);
+                },
+                Err(err) => {
+                    snackbar::show(format!("{}", err));
+                }
+            }
+        }));
+    }
+
     fn uninstall_source(settings: Rc<Self>, id: i64) {
         settings.loader.load(async move {
             match query::uninstall_source(id).await {
@@ -258,6 +356,7 @@ impl Settings {
                             SettingCategory::General => "General",
                             SettingCategory::Reader => "Reader",
                             SettingCategory::Source(_) => "Sources",
+                            SettingCategory::SourcePreferences(_) => "Preferences",
                             SettingCategory::Users => "Users",
                             SettingCategory::CreateUser => "Create User",
                             SettingCategory::User => "User",
@@ -357,7 +456,10 @@ impl Settings {
                                                     .text(&x.name)
                                                 }),
                                                 html!("span", {
-                                                    .text(&x.version)
+                                                    .text(&match x.repository.as_ref() {
+                                                        Some(repository) => format!("{} ({})", x.version, repository),
+                                                        None => x.version.clone(),
+                                                    })
                                                 })
                                             ])
                                         })
@@ -424,6 +526,10 @@ impl Settings {
                     html!("div", {})
                 }))
                 .children(&mut [
+                    link!(Route::Settings(SettingCategory::SourcePreferences(source_id)).url(), {
+                        .style("margin", "0.5rem")
+                        .text("Preferences")
+                    }),
                     html!("button", {
                         .class("uninstall-btn")
                         .children(&mut [
@@ -434,12 +540,171 @@ impl Settings {
                                 }))
                             })
                         ])
-                    })
+                    }),
+                    Self::render_extension_logs(settings.clone(), source_id),
                 ])
             })
         }
     }
 
+    fn render_extension_logs(settings: Rc<Self>, source_id: i64) -> Dom {
+        html!("div", {
+            .class("extension-logs")
+            .visible_signal(settings.me.signal_cloned().map(|me| me.map(|me| me.is_admin).unwrap_or(false)))
+            .children(&mut [
+                html!("div", {
+                    .class("extension-logs-levels")
+                    .children(&mut ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"].iter().map(|level| {
+                        let level = *level;
+                        html!("span", {
+                            .class("chip")
+                            .class_signal("active", settings.log_level.signal().map(move |x| x == level))
+                            .text(level)
+                            .event(clone!(settings => move |_: events::Click| {
+                                settings.log_level.set_neq(level);
+                                Self::fetch_extension_logs(settings.clone(), source_id);
+                            }))
+                        })
+                    }).collect::<Vec<Dom>>())
+                    .children(&mut [
+                        html!("button", {
+                            .text("Refresh")
+                            .event(clone!(settings => move |_: events::Click| {
+                                Self::fetch_extension_logs(settings.clone(), source_id);
+                            }))
+                        })
+                    ])
+                }),
+                html!("ul", {
+                    .class(["list", "group"])
+                    .children_signal_vec(settings.extension_logs.signal_vec_cloned().map(|line| html!("li", {
+                        .class(["list-item", "extension-log"])
+                        .children(&mut [
+                            html!("span", {
+                                .text(&format!("{} {:?}", line.at, line.level))
+                            }),
+                            html!("pre", {
+                                .text(&line.message)
+                            }),
+                        ])
+                    })))
+                })
+            ])
+        })
+    }
+
+    fn render_preference_control(settings: Rc<Self>, source_id: i64, preference: &SourcePreference, server: bool) -> Dom {
+        // value in effect for what is edited, user's own value falls back to server value
+        let value = if server {
+            preference.server_value.clone()
+        } else {
+            preference.value.clone().or_else(|| preference.server_value.clone())
+        }.unwrap_or_else(|| preference.default.clone());
+        let is_set = if server { preference.server_value.is_some() } else { preference.value.is_some() };
+        let key = preference.key.clone();
+
+        html!("div", {
+            .style("display", "flex")
+            .style("align-items", "center")
+            .style("justify-content", "space-between")
+            .children(&mut [
+                match preference.kind {
+                    PreferenceKind::SELECT => html!("div", {
+                        // row is only styled inside reader settings
+                        .class("reader-settings")
+                        .children(&mut [
+                            html!("div", {
+                                .class("reader-settings-row")
+                                .children(&mut preference.options.iter().map(|option| html!("button", {
+                                    .class_signal("active", signal::always(option.value == value))
+                                    .text(&option.title)
+                                    .event(clone!(settings, key, option => move |_: events::Click| {
+                                        Self::set_source_preference(settings.clone(), source_id, key.clone(), Some(option.value.clone()));
+                                    }))
+                                })).collect::<Vec<Dom>>())
+                            })
+                        ])
+                    }),
+                    PreferenceKind::TOGGLE => html!("input" => HtmlInputElement, {
+                        .attribute("type", "checkbox")
+                        .attribute_signal("checked", signal::always(if value == "true" {Some("checked")} else {None}))
+                        .with_node!(element => {
+                            .event(clone!(settings, key => move |_: events::Change| {
+                                Self::set_source_preference(settings.clone(), source_id, key.clone(), Some(element.checked().to_string()));
+                            }))
+                        })
+                    }),
+                    PreferenceKind::TEXT => html!("input" => HtmlInputElement, {
+                        .attribute("type", "text")
+                        .property("value", value.as_str())
+                        .with_node!(input => {
+                            .event(clone!(settings, key => move |_: events::Change| {
+                                Self::set_source_preference(settings.clone(), source_id, key.clone(), Some(input.value()));
+                            }))
+                        })
+                    }),
+                    PreferenceKind::Other(_) => html!("div", {}),
+                },
+                html!("button", {
+                    .visible(is_set)
+                    .text("Reset")
+                    .event(clone!(settings => move |_: events::Click| {
+                        Self::set_source_preference(settings.clone(), source_id, key.clone(), None);
+                    }))
+                })
+            ])
+        })
+    }
+
+    pub fn render_source_preferences(settings: Rc<Self>, source_id: i64) -> Dom {
+        html!("div", {
+            .child_signal(settings.me.signal_cloned().map(clone!(settings => move |me| match me {
+                Some(me) if me.is_admin => Some(html!("label", {
+                    .style("display", "flex")
+                    .style("align-items", "center")
+                    .style("padding", "0.5rem")
+                    .children(&mut [
+                        html!("input" => HtmlInputElement, {
+                            .attribute("type", "checkbox")
+                            .attribute_signal("checked", settings.server_preferences.signal().map(|x| if x {Some("checked")} else {None}))
+                            .with_node!(element => {
+                                .event(clone!(settings => move |_: events::Change| {
+                                    settings.server_preferences.set_neq(element.checked());
+                                }))
+                            })
+                        })
+                    ])
+                    .text("Set for all users")
+                })),
+                _ => None,
+            })))
+            .children(&mut [
+                html!("ul", {
+                    .class(["list", "group"])
+                    .children_signal_vec(settings.source_preferences.signal_vec_cloned().map(clone!(settings => move |preference| html!("li", {
+                        .class("list-item")
+                        .style("display", "flex")
+                        .style("flex-direction", "column")
+                        .style("align-items", "stretch")
+                        .children(&mut [
+                            html!("span", {
+                                .text(&preference.title)
+                            }),
+                            html!("span", {
+                                .style("font-size", "small")
+                                .visible(preference.description.is_some())
+                                .text(preference.description.as_deref().unwrap_or_default())
+                            }),
+                        ])
+                        .child_signal(settings.server_preferences.signal().map(clone!(settings, preference => move |server| {
+                            Some(Self::render_preference_control(settings.clone(), source_id, &preference, server))
+                        })))
+                    }))))
+                })
+            ])
+        })
+    }
+
     pub fn render_users_management(settings: Rc<Self>) -> Dom {
         html!("ul", {
             .class(["list", "group"])
@@ -526,7 +791,14 @@ impl Settings {
         settings.page.set(category.clone());
         match category {
             SettingCategory::None => Self::fetch_me(settings.clone()),
-            SettingCategory::Source(_) => Self::fetch_sources(settings.clone()),
+            SettingCategory::Source(source_id) => {
+                Self::fetch_sources(settings.clone());
+                if source_id > 0 {
+                    settings.extension_logs.lock_mut().clear();
+                    Self::fetch_extension_logs(settings.clone(), source_id);
+                }
+            },
+            SettingCategory::SourcePreferences(source_id) => Self::fetch_source_preferences(settings.clone(), source_id),
             SettingCategory::Users => Self::fetch_user_list(settings.clone()),
             _ => {}
         }
@@ -563,6 +835,7 @@ impl Settings {
                     })),
                     SettingCategory::Reader => Some(ReaderSettings::render(settings.reader_settings.clone())),
                     SettingCategory::Source(source_id) => Some(Self::render_source_settings(settings.clone(), source_id)),
+                    SettingCategory::SourcePreferences(source_id) => Some(Self::render_source_preferences(settings.clone(), source_id)),
                     SettingCategory::Users => Some(Self::render_users_management(settings.clone())),
                     SettingCategory::User => Some(Profile::render(Profile::new())),
                     SettingCategory::CreateUser => Some(Login::render(Login::new())),
@@ -8,6 +8,7 @@ use crate::{
     common::{Route, Spinner},
 };
 use dominator::{clone, events, html, link, routing, svg, Dom};
+use futures::StreamExt;
 use futures_signals::signal::{Mutable, SignalExt};
 use futures_signals::signal_vec::{MutableVec, SignalVecExt};
 use wasm_bindgen::UnwrapThrowExt;
@@ -67,6 +68,37 @@ impl Updates {
         }));
     }
 
+    /// Put chapter found by server while this page is open on top of the list
+    fn insert_update(updates: &Self, update: query::chapter_updates::ChapterUpdatesChapterUpdates) {
+        let uploaded =
+            match chrono::NaiveDateTime::parse_from_str(&update.uploaded, "%Y-%m-%dT%H:%M:%S%.f") {
+                Ok(uploaded) => uploaded,
+                Err(_) => return,
+            };
+
+        let mut entries = updates.entries.lock_mut();
+        if entries
+            .iter()
+            .any(|entry| entry.chapter_id == update.chapter_id)
+        {
+            return;
+        }
+        entries.insert_cloned(
+            0,
+            Entry {
+                manga_id: update.manga_id,
+                manga_title: update.manga_title,
+                cover_url: update.cover_url,
+                chapter_id: update.chapter_id,
+                chapter_title: update.chapter_title,
+                uploaded,
+                // same as cursor of recentUpdates, in case it is the only entry when loading more
+                cursor: base64::encode(format!("{}#{}", uploaded.timestamp(), update.chapter_id)),
+            },
+        );
+        updates.is_entries_empty.set(false);
+    }
+
     pub fn render_topbar() -> Dom {
         html!("div", {
             .class("topbar")
@@ -170,6 +202,18 @@ impl Updates {
     pub fn render(updates: Rc<Self>, _app: Rc<App>) -> Dom {
         Self::fetch_recent_chapters(updates.clone());
         html! {"div", {
+            // subscription is closed when page is left and this future dropped
+            .future(clone!(updates => async move {
+                match query::subscribe_chapter_updates() {
+                    Ok(chapter_updates) => {
+                        chapter_updates.for_each(|update| {
+                            Self::insert_update(&updates, update);
+                            async {}
+                        }).await;
+                    }
+                    Err(err) => snackbar::show(format!("{}", err)),
+                }
+            }))
             .children(&mut [
                 Self::render_topbar(),
                 html!("div", {
@@ -0,0 +1,6 @@
+ALTER TABLE user ADD COLUMN notifier TEXT;
+ALTER TABLE user ADD COLUMN discord_webhook TEXT;
+ALTER TABLE user ADD COLUMN webhook_url TEXT;
+ALTER TABLE user ADD COLUMN email TEXT;
+
+UPDATE user SET notifier = 'telegram' WHERE telegram_chat_id IS NOT NULL;
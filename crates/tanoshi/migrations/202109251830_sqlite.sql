@@ -0,0 +1,9 @@
+CREATE TABLE source_credential (
+    user_id INTEGER NOT NULL,
+    source_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    auth TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY(user_id, source_id),
+    FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE ON UPDATE NO ACTION
+);
@@ -0,0 +1,14 @@
+CREATE TABLE source_preference (
+    source_id INTEGER PRIMARY KEY,
+    preferences TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE user_source_preference (
+    user_id INTEGER NOT NULL,
+    source_id INTEGER NOT NULL,
+    preferences TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY(user_id, source_id),
+    FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE ON UPDATE NO ACTION
+);
@@ -0,0 +1,7 @@
+CREATE TABLE extension_storage (
+    source_id INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY(source_id, key)
+);
@@ -0,0 +1 @@
+ALTER TABLE chapter ADD COLUMN volume INTEGER;
\ No newline at end of file
@@ -0,0 +1,80 @@
+-- local manga and chapters are addressed by their path relative to `local_path`,
+-- manga is a direct child of it and chapter is a direct child of its manga,
+-- or the manga itself for a series in a single archive.
+-- rows that end up with the same path, e.g. from manga moved to another `local_path`,
+-- are merged into the oldest one
+CREATE TEMP TABLE local_manga AS
+SELECT
+    id,
+    path AS old_path,
+    substr(
+        replace(path, '\', '/'),
+        length(rtrim(replace(path, '\', '/'), replace(replace(path, '\', '/'), '/', ''))) + 1
+    ) AS path,
+    id AS keep_id
+FROM manga
+WHERE source_id = 1;
+
+UPDATE local_manga
+SET keep_id = (SELECT MIN(other.id) FROM local_manga AS other WHERE other.path = local_manga.path);
+
+CREATE TEMP TABLE local_chapter AS
+SELECT
+    chapter.id AS id,
+    local_manga.keep_id AS manga_id,
+    CASE
+        WHEN chapter.path = local_manga.old_path THEN local_manga.path
+        ELSE local_manga.path || '/' || substr(
+            replace(chapter.path, '\', '/'),
+            length(rtrim(replace(chapter.path, '\', '/'), replace(replace(chapter.path, '\', '/'), '/', ''))) + 1
+        )
+    END AS path,
+    chapter.id AS keep_id
+FROM chapter
+JOIN local_manga ON local_manga.id = chapter.manga_id
+WHERE chapter.source_id = 1;
+
+UPDATE local_chapter
+SET keep_id = (SELECT MIN(other.id) FROM local_chapter AS other WHERE other.path = local_chapter.path);
+
+-- reading history of merged chapters is kept unless the kept chapter has its own
+INSERT INTO user_history (user_id, chapter_id, last_page, read_at, is_complete)
+SELECT user_history.user_id, local_chapter.keep_id, user_history.last_page, MAX(user_history.read_at), user_history.is_complete
+FROM user_history
+JOIN local_chapter ON local_chapter.id = user_history.chapter_id
+WHERE local_chapter.id != local_chapter.keep_id
+    AND NOT EXISTS (
+        SELECT 1 FROM user_history AS kept
+        WHERE kept.user_id = user_history.user_id AND kept.chapter_id = local_chapter.keep_id
+    )
+GROUP BY user_history.user_id, local_chapter.keep_id;
+
+DELETE FROM user_history WHERE chapter_id IN (SELECT id FROM local_chapter WHERE id != keep_id);
+DELETE FROM page WHERE chapter_id IN (SELECT id FROM local_chapter WHERE id != keep_id);
+DELETE FROM chapter WHERE id IN (SELECT id FROM local_chapter WHERE id != keep_id);
+
+UPDATE chapter
+SET
+    path = (SELECT path FROM local_chapter WHERE local_chapter.id = chapter.id),
+    manga_id = (SELECT manga_id FROM local_chapter WHERE local_chapter.id = chapter.id)
+WHERE id IN (SELECT id FROM local_chapter);
+
+INSERT INTO user_library (user_id, manga_id)
+SELECT DISTINCT user_library.user_id, local_manga.keep_id
+FROM user_library
+JOIN local_manga ON local_manga.id = user_library.manga_id
+WHERE local_manga.id != local_manga.keep_id
+    AND NOT EXISTS (
+        SELECT 1 FROM user_library AS kept
+        WHERE kept.user_id = user_library.user_id AND kept.manga_id = local_manga.keep_id
+    );
+
+DELETE FROM user_library WHERE manga_id IN (SELECT id FROM local_manga WHERE id != keep_id);
+DELETE FROM manga WHERE id IN (SELECT id FROM local_manga WHERE id != keep_id);
+
+UPDATE manga
+SET path = (SELECT path FROM local_manga WHERE local_manga.id = manga.id)
+WHERE id IN (SELECT id FROM local_manga);
+
+DROP TABLE local_chapter;
+DROP TABLE local_manga;
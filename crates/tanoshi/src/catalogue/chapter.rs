@@ -1,5 +1,5 @@
 use super::Manga;
-use crate::{context::GlobalContext, user, utils};
+use crate::{context::GlobalContext, proxy::Image, user};
 use async_graphql::{Context, Object, Result, SimpleObject};
 use chrono::NaiveDateTime;
 
@@ -29,6 +29,7 @@ pub struct Chapter {
     pub title: String,
     pub path: String,
     pub number: f64,
+    pub volume: Option<i64>,
     pub scanlator: String,
     pub prev: Option<i64>,
     pub next: Option<i64>,
@@ -47,6 +48,7 @@ impl From<tanoshi_lib::data::Chapter> for Chapter {
             title: ch.title,
             path: ch.path,
             number: ch.number,
+            volume: ch.volume,
             scanlator: ch.scanlator,
             prev: None,
             next: None,
@@ -67,6 +69,7 @@ impl From<crate::db::model::Chapter> for Chapter {
             title: val.title,
             path: val.path,
             number: val.number,
+            volume: val.volume,
             scanlator: val.scanlator,
             prev: val.prev,
             next: val.next,
@@ -87,6 +90,7 @@ impl From<tanoshi_lib::data::Chapter> for crate::db::model::Chapter {
             title: ch.title,
             path: ch.path,
             number: ch.number,
+            volume: ch.volume,
             scanlator: ch.scanlator,
             prev: None,
             next: None,
@@ -106,6 +110,7 @@ impl From<Chapter> for crate::db::model::Chapter {
             title: val.title,
             path: val.path,
             number: val.number,
+            volume: val.volume,
             scanlator: val.scanlator,
             prev: val.prev,
             next: val.next,
@@ -134,6 +139,10 @@ impl Chapter {
         self.number
     }
 
+    async fn volume(&self) -> Option<i64> {
+        self.volume
+    }
+
     async fn scanlator(&self) -> String {
         self.scanlator.clone()
     }
@@ -184,24 +193,35 @@ impl Chapter {
         let pages = if !self.pages.is_empty() && !fetch {
             self.pages.clone()
         } else {
+            let session = super::source_session(ctx, self.source_id).await;
             let pages = ctx
                 .data::<GlobalContext>()?
                 .extensions
-                .get_pages(self.source_id, self.path.clone())
-                .await?;
-
-            ctx.data::<GlobalContext>()?
-                .mangadb
-                .insert_pages(self.id, &pages)
-                .await?;
-
-            pages
+                .get_pages(self.source_id, self.path.clone(), session)
+                .await
+                .map_err(super::extension_error)?;
+
+            let mangadb = &ctx.data::<GlobalContext>()?.mangadb;
+            mangadb.insert_pages(self.id, &pages).await?;
+
+            // serve already downloaded pages from disk
+            mangadb
+                .get_pages_by_chapter_id(self.id)
+                .await?
+                .into_iter()
+                .map(|page| page.local_url.unwrap_or(page.remote_url))
+                .collect()
         };
 
         let secret = ctx.data::<GlobalContext>()?.secret.clone();
+        let user_id = user::get_claims(ctx).ok().map(|claims| claims.sub);
         let pages = pages
             .iter()
-            .map(|page| utils::encrypt_url(&secret, page).unwrap_or_default())
+            .map(|page| {
+                Image::new(self.source_id, page, user_id)
+                    .encrypt(&secret)
+                    .unwrap_or_default()
+            })
             .collect();
 
         Ok(pages)
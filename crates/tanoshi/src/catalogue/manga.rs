@@ -1,5 +1,5 @@
 use super::{Chapter, Source};
-use crate::{context::GlobalContext, user, utils};
+use crate::{context::GlobalContext, proxy::Image, user};
 use async_graphql::{Context, Object, Result};
 
 /// A type represent manga details, normalized across source
@@ -125,8 +125,9 @@ impl Manga {
     }
 
     async fn cover_url(&self, ctx: &Context<'_>) -> String {
+        let user_id = user::get_claims(ctx).ok().map(|claims| claims.sub);
         if let Ok(ctx) = ctx.data::<GlobalContext>() {
-            match utils::encrypt_url(&ctx.secret, &self.cover_url) {
+            match Image::new(self.source_id, &self.cover_url, user_id).encrypt(&ctx.secret) {
                 Ok(encrypted_url) => {
                     return encrypted_url;
                 }
@@ -179,7 +180,11 @@ impl Manga {
 
     async fn source(&self, ctx: &Context<'_>) -> Result<Source> {
         let ctx = ctx.data::<GlobalContext>()?;
-        let source = ctx.extensions.detail(self.source_id).await?;
+        let source = ctx
+            .extensions
+            .detail(self.source_id)
+            .await
+            .map_err(super::extension_error)?;
         Ok(source.into())
     }
 
@@ -188,6 +193,7 @@ impl Manga {
         ctx: &Context<'_>,
         #[graphql(desc = "refresh data from source", default = false)] refresh: bool,
     ) -> Result<Vec<Chapter>> {
+        let session = super::source_session(ctx, self.source_id).await;
         let ctx = ctx.data::<GlobalContext>()?;
         let db = ctx.mangadb.clone();
 
@@ -199,8 +205,9 @@ impl Manga {
 
         let chapters: Vec<crate::db::model::Chapter> = ctx
             .extensions
-            .get_chapters(self.source_id, self.path.clone())
-            .await?
+            .get_chapters(self.source_id, self.path.clone(), session)
+            .await
+            .map_err(super::extension_error)?
             .into_iter()
             .map(|c| {
                 let mut c: crate::db::model::Chapter = c.into();
@@ -1,5 +1,5 @@
 mod source;
-pub use source::{Source, SourceMutationRoot, SourceRoot};
+pub use source::{get_source_session, source_session, Source, SourceMutationRoot, SourceRoot};
 
 mod manga;
 pub use manga::Manga;
@@ -7,10 +7,17 @@ pub use manga::Manga;
 mod chapter;
 pub use chapter::Chapter;
 
-use crate::context::GlobalContext;
+use crate::{context::GlobalContext, utils};
 
-use async_graphql::{Context, Enum, Object, Result};
-use tanoshi_lib::prelude::Param;
+use async_graphql::{Context, Enum, ErrorExtensions, Json, Object, Result, Union};
+use tanoshi_lib::prelude::{Param, ParamFilterValue, UrlPath};
+use tanoshi_vm::prelude::{ExtensionError, Session};
+
+/// Error of extension call with its variant in `extensions.code`, so client can tell
+/// e.g. a source that is down from one that isn't installed
+pub fn extension_error(e: ExtensionError) -> async_graphql::Error {
+    async_graphql::Error::new(e.to_string()).extend_with(|_, ext| ext.set("code", e.code()))
+}
 
 /// A type represent sort parameter for query manga from source, normalized across sources
 #[derive(Enum, Copy, Clone, Eq, PartialEq)]
@@ -30,6 +37,36 @@ pub enum SortOrderParam {
     Desc,
 }
 
+/// Manga or chapter a url of a source points to
+#[derive(Union)]
+pub enum ResolvedUrl {
+    Manga(Manga),
+    Chapter(Chapter),
+}
+
+/// Get manga from database, fetch and insert it from source if it isn't there yet
+async fn get_or_insert_manga(
+    ctx: &GlobalContext,
+    source_id: i64,
+    path: String,
+    session: Session,
+) -> Result<crate::db::model::Manga> {
+    let db = ctx.mangadb.clone();
+    if let Ok(manga) = db.get_manga_by_source_path(source_id, &path).await {
+        return Ok(manga);
+    }
+
+    let mut m: crate::db::model::Manga = ctx
+        .extensions
+        .get_manga_info(source_id, path, session)
+        .await
+        .map_err(extension_error)?
+        .into();
+
+    db.insert_manga(&mut m).await?;
+    Ok(m)
+}
+
 #[derive(Default)]
 pub struct CatalogueRoot;
 
@@ -44,10 +81,12 @@ impl CatalogueRoot {
         #[graphql(desc = "page")] page: Option<i32>,
         #[graphql(desc = "sort by")] sort_by: Option<SortByParam>,
         #[graphql(desc = "sort order")] sort_order: Option<SortOrderParam>,
+        #[graphql(desc = "source filters")] filters: Option<Json<ParamFilterValue>>,
     ) -> Result<Vec<Manga>> {
         let sort_by = sort_by.map(|s| s.into());
         let sort_order = sort_order.map(|s| s.into());
 
+        let session = source_session(ctx, source_id).await;
         let ctx = ctx.data::<GlobalContext>()?;
         let fetched_manga = {
             let extensions = ctx.extensions.clone();
@@ -60,10 +99,13 @@ impl CatalogueRoot {
                         page,
                         sort_by,
                         sort_order,
+                        filters: filters.map(|filters| filters.0),
                         ..Default::default()
                     },
+                    session,
                 )
-                .await?
+                .await
+                .map_err(extension_error)?
                 .iter()
                 .map(Manga::from)
                 .collect()
@@ -72,45 +114,143 @@ impl CatalogueRoot {
         Ok(fetched_manga)
     }
 
+    /// Recently updated manga of a source, as listed by the source itself
+    async fn browse_latest(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "source id")] source_id: i64,
+        #[graphql(desc = "page", default = 1)] page: i32,
+    ) -> Result<Vec<Manga>> {
+        let session = source_session(ctx, source_id).await;
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        Ok(ctx
+            .extensions
+            .get_latest_manga(source_id, page, session)
+            .await
+            .map_err(extension_error)?
+            .iter()
+            .map(Manga::from)
+            .collect())
+    }
+
+    /// Popular manga of a source, as listed by the source itself
+    async fn browse_popular(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "source id")] source_id: i64,
+        #[graphql(desc = "page", default = 1)] page: i32,
+    ) -> Result<Vec<Manga>> {
+        let session = source_session(ctx, source_id).await;
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        Ok(ctx
+            .extensions
+            .get_popular_manga(source_id, page, session)
+            .await
+            .map_err(extension_error)?
+            .iter()
+            .map(Manga::from)
+            .collect())
+    }
+
     async fn manga_by_source_path(
         &self,
         ctx: &Context<'_>,
         #[graphql(desc = "source id")] source_id: i64,
         #[graphql(desc = "path to manga in source")] path: String,
     ) -> Result<Manga> {
+        let session = source_session(ctx, source_id).await;
         let ctx = ctx.data::<GlobalContext>()?;
 
-        let db = ctx.mangadb.clone();
-        let manga = if let Ok(manga) = db.get_manga_by_source_path(source_id, &path).await {
-            manga
-        } else {
-            let mut m: crate::db::model::Manga = {
-                let extensions = ctx.extensions.clone();
-                extensions.get_manga_info(source_id, path).await?.into()
-            };
-
-            db.insert_manga(&mut m).await?;
-            m
-        };
+        let manga = get_or_insert_manga(ctx, source_id, path, session).await?;
 
         Ok(manga.into())
     }
 
+    /// Find manga or chapter of a url from any installed source that recognizes it
+    async fn resolve_url(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "url of manga or chapter in source")] url: String,
+    ) -> Result<ResolvedUrl> {
+        let sources = ctx
+            .data::<GlobalContext>()?
+            .extensions
+            .list()
+            .await
+            .map_err(extension_error)?;
+
+        for source in sources {
+            if !utils::is_source_url(&source.url, &url) {
+                continue;
+            }
+
+            let session = source_session(ctx, source.id).await;
+            let ctx = ctx.data::<GlobalContext>()?;
+            let url_path = ctx
+                .extensions
+                .parse_url(source.id, url.clone())
+                .await
+                .map_err(extension_error)?;
+
+            match url_path {
+                Some(UrlPath::Manga(path)) => {
+                    let manga = get_or_insert_manga(ctx, source.id, path, session).await?;
+                    return Ok(ResolvedUrl::Manga(manga.into()));
+                }
+                Some(UrlPath::Chapter { path, manga_path }) => {
+                    let db = ctx.mangadb.clone();
+                    if let Some(chapter) = db.get_chapter_by_source_path(source.id, &path).await {
+                        return Ok(ResolvedUrl::Chapter(chapter.into()));
+                    }
+
+                    let manga_path = manga_path.ok_or("chapter not found, open its manga first")?;
+                    let manga =
+                        get_or_insert_manga(ctx, source.id, manga_path, session.clone()).await?;
+                    let chapters: Vec<crate::db::model::Chapter> = ctx
+                        .extensions
+                        .get_chapters(source.id, manga.path.clone(), session)
+                        .await
+                        .map_err(extension_error)?
+                        .into_iter()
+                        .map(|c| {
+                            let mut c: crate::db::model::Chapter = c.into();
+                            c.manga_id = manga.id;
+                            c
+                        })
+                        .collect();
+                    db.insert_chapters(&chapters).await?;
+
+                    return db
+                        .get_chapter_by_source_path(source.id, &path)
+                        .await
+                        .map(|chapter| ResolvedUrl::Chapter(chapter.into()))
+                        .ok_or_else(|| "chapter not found in manga".into());
+                }
+                None => {}
+            }
+        }
+
+        Err("no installed source recognizes the url".into())
+    }
+
     async fn manga(
         &self,
         ctx: &Context<'_>,
         #[graphql(desc = "manga id")] id: i64,
         #[graphql(desc = "refresh data from source", default = false)] refresh: bool,
     ) -> Result<Manga> {
-        let ctx = ctx.data::<GlobalContext>()?;
-        let db = ctx.mangadb.clone();
+        let db = ctx.data::<GlobalContext>()?.mangadb.clone();
         let manga = db.get_manga_by_id(id).await?;
         if refresh {
+            let session = source_session(ctx, manga.source_id).await;
             let mut m: crate::db::model::Manga = {
-                let extensions = ctx.extensions.clone();
+                let extensions = ctx.data::<GlobalContext>()?.extensions.clone();
                 extensions
-                    .get_manga_info(manga.source_id, manga.path)
-                    .await?
+                    .get_manga_info(manga.source_id, manga.path, session)
+                    .await
+                    .map_err(extension_error)?
                     .into()
             };
             m.id = manga.id;
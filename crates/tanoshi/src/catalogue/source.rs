@@ -3,18 +3,80 @@ use std::{
     str::FromStr,
 };
 
-use crate::{context::GlobalContext, user};
-use async_graphql::{Context, Json, Object, Result, SimpleObject};
+use crate::{context::GlobalContext, db::UserDatabase, repository::SourceIndex, user, utils};
+use async_graphql::{Context, Enum, Json, Object, Result, SimpleObject};
 use serde::{Deserialize, Serialize};
-use tanoshi_lib::prelude::{FilterField, Version};
+use tanoshi_lib::prelude::{
+    FilterField, Preference, PreferenceField, Preferences, SourceLogin, SourceLoginResult, Version,
+};
+use tanoshi_vm::{
+    bus::Session,
+    logs::LogLine,
+    watcher::{Reload, ReloadResult},
+};
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct SourceIndex {
-    pub id: i64,
-    pub name: String,
-    pub path: String,
-    pub version: String,
-    pub icon: String,
+/// Stored login of user to a source, `None` if user hasn't logged in or it can't be read
+async fn get_source_auth(
+    userdb: &UserDatabase,
+    secret: &str,
+    user_id: i64,
+    source_id: i64,
+) -> Option<SourceLoginResult> {
+    let encrypted = match userdb.get_source_credential(user_id, source_id).await {
+        Ok(Some(encrypted)) => encrypted,
+        Ok(None) => return None,
+        Err(e) => {
+            error!("error get source credential: {}", e);
+            return None;
+        }
+    };
+
+    let auth = utils::decrypt(secret, &encrypted)
+        .and_then(|decrypted| Ok(ron::de::from_bytes(&decrypted)?));
+    match auth {
+        Ok(auth) => Some(auth),
+        Err(e) => {
+            error!("error decrypt source credential: {}", e);
+            None
+        }
+    }
+}
+
+/// Stored login and preferences of user to a source, extension calls are made with it
+pub async fn get_source_session(
+    userdb: &UserDatabase,
+    secret: &str,
+    user_id: i64,
+    source_id: i64,
+) -> Session {
+    let preferences = userdb
+        .get_user_source_preferences(user_id, source_id)
+        .await
+        .unwrap_or_else(|e| {
+            error!("error get source preferences: {}", e);
+            None
+        })
+        .unwrap_or_default();
+
+    Session {
+        user_id: Some(user_id),
+        auth: get_source_auth(userdb, secret, user_id, source_id).await,
+        preferences,
+    }
+}
+
+/// Stored login and preferences of requesting user to a source
+pub async fn source_session(ctx: &Context<'_>, source_id: i64) -> Session {
+    let user_id = match user::get_claims(ctx) {
+        Ok(claims) => claims.sub,
+        Err(_) => return Session::default(),
+    };
+    let ctx = match ctx.data::<GlobalContext>() {
+        Ok(ctx) => ctx,
+        Err(_) => return Session::default(),
+    };
+
+    get_source_session(&ctx.userdb, &ctx.secret, user_id, source_id).await
 }
 
 impl From<SourceIndex> for Source {
@@ -26,6 +88,9 @@ impl From<SourceIndex> for Source {
             icon: index.icon,
             need_login: false,
             has_update: false,
+            repository: Some(index.repository),
+            lib_version: index.lib_version,
+            preferences: vec![],
         }
     }
 }
@@ -45,6 +110,135 @@ impl From<tanoshi_lib::data::Filters> for Filters {
     }
 }
 
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum PreferenceKind {
+    Select,
+    Toggle,
+    Text,
+}
+
+#[derive(Debug, SimpleObject)]
+pub struct PreferenceOption {
+    title: String,
+    value: String,
+}
+
+/// Setting declared by a source, with values set to it
+#[derive(SimpleObject)]
+pub struct SourcePreference {
+    key: String,
+    title: String,
+    description: Option<String>,
+    kind: PreferenceKind,
+    /// Values a select can be set to, empty for other kinds
+    options: Vec<PreferenceOption>,
+    default: String,
+    /// Value set by requesting user, overrides `server_value`
+    value: Option<String>,
+    /// Value set by admin for the whole server
+    server_value: Option<String>,
+}
+
+impl SourcePreference {
+    fn new(preference: &Preference, values: &Preferences, server_values: &Preferences) -> Self {
+        let (kind, options) = match &preference.field {
+            PreferenceField::Select { options, .. } => (
+                PreferenceKind::Select,
+                options
+                    .iter()
+                    .map(|option| PreferenceOption {
+                        title: option.title.clone(),
+                        value: option.value.clone(),
+                    })
+                    .collect(),
+            ),
+            PreferenceField::Toggle { .. } => (PreferenceKind::Toggle, vec![]),
+            PreferenceField::Text { .. } => (PreferenceKind::Text, vec![]),
+        };
+
+        Self {
+            key: preference.key.clone(),
+            title: preference.title.clone(),
+            description: preference.description.clone(),
+            kind,
+            options,
+            default: preference.default_value(),
+            value: values.get(&preference.key).cloned(),
+            server_value: server_values.get(&preference.key).cloned(),
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(remote = "log::Level")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Line logged by an extension
+#[derive(Debug, SimpleObject)]
+pub struct ExtensionLog {
+    level: LogLevel,
+    message: String,
+    at: chrono::NaiveDateTime,
+}
+
+impl From<LogLine> for ExtensionLog {
+    fn from(line: LogLine) -> Self {
+        Self {
+            level: line.level.into(),
+            message: line.message,
+            at: chrono::DateTime::<chrono::Utc>::from(line.at).naive_utc(),
+        }
+    }
+}
+
+/// Reload of an extension after its wasm changed in `plugin_path`
+#[derive(Debug, SimpleObject)]
+pub struct ExtensionReload {
+    path: String,
+    source_id: Option<i64>,
+    source_name: Option<String>,
+    version: Option<String>,
+    /// Whether wasm is removed and its source unloaded
+    unloaded: bool,
+    error: Option<String>,
+    at: chrono::NaiveDateTime,
+}
+
+impl From<Reload> for ExtensionReload {
+    fn from(reload: Reload) -> Self {
+        let mut res = Self {
+            path: reload.path.display().to_string(),
+            source_id: None,
+            source_name: None,
+            version: None,
+            unloaded: false,
+            error: None,
+            at: chrono::DateTime::<chrono::Utc>::from(reload.at).naive_utc(),
+        };
+        match reload.result {
+            ReloadResult::Loaded(source) => {
+                res.source_id = Some(source.id);
+                res.source_name = Some(source.name);
+                res.version = Some(source.version.to_string());
+            }
+            ReloadResult::Unloaded(source_id) => {
+                res.source_id = Some(source_id);
+                res.unloaded = true;
+            }
+            ReloadResult::Failed(e) => {
+                res.error = Some(e);
+            }
+        }
+        res
+    }
+}
+
 #[derive(Clone)]
 pub struct Source {
     pub id: i64,
@@ -53,6 +247,21 @@ pub struct Source {
     pub icon: String,
     pub need_login: bool,
     pub has_update: bool,
+    pub repository: Option<String>,
+    /// Version of tanoshi-lib the source is built against, `None` if index doesn't tell
+    pub lib_version: Option<String>,
+    pub preferences: Vec<Preference>,
+}
+
+impl Source {
+    /// Reason source can't be run by this build, unknown `lib_version` is assumed compatible
+    pub fn incompatible_reason(&self) -> Option<String> {
+        let lib_version = self.lib_version.as_ref()?;
+        match Version::from_str(lib_version) {
+            Ok(lib_version) => lib_version.check_compatible(&tanoshi_lib::VERSION).err(),
+            Err(_) => Some(format!("invalid lib_version {}", lib_version)),
+        }
+    }
 }
 
 impl From<tanoshi_lib::data::Source> for Source {
@@ -64,6 +273,9 @@ impl From<tanoshi_lib::data::Source> for Source {
             icon: s.icon,
             need_login: s.need_login,
             has_update: false,
+            repository: None,
+            lib_version: Some(s.lib_version.to_string()),
+            preferences: s.preferences,
         }
     }
 }
@@ -85,18 +297,70 @@ impl Source {
     async fn need_login(&self) -> bool {
         self.need_login
     }
+    /// Whether requesting user has logged in to this source
+    async fn logged_in(&self, ctx: &Context<'_>) -> Result<bool> {
+        let user_id = user::get_claims(ctx)?.sub;
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        Ok(ctx
+            .userdb
+            .get_source_credential(user_id, self.id)
+            .await?
+            .is_some())
+    }
     async fn has_update(&self) -> bool {
         self.has_update
     }
+    /// Name of repository the source is available from
+    async fn repository(&self) -> Option<String> {
+        self.repository.clone()
+    }
+    async fn lib_version(&self) -> Option<String> {
+        self.lib_version.clone()
+    }
+    /// Whether source is built against a tanoshi-lib this server can run
+    async fn compatible(&self) -> bool {
+        self.incompatible_reason().is_none()
+    }
+    async fn incompatible_reason(&self) -> Option<String> {
+        self.incompatible_reason()
+    }
 
     async fn filters(&self, ctx: &Context<'_>) -> Result<Option<Filters>> {
         let extensions = ctx.data::<GlobalContext>()?.extensions.clone();
-        if let Some(res) = extensions.filters(self.id).await? {
+        if let Some(res) = extensions
+            .filters(self.id)
+            .await
+            .map_err(super::extension_error)?
+        {
             Ok(Some(res.into()))
         } else {
             Ok(None)
         }
     }
+
+    /// Settings declared by the source, available sources don't have any until installed
+    async fn preferences(&self, ctx: &Context<'_>) -> Result<Vec<SourcePreference>> {
+        let user_id = user::get_claims(ctx)?.sub;
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        let values = ctx
+            .userdb
+            .get_user_source_preferences(user_id, self.id)
+            .await?
+            .unwrap_or_default();
+        let server_values = ctx
+            .userdb
+            .get_source_preferences(self.id)
+            .await?
+            .unwrap_or_default();
+
+        Ok(self
+            .preferences
+            .iter()
+            .map(|preference| SourcePreference::new(preference, &values, &server_values))
+            .collect())
+    }
 }
 
 #[derive(Default)]
@@ -106,9 +370,11 @@ pub struct SourceRoot;
 impl SourceRoot {
     async fn installed_sources(&self, ctx: &Context<'_>) -> Result<Vec<Source>> {
         let available_sources_map = {
-            let url = "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repo/index.json"
-                .to_string();
-            let available_sources = reqwest::get(url).await?.json::<Vec<SourceIndex>>().await?;
+            let repositories = &ctx.data::<GlobalContext>()?.repositories;
+            let available_sources = repositories.index().await.unwrap_or_else(|e| {
+                error!("failed to read repositories: {}", e);
+                vec![]
+            });
             let mut available_sources_map = HashMap::new();
             for source in available_sources {
                 available_sources_map.insert(source.id, source);
@@ -118,14 +384,27 @@ impl SourceRoot {
 
         let sources = {
             let extensions = ctx.data::<GlobalContext>()?.extensions.clone();
-            let installed_sources = extensions.list().await?;
+            let installed_sources = extensions.list().await.map_err(super::extension_error)?;
 
             let mut sources: Vec<Source> = vec![];
             for source in installed_sources {
                 let mut source: Source = source.into();
                 if let Some(index) = available_sources_map.get(&source.id) {
-                    source.has_update =
-                        Version::from_str(&index.version)? > Version::from_str(&source.version)?;
+                    // one malformed version in a repository shouldn't fail the whole list
+                    source.has_update = match (
+                        Version::from_str(&index.version),
+                        Version::from_str(&source.version),
+                    ) {
+                        (Ok(available), Ok(installed)) => available > installed,
+                        (Err(e), _) | (_, Err(e)) => {
+                            error!(
+                                "can't compare version {:?} of source {} in {} with {:?}: {}",
+                                index.version, source.id, index.repository, source.version, e
+                            );
+                            false
+                        }
+                    };
+                    source.repository = Some(index.repository.clone());
                 }
                 sources.push(source);
             }
@@ -137,24 +416,76 @@ impl SourceRoot {
         Ok(sources)
     }
 
-    async fn available_sources(&self, ctx: &Context<'_>) -> Result<Vec<Source>> {
-        let url = "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repo/index.json"
-            .to_string();
-        let source_indexes = reqwest::get(url).await?.json::<Vec<SourceIndex>>().await?;
-        let extensions = ctx.data::<GlobalContext>()?.extensions.clone();
+    /// Sources not installed yet, those incompatible with this server are left out unless
+    /// `include_incompatible`
+    async fn available_sources(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = false)] include_incompatible: bool,
+    ) -> Result<Vec<Source>> {
+        let ctx = ctx.data::<GlobalContext>()?;
+        let source_indexes = ctx.repositories.index().await?;
+        let extensions = ctx.extensions.clone();
 
         let mut sources: Vec<Source> = vec![];
         for index in source_indexes {
-            if !extensions.exist(index.id).await? {
-                sources.push(index.into());
+            if !extensions
+                .exist(index.id)
+                .await
+                .map_err(super::extension_error)?
+            {
+                let source: Source = index.into();
+                if include_incompatible || source.incompatible_reason().is_none() {
+                    sources.push(source);
+                }
             }
         }
         Ok(sources)
     }
 
+    /// Latest extensions reloaded after their wasm changed, newest first
+    async fn extension_reloads(&self, ctx: &Context<'_>) -> Result<Vec<ExtensionReload>> {
+        if !user::check_is_admin(ctx)? {
+            return Err("Forbidden".into());
+        }
+
+        let ctx = ctx.data::<GlobalContext>()?;
+        Ok(ctx
+            .extension_reloads
+            .list()
+            .into_iter()
+            .map(ExtensionReload::from)
+            .collect())
+    }
+
+    /// Latest lines logged by an extension, newest first
+    async fn extension_logs(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "source id")] source_id: i64,
+        #[graphql(desc = "minimum level of lines", default_with = "LogLevel::Trace")]
+        level: LogLevel,
+    ) -> Result<Vec<ExtensionLog>> {
+        if !user::check_is_admin(ctx)? {
+            return Err("Forbidden".into());
+        }
+
+        let ctx = ctx.data::<GlobalContext>()?;
+        Ok(ctx
+            .extension_logs
+            .list(source_id, level.into())
+            .into_iter()
+            .map(ExtensionLog::from)
+            .collect())
+    }
+
     async fn source(&self, ctx: &Context<'_>, source_id: i64) -> Result<Source> {
         let exts = ctx.data::<GlobalContext>()?.extensions.clone();
-        Ok(exts.detail(source_id).await?.into())
+        Ok(exts
+            .detail(source_id)
+            .await
+            .map_err(super::extension_error)?
+            .into())
     }
 }
 
@@ -170,26 +501,23 @@ impl SourceMutationRoot {
 
         let ctx = ctx.data::<GlobalContext>()?;
         let extensions = ctx.extensions.clone();
-        if extensions.exist(source_id).await? {
+        if extensions
+            .exist(source_id)
+            .await
+            .map_err(super::extension_error)?
+        {
             return Err("source installed, use updateSource to update".into());
         }
 
-        let url = "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repo/index.json"
-            .to_string();
-        let source_indexes = reqwest::get(url).await?.json::<Vec<SourceIndex>>().await?;
-        let source: SourceIndex = source_indexes
-            .iter()
-            .find(|index| index.id == source_id)
-            .ok_or("source not found")?
-            .clone();
-
-        let url = format!(
-            "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repo/{}",
-            source.path,
-        );
-
-        let raw = reqwest::get(url).await?.bytes().await?;
-        extensions.install(source.name, &raw).await?;
+        let source = ctx.repositories.find(source_id).await?;
+        if let Some(reason) = Source::from(source.clone()).incompatible_reason() {
+            return Err(reason.into());
+        }
+        let raw = ctx.repositories.fetch(&source).await?;
+        extensions
+            .install(source.name, &raw)
+            .await
+            .map_err(super::extension_error)?;
 
         Ok(source.id)
     }
@@ -202,7 +530,10 @@ impl SourceMutationRoot {
         let ctx = ctx.data::<GlobalContext>()?;
         let extensions = ctx.extensions.clone();
 
-        extensions.unload(source_id).await?;
+        extensions
+            .unload(source_id)
+            .await
+            .map_err(super::extension_error)?;
 
         Ok(source_id)
     }
@@ -214,31 +545,147 @@ impl SourceMutationRoot {
 
         let ctx = ctx.data::<GlobalContext>()?;
         let extensions = ctx.extensions.clone();
-        extensions.exist(source_id).await?;
+        extensions
+            .exist(source_id)
+            .await
+            .map_err(super::extension_error)?;
+
+        let source = ctx.repositories.find(source_id).await?;
+
+        let installed = extensions
+            .detail(source_id)
+            .await
+            .map_err(super::extension_error)?;
+        if installed.version == Version::from_str(&source.version)? {
+            return Err("No new version".into());
+        }
+        // checked before the installed one is unloaded, so it is kept
+        if let Some(reason) = Source::from(source.clone()).incompatible_reason() {
+            return Err(reason.into());
+        }
+
+        let raw = ctx.repositories.fetch(&source).await?;
+
+        extensions
+            .unload(source_id)
+            .await
+            .map_err(super::extension_error)?;
+        extensions
+            .install(source.name, &raw)
+            .await
+            .map_err(super::extension_error)?;
+
+        Ok(source_id)
+    }
+
+    async fn login_source(
+        &self,
+        ctx: &Context<'_>,
+        source_id: i64,
+        username: String,
+        password: String,
+        two_factor: Option<String>,
+    ) -> Result<bool> {
+        let user_id = user::get_claims(ctx)?.sub;
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        let auth = ctx
+            .extensions
+            .login(
+                source_id,
+                SourceLogin {
+                    username: username.clone(),
+                    password,
+                    remember_me: Some(true),
+                    two_factor,
+                },
+                // cookies set on login are kept for this user only
+                Session {
+                    user_id: Some(user_id),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(super::extension_error)?;
+
+        let encrypted = utils::encrypt(&ctx.secret, ron::to_string(&auth)?.as_bytes())?;
+        ctx.userdb
+            .insert_source_credential(user_id, source_id, &username, &encrypted)
+            .await?;
+
+        Ok(true)
+    }
 
-        let url = "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repo/index.json"
-            .to_string();
+    /// Set preference declared by a source, `value` of null resets it. `server` sets it for
+    /// every user that hasn't set their own, which only admin can do
+    async fn set_source_preference(
+        &self,
+        ctx: &Context<'_>,
+        source_id: i64,
+        key: String,
+        value: Option<String>,
+        #[graphql(default = false)] server: bool,
+    ) -> Result<bool> {
+        let user_id = user::get_claims(ctx)?.sub;
+        if server && !user::check_is_admin(ctx)? {
+            return Err("Forbidden".into());
+        }
+        let ctx = ctx.data::<GlobalContext>()?;
 
-        let source_indexes = reqwest::get(url).await?.json::<Vec<SourceIndex>>().await?;
-        let source: SourceIndex = source_indexes
+        let source = ctx
+            .extensions
+            .detail(source_id)
+            .await
+            .map_err(super::extension_error)?;
+        let preference = source
+            .preferences
             .iter()
-            .find(|index| index.id == source_id)
-            .ok_or("source not found")?
-            .clone();
+            .find(|preference| preference.key == key)
+            .ok_or("source has no such preference")?;
+        if let Some(value) = value.as_ref() {
+            if !preference.is_valid(value) {
+                return Err(format!("invalid value for {}", preference.title).into());
+            }
+        }
 
-        if extensions.detail(source_id).await?.version == Version::from_str(&source.version)? {
-            return Err("No new version".into());
+        let mut preferences = if server {
+            ctx.userdb.get_source_preferences(source_id).await?
+        } else {
+            ctx.userdb
+                .get_user_source_preferences(user_id, source_id)
+                .await?
         }
+        .unwrap_or_default();
+        match value {
+            Some(value) => preferences.insert(key, value),
+            None => preferences.remove(&key),
+        };
 
-        let url = format!(
-            "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repo/{}",
-            source.path,
-        );
-        let raw = reqwest::get(url).await?.bytes().await?;
+        if server {
+            ctx.userdb
+                .insert_source_preferences(source_id, &preferences)
+                .await?;
+            ctx.extensions
+                .set_preferences(source_id, preferences)
+                .await
+                .map_err(super::extension_error)?;
+        } else {
+            ctx.userdb
+                .insert_user_source_preferences(user_id, source_id, &preferences)
+                .await?;
+        }
 
-        extensions.unload(source_id).await?;
-        extensions.install(source.name, &raw).await?;
+        Ok(true)
+    }
 
-        Ok(source_id)
+    async fn logout_source(&self, ctx: &Context<'_>, source_id: i64) -> Result<bool> {
+        let user_id = user::get_claims(ctx)?.sub;
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        Ok(ctx
+            .userdb
+            .delete_source_credential(user_id, source_id)
+            .await?
+            > 0)
     }
 }
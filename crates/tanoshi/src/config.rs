@@ -2,7 +2,12 @@ use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
-use std::{iter, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+    path::PathBuf,
+};
+use tanoshi_vm::{bus::Timeouts, limits::Limits};
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct TelegramConfig {
@@ -10,6 +15,40 @@ pub struct TelegramConfig {
     pub token: String,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RepositoryConfig {
+    pub name: String,
+    pub url: String,
+    /// Index of signed repository lists checksum of every extension, extension without one is
+    /// refused. Checksum is only checked if present otherwise
+    #[serde(default)]
+    pub signed: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DiscordConfig {
+    #[serde(default = "default_discord_username")]
+    pub username: String,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WebhookConfig {
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    #[serde(default)]
+    pub starttls: bool,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     #[serde(skip)]
@@ -26,9 +65,27 @@ pub struct Config {
     pub plugin_path: String,
     #[serde(default = "default_local_path")]
     pub local_path: String,
+    #[serde(default = "default_download_path")]
+    pub download_path: String,
+    #[serde(default = "default_repositories")]
+    pub repositories: Vec<RepositoryConfig>,
+    #[serde(default)]
+    pub trusted_keys: Vec<String>,
+    #[serde(default)]
+    pub extension_limits: Limits,
+    #[serde(default)]
+    pub extension_timeouts: Timeouts,
+    #[serde(default = "default_image_cache_path")]
+    pub image_cache_path: String,
+    /// Maximum size of image cache in megabytes, 0 disable the cache
+    #[serde(default = "default_image_cache_size")]
+    pub image_cache_size: u64,
     #[serde(default)]
     pub enable_playground: bool,
     pub telegram: Option<TelegramConfig>,
+    pub discord: Option<DiscordConfig>,
+    pub webhook: Option<WebhookConfig>,
+    pub smtp: Option<SmtpConfig>,
 }
 
 impl Default for Config {
@@ -41,8 +98,18 @@ impl Default for Config {
             update_interval: default_update_interval(),
             plugin_path: default_plugin_path(),
             local_path: default_local_path(),
+            download_path: default_download_path(),
+            repositories: default_repositories(),
+            trusted_keys: vec![],
+            extension_limits: Limits::default(),
+            extension_timeouts: Timeouts::default(),
+            image_cache_path: default_image_cache_path(),
+            image_cache_size: default_image_cache_size(),
             enable_playground: false,
             telegram: None,
+            discord: None,
+            webhook: None,
+            smtp: None,
         }
     }
 }
@@ -62,6 +129,10 @@ fn default_update_interval() -> u64 {
     3600
 }
 
+fn default_discord_username() -> String {
+    "Tanoshi".to_string()
+}
+
 fn default_secret() -> String {
     let mut rng = thread_rng();
     let chars = iter::repeat(())
@@ -95,6 +166,49 @@ fn default_local_path() -> String {
     path.to_str().unwrap().to_string()
 }
 
+fn default_download_path() -> String {
+    let path = tanoshi_home().join("downloads");
+    if !path.exists() {
+        let _ = std::fs::create_dir_all(&path);
+    }
+    path.to_str().unwrap().to_string()
+}
+
+fn default_image_cache_path() -> String {
+    let path = tanoshi_home().join("cache");
+    if !path.exists() {
+        let _ = std::fs::create_dir_all(&path);
+    }
+    path.to_str().unwrap().to_string()
+}
+
+fn default_image_cache_size() -> u64 {
+    1024
+}
+
+fn default_repositories() -> Vec<RepositoryConfig> {
+    vec![RepositoryConfig {
+        name: "official".to_string(),
+        url: "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repo".to_string(),
+        signed: false,
+    }]
+}
+
+/// Source is fetched from the repository it is listed in by name, so names must be unique
+fn validate_repositories(repositories: &[RepositoryConfig]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for repository in repositories {
+        if !names.insert(repository.name.as_str()) {
+            return Err(format!(
+                "repository {} is listed more than once",
+                repository.name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 impl Config {
     pub fn open<P: AsRef<Path>>(path: Option<P>) -> Result<Config, Box<dyn std::error::Error>> {
         let config_path = match path {
@@ -106,6 +220,7 @@ impl Config {
             Ok(file) => {
                 info!("Open config from {:?}", config_path);
                 let mut cfg: Self = serde_yaml::from_reader(file)?;
+                validate_repositories(&cfg.repositories)?;
                 cfg.path = config_path;
                 Ok(cfg)
             }
@@ -127,3 +242,25 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn repository(name: &str) -> RepositoryConfig {
+        RepositoryConfig {
+            name: name.to_string(),
+            url: format!("/tmp/{}", name),
+            signed: false,
+        }
+    }
+
+    #[test]
+    fn test_validate_repositories() {
+        let mut repositories = vec![repository("official"), repository("private")];
+        assert!(validate_repositories(&repositories).is_ok());
+
+        repositories.push(repository("private"));
+        assert!(validate_repositories(&repositories).is_err());
+    }
+}
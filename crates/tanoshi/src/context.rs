@@ -1,7 +1,11 @@
 use crate::db::{MangaDatabase, UserDatabase};
+use crate::notifier::Notifiers;
+use crate::proxy::ImageCache;
+use crate::repository::Repositories;
+use crate::subscription::Event;
 use crate::worker::Command as WorkerCommand;
-use tanoshi_vm::bus::ExtensionBus;
-use tokio::sync::mpsc::UnboundedSender;
+use tanoshi_vm::{bus::ExtensionBus, logs::ExtensionLogs, watcher::ReloadLog};
+use tokio::sync::{broadcast, mpsc::UnboundedSender};
 
 pub struct GlobalContext {
     pub userdb: UserDatabase,
@@ -9,6 +13,12 @@ pub struct GlobalContext {
     pub mangadb: MangaDatabase,
     pub extensions: ExtensionBus,
     pub worker_tx: UnboundedSender<WorkerCommand>,
+    pub event_tx: broadcast::Sender<Event>,
+    pub notifiers: Notifiers,
+    pub repositories: Repositories,
+    pub image_cache: ImageCache,
+    pub extension_reloads: ReloadLog,
+    pub extension_logs: ExtensionLogs,
 }
 
 impl GlobalContext {
@@ -18,6 +28,12 @@ impl GlobalContext {
         secret: String,
         extensions: ExtensionBus,
         worker_tx: UnboundedSender<WorkerCommand>,
+        event_tx: broadcast::Sender<Event>,
+        notifiers: Notifiers,
+        repositories: Repositories,
+        image_cache: ImageCache,
+        extension_reloads: ReloadLog,
+        extension_logs: ExtensionLogs,
     ) -> Self {
         Self {
             userdb,
@@ -25,6 +41,12 @@ impl GlobalContext {
             mangadb,
             extensions,
             worker_tx,
+            event_tx,
+            notifiers,
+            repositories,
+            image_cache,
+            extension_reloads,
+            extension_logs,
         }
     }
 }
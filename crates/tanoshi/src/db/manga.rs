@@ -1,4 +1,4 @@
-use super::model::{Chapter, Manga, ReadProgress};
+use super::model::{Chapter, Manga, Page, ReadProgress};
 use crate::library::{RecentChapter, RecentUpdate};
 use anyhow::{anyhow, Result};
 use sqlx::sqlite::{SqliteArguments, SqlitePool};
@@ -83,11 +83,10 @@ impl Db {
         Ok(mangas)
     }
 
-    pub async fn get_all_user_library(&self) -> Result<Vec<(Option<i64>, Manga)>> {
+    pub async fn get_all_user_library(&self) -> Result<Vec<(i64, Manga)>> {
         let mut stream = sqlx::query(
-            r#"SELECT manga.*, user.telegram_chat_id FROM manga
-            JOIN user_library ON user_library.manga_id = manga.id
-            JOIN user ON user.id = user_library.user_id"#,
+            r#"SELECT manga.*, user_library.user_id FROM manga
+            JOIN user_library ON user_library.manga_id = manga.id"#,
         )
         .fetch(&self.pool);
 
@@ -710,7 +709,7 @@ impl Db {
         let stream = sqlx::query(
             r#"
             SELECT *,
-            (SELECT JSON_GROUP_ARRAY(remote_url) FROM page WHERE chapter_id = chapter.id) pages,
+            (SELECT JSON_GROUP_ARRAY(COALESCE(local_url, remote_url)) FROM page WHERE chapter_id = chapter.id) pages,
             (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number < chapter.number ORDER BY c.number DESC LIMIT 1) prev,
             (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number > chapter.number ORDER BY c.number ASC LIMIT 1) next
             FROM chapter WHERE id = ?"#,
@@ -729,9 +728,10 @@ impl Db {
             scanlator: row.get(6),
             uploaded: row.get(7),
             date_added: row.get(8),
-            pages: serde_json::from_str(row.get(9)).unwrap_or_default(),
-            prev: row.get(10),
-            next: row.get(11),
+            volume: row.get(9),
+            pages: serde_json::from_str(row.get(10)).unwrap_or_default(),
+            prev: row.get(11),
+            next: row.get(12),
         })?)
     }
 
@@ -802,7 +802,7 @@ impl Db {
             )
             SELECT
                 chapter.*,
-                (SELECT JSON_GROUP_ARRAY(remote_url) FROM page WHERE page.chapter_id = chapter.id) pages,
+                (SELECT JSON_GROUP_ARRAY(COALESCE(local_url, remote_url)) FROM page WHERE page.chapter_id = chapter.id) pages,
                 (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number < chapter.number ORDER BY c.number DESC LIMIT 1) prev,
                 (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number > chapter.number ORDER BY c.number ASC LIMIT 1) next
             FROM
@@ -834,18 +834,18 @@ impl Db {
             scanlator: row.get(6),
             uploaded: row.get(7),
             date_added: row.get(8),
-            pages: serde_json::from_str(row.get(9)).unwrap_or_default(),
-            prev: row.get(10),
-            next: row.get(11),
+            volume: row.get(9),
+            pages: serde_json::from_str(row.get(10)).unwrap_or_default(),
+            prev: row.get(11),
+            next: row.get(12),
         }))
     }
 
-    #[allow(dead_code)]
     pub async fn get_chapter_by_source_path(&self, source_id: i64, path: &str) -> Option<Chapter> {
         let stream = sqlx::query(
             r#"
             SELECT *,
-            (SELECT JSON_GROUP_ARRAY(remote_url) FROM page WHERE chapter_id = chapter.id) pages,
+            (SELECT JSON_GROUP_ARRAY(COALESCE(local_url, remote_url)) FROM page WHERE chapter_id = chapter.id) pages,
             (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number < chapter.number ORDER BY c.number DESC LIMIT 1) prev,
             (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number > chapter.number ORDER BY c.number ASC LIMIT 1) next
             FROM chapter WHERE source_id = ? AND path = ?"#,
@@ -866,9 +866,10 @@ impl Db {
             scanlator: row.get(6),
             uploaded: row.get(7),
             date_added: row.get(8),
-            pages: serde_json::from_str(row.get(9)).unwrap_or_default(),
-            prev: row.get(10),
-            next: row.get(11),
+            volume: row.get(9),
+            pages: serde_json::from_str(row.get(10)).unwrap_or_default(),
+            prev: row.get(11),
+            next: row.get(12),
         })
     }
 
@@ -876,7 +877,7 @@ impl Db {
         let mut stream = sqlx::query(
             r#"
             SELECT *,
-            (SELECT JSON_GROUP_ARRAY(remote_url) FROM page WHERE chapter_id = chapter.id) pages,
+            (SELECT JSON_GROUP_ARRAY(COALESCE(local_url, remote_url)) FROM page WHERE chapter_id = chapter.id) pages,
             (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number < chapter.number ORDER BY c.number DESC LIMIT 1) prev,
             (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number > chapter.number ORDER BY c.number ASC LIMIT 1) next
             FROM chapter WHERE manga_id = ? ORDER BY number DESC"#
@@ -896,9 +897,10 @@ impl Db {
                 scanlator: row.get(6),
                 uploaded: row.get(7),
                 date_added: row.get(8),
-                pages: serde_json::from_str(row.get(9)).unwrap_or_default(),
-                prev: row.get(10),
-                next: row.get(11),
+                volume: row.get(9),
+                pages: serde_json::from_str(row.get(10)).unwrap_or_default(),
+                prev: row.get(11),
+                next: row.get(12),
             });
         }
         if chapters.is_empty() {
@@ -912,7 +914,7 @@ impl Db {
         let stream = sqlx::query(
             r#"
             SELECT *,
-            (SELECT JSON_GROUP_ARRAY(remote_url) FROM page WHERE chapter_id = chapter.id) pages,
+            (SELECT JSON_GROUP_ARRAY(COALESCE(local_url, remote_url)) FROM page WHERE chapter_id = chapter.id) pages,
             (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number < chapter.number ORDER BY c.number DESC LIMIT 1) prev,
             (SELECT c.id FROM chapter c WHERE c.manga_id = chapter.manga_id AND c.number > chapter.number ORDER BY c.number ASC LIMIT 1) next
             FROM chapter WHERE manga_id = ? ORDER BY uploaded DESC LIMIT 1"#
@@ -932,9 +934,10 @@ impl Db {
             scanlator: row.get(6),
             uploaded: row.get(7),
             date_added: row.get(8),
-            pages: serde_json::from_str(row.get(9)).unwrap_or_default(),
-            prev: row.get(10),
-            next: row.get(11),
+            volume: row.get(9),
+            pages: serde_json::from_str(row.get(10)).unwrap_or_default(),
+            prev: row.get(11),
+            next: row.get(12),
         })
     }
 
@@ -947,16 +950,18 @@ impl Db {
                 title,
                 path,
                 number,
+                volume,
                 scanlator,
                 uploaded,
                 date_added
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
         )
         .bind(chapter.source_id)
         .bind(chapter.manga_id)
         .bind(&chapter.title)
         .bind(&chapter.path)
         .bind(chapter.number)
+        .bind(chapter.volume)
         .bind(&chapter.scanlator)
         .bind(chapter.uploaded)
         .bind(chrono::NaiveDateTime::from_timestamp(
@@ -976,7 +981,7 @@ impl Db {
         }
 
         let mut values = vec![];
-        values.resize(chapters.len(), "(?, ?, ?, ?, ?, ?, ?, ?)");
+        values.resize(chapters.len(), "(?, ?, ?, ?, ?, ?, ?, ?, ?)");
 
         let query_str = format!(
             r#"INSERT INTO chapter(
@@ -985,6 +990,7 @@ impl Db {
             title,
             path,
             number,
+            volume,
             scanlator,
             uploaded,
             date_added
@@ -992,6 +998,7 @@ impl Db {
             manga_id=excluded.manga_id,
             title=excluded.title,
             number=excluded.number,
+            volume=excluded.volume,
             scanlator=excluded.scanlator,
             uploaded=excluded.uploaded,
             date_added=excluded.date_added
@@ -1007,6 +1014,7 @@ impl Db {
                 .bind(&chapter.title)
                 .bind(&chapter.path)
                 .bind(chapter.number)
+                .bind(chapter.volume)
                 .bind(&chapter.scanlator)
                 .bind(chapter.uploaded)
                 .bind(chrono::NaiveDateTime::from_timestamp(
@@ -1034,7 +1042,11 @@ impl Db {
                 rank,
                 remote_url
             ) VALUES {} ON CONFLICT(chapter_id, rank) DO UPDATE SET
-                remote_url=excluded.remote_url
+                remote_url=excluded.remote_url,
+                local_url=CASE
+                    WHEN excluded.remote_url = page.remote_url THEN page.local_url
+                    ELSE NULL
+                END
             "#,
             values.join(",")
         );
@@ -1049,6 +1061,43 @@ impl Db {
         Ok(())
     }
 
+    pub async fn get_pages_by_chapter_id(&self, chapter_id: i64) -> Result<Vec<Page>> {
+        let mut stream = sqlx::query(
+            r#"SELECT chapter_id, rank, remote_url, local_url FROM page
+            WHERE chapter_id = ?
+            ORDER BY rank"#,
+        )
+        .bind(chapter_id)
+        .fetch(&self.pool);
+
+        let mut pages = vec![];
+        while let Some(row) = stream.try_next().await? {
+            pages.push(Page {
+                chapter_id: row.get(0),
+                rank: row.get(1),
+                remote_url: row.get(2),
+                local_url: row.get(3),
+            });
+        }
+        Ok(pages)
+    }
+
+    pub async fn update_page_local_url(
+        &self,
+        chapter_id: i64,
+        rank: i64,
+        local_url: &str,
+    ) -> Result<u64> {
+        sqlx::query(r#"UPDATE page SET local_url = ? WHERE chapter_id = ? AND rank = ?"#)
+            .bind(local_url)
+            .bind(chapter_id)
+            .bind(rank)
+            .execute(&self.pool)
+            .await
+            .map(|res| res.rows_affected())
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
     pub async fn insert_user_library(&self, user_id: i64, manga_id: i64) -> Result<u64> {
         sqlx::query("INSERT INTO user_library (user_id, manga_id) VALUES (?, ?)")
             .bind(user_id)
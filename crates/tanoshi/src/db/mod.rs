@@ -10,6 +10,9 @@ pub use manga::Db as MangaDatabase;
 mod user;
 pub use user::Db as UserDatabase;
 
+mod storage;
+pub use storage::Db as ExtensionStorage;
+
 pub mod model;
 
 pub async fn establish_connection(
@@ -1,5 +1,7 @@
 use chrono::NaiveDateTime;
 
+use crate::notifier::NotifierKind;
+
 #[derive(Debug, Clone)]
 pub struct Manga {
     pub id: i64,
@@ -39,6 +41,7 @@ pub struct Chapter {
     pub title: String,
     pub path: String,
     pub number: f64,
+    pub volume: Option<i64>,
     pub scanlator: String,
     pub prev: Option<i64>,
     pub next: Option<i64>,
@@ -56,6 +59,7 @@ impl Default for Chapter {
             title: "".to_string(),
             path: "".to_string(),
             number: 0_f64,
+            volume: None,
             scanlator: "".to_string(),
             prev: None,
             next: None,
@@ -66,6 +70,14 @@ impl Default for Chapter {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub chapter_id: i64,
+    pub rank: i64,
+    pub remote_url: String,
+    pub local_url: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct User {
     pub id: i64,
@@ -75,6 +87,10 @@ pub struct User {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub telegram_chat_id: Option<i64>,
+    pub notifier: Option<NotifierKind>,
+    pub discord_webhook: Option<String>,
+    pub webhook_url: Option<String>,
+    pub email: Option<String>,
 }
 
 impl Default for User {
@@ -87,6 +103,10 @@ impl Default for User {
             created_at: NaiveDateTime::from_timestamp(0, 0),
             updated_at: NaiveDateTime::from_timestamp(0, 0),
             telegram_chat_id: None,
+            notifier: None,
+            discord_webhook: None,
+            webhook_url: None,
+            email: None,
         }
     }
 }
@@ -0,0 +1,145 @@
+use std::{
+    future::Future,
+    sync::{mpsc, Arc, Mutex},
+};
+
+use anyhow::Result;
+use sqlx::{sqlite::SqlitePool, Row};
+use tanoshi_vm::prelude::Storage;
+use tokio::runtime::{Builder, Runtime};
+
+type Job = Box<dyn FnOnce(&Runtime) + Send>;
+
+/// Key-value storage of extensions, rows are kept when extension is uninstalled
+#[derive(Debug, Clone)]
+pub struct Db {
+    pool: SqlitePool,
+    /// Queries of host functions, run on a thread of their own
+    jobs: Arc<Mutex<mpsc::Sender<Job>>>,
+}
+
+impl Db {
+    pub fn new(pool: SqlitePool) -> Db {
+        let (tx, rx) = mpsc::channel::<Job>();
+        std::thread::spawn(move || {
+            // pool returns connection in a spawned task, so runtime runs tasks on its own
+            let runtime = match Builder::new_multi_thread()
+                .worker_threads(1)
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    error!("error start extension storage runtime: {}", e);
+                    return;
+                }
+            };
+            for job in rx {
+                job(&runtime);
+            }
+        });
+
+        Db {
+            pool,
+            jobs: Arc::new(Mutex::new(tx)),
+        }
+    }
+
+    /// Run query on storage thread and wait for its result. Host functions may be called
+    /// on a thread of the main runtime, e.g. extension calls `detail` while it is instantiated,
+    /// where blocking on that runtime panics
+    fn block_on<T, F>(&self, query: impl FnOnce(Db) -> F + Send + 'static) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: Future<Output = Result<T>>,
+    {
+        let (tx, rx) = mpsc::channel();
+        let db = self.clone();
+        let job: Job = Box::new(move |runtime| {
+            let _ = tx.send(runtime.block_on(query(db)).map_err(|e| e.to_string()));
+        });
+        self.jobs
+            .lock()
+            .map_err(|e| e.to_string())?
+            .send(job)
+            .map_err(|_| "storage thread stopped".to_string())?;
+
+        rx.recv()
+            .map_err(|_| "storage thread stopped".to_string())?
+    }
+
+    pub async fn get_value(&self, source_id: i64, key: &str) -> Result<Option<String>> {
+        let row =
+            sqlx::query(r#"SELECT value FROM extension_storage WHERE source_id = ? AND key = ?"#)
+                .bind(source_id)
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    pub async fn insert_value(&self, source_id: i64, key: &str, value: &str) -> Result<u64> {
+        let rows_affected = sqlx::query(
+            r#"INSERT OR REPLACE INTO extension_storage(
+                source_id,
+                key,
+                value,
+                updated_at
+            ) VALUES (?, ?, ?, CURRENT_TIMESTAMP)"#,
+        )
+        .bind(source_id)
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected)
+    }
+
+    pub async fn delete_value(&self, source_id: i64, key: &str) -> Result<u64> {
+        let rows_affected =
+            sqlx::query(r#"DELETE FROM extension_storage WHERE source_id = ? AND key = ?"#)
+                .bind(source_id)
+                .bind(key)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+
+        Ok(rows_affected)
+    }
+
+    pub async fn count_values(&self, source_id: i64) -> Result<i64> {
+        let row = sqlx::query(r#"SELECT COUNT(1) FROM extension_storage WHERE source_id = ?"#)
+            .bind(source_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get(0))
+    }
+}
+
+impl Storage for Db {
+    fn get(&self, source_id: i64, key: &str) -> Result<Option<String>, String> {
+        let key = key.to_string();
+        self.block_on(move |db| async move { db.get_value(source_id, &key).await })
+    }
+
+    fn set(&self, source_id: i64, key: &str, value: &str) -> Result<(), String> {
+        let (key, value) = (key.to_string(), value.to_string());
+        self.block_on(move |db| async move { db.insert_value(source_id, &key, &value).await })
+            .map(|_| ())
+    }
+
+    fn delete(&self, source_id: i64, key: &str) -> Result<(), String> {
+        let key = key.to_string();
+        self.block_on(move |db| async move { db.delete_value(source_id, &key).await })
+            .map(|_| ())
+    }
+
+    fn count(&self, source_id: i64) -> Result<usize, String> {
+        self.block_on(move |db| async move { db.count_values(source_id).await })
+            .map(|count| count as usize)
+    }
+}
@@ -4,6 +4,8 @@ use sqlx::{
     sqlite::{SqliteArguments, SqlitePool},
     Arguments, Row,
 };
+use std::collections::BTreeMap;
+use tanoshi_lib::prelude::Preferences;
 use tokio_stream::StreamExt;
 
 #[derive(Debug, Clone)]
@@ -78,6 +80,12 @@ impl Db {
                 created_at: row.get(4),
                 updated_at: row.get(5),
                 telegram_chat_id: row.get(6),
+                notifier: row
+                    .get::<Option<String>, _>(7)
+                    .and_then(|kind| kind.parse().ok()),
+                discord_webhook: row.get(8),
+                webhook_url: row.get(9),
+                email: row.get(10),
             })
         }
 
@@ -106,6 +114,12 @@ impl Db {
                 created_at: row.get(4),
                 updated_at: row.get(5),
                 telegram_chat_id: row.get(6),
+                notifier: row
+                    .get::<Option<String>, _>(7)
+                    .and_then(|kind| kind.parse().ok()),
+                discord_webhook: row.get(8),
+                webhook_url: row.get(9),
+                email: row.get(10),
             });
         }
         Ok(users)
@@ -125,6 +139,12 @@ impl Db {
             created_at: row.get(4),
             updated_at: row.get(5),
             telegram_chat_id: row.get(6),
+            notifier: row
+                .get::<Option<String>, _>(7)
+                .and_then(|kind| kind.parse().ok()),
+            discord_webhook: row.get(8),
+            webhook_url: row.get(9),
+            email: row.get(10),
         })?)
     }
 
@@ -142,6 +162,12 @@ impl Db {
             created_at: row.get(4),
             updated_at: row.get(5),
             telegram_chat_id: row.get(6),
+            notifier: row
+                .get::<Option<String>, _>(7)
+                .and_then(|kind| kind.parse().ok()),
+            discord_webhook: row.get(8),
+            webhook_url: row.get(9),
+            email: row.get(10),
         })?)
     }
 
@@ -151,6 +177,14 @@ impl Db {
 
         column_to_update.push("telegram_chat_id = ?");
         arguments.add(user.telegram_chat_id);
+        column_to_update.push("notifier = ?");
+        arguments.add(user.notifier.map(|kind| kind.to_string()));
+        column_to_update.push("discord_webhook = ?");
+        arguments.add(user.discord_webhook.clone());
+        column_to_update.push("webhook_url = ?");
+        arguments.add(user.webhook_url.clone());
+        column_to_update.push("email = ?");
+        arguments.add(user.email.clone());
         arguments.add(user.id);
 
         if column_to_update.is_empty() {
@@ -171,4 +205,151 @@ impl Db {
 
         Ok(rows_affected)
     }
+
+    /// `auth` is encrypted by caller, database only store it as is
+    pub async fn insert_source_credential(
+        &self,
+        user_id: i64,
+        source_id: i64,
+        username: &str,
+        auth: &str,
+    ) -> Result<u64> {
+        let rows_affected = sqlx::query(
+            r#"INSERT OR REPLACE INTO source_credential(
+                user_id,
+                source_id,
+                username,
+                auth,
+                updated_at
+            ) VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)"#,
+        )
+        .bind(user_id)
+        .bind(source_id)
+        .bind(username)
+        .bind(auth)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected)
+    }
+
+    pub async fn get_source_credential(
+        &self,
+        user_id: i64,
+        source_id: i64,
+    ) -> Result<Option<String>> {
+        let row = sqlx::query(
+            r#"SELECT auth FROM source_credential WHERE user_id = ? AND source_id = ?"#,
+        )
+        .bind(user_id)
+        .bind(source_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    pub async fn delete_source_credential(&self, user_id: i64, source_id: i64) -> Result<u64> {
+        let rows_affected =
+            sqlx::query(r#"DELETE FROM source_credential WHERE user_id = ? AND source_id = ?"#)
+                .bind(user_id)
+                .bind(source_id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+
+        Ok(rows_affected)
+    }
+
+    /// Preferences of a source set for the whole server, `None` if admin hasn't set any
+    pub async fn get_source_preferences(&self, source_id: i64) -> Result<Option<Preferences>> {
+        let row = sqlx::query(r#"SELECT preferences FROM source_preference WHERE source_id = ?"#)
+            .bind(source_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(row.get(0))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Preferences set for the whole server of every source, by source id
+    pub async fn get_all_source_preferences(&self) -> Result<BTreeMap<i64, Preferences>> {
+        let mut stream = sqlx::query(r#"SELECT source_id, preferences FROM source_preference"#)
+            .fetch(&self.pool);
+
+        let mut preferences = BTreeMap::new();
+        while let Some(row) = stream.try_next().await? {
+            preferences.insert(row.get(0), serde_json::from_str(row.get(1))?);
+        }
+
+        Ok(preferences)
+    }
+
+    pub async fn insert_source_preferences(
+        &self,
+        source_id: i64,
+        preferences: &Preferences,
+    ) -> Result<u64> {
+        let rows_affected = sqlx::query(
+            r#"INSERT OR REPLACE INTO source_preference(
+                source_id,
+                preferences,
+                updated_at
+            ) VALUES (?, ?, CURRENT_TIMESTAMP)"#,
+        )
+        .bind(source_id)
+        .bind(serde_json::to_string(preferences)?)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected)
+    }
+
+    /// Preferences of a source set by user, `None` if user hasn't set any
+    pub async fn get_user_source_preferences(
+        &self,
+        user_id: i64,
+        source_id: i64,
+    ) -> Result<Option<Preferences>> {
+        let row = sqlx::query(
+            r#"SELECT preferences FROM user_source_preference WHERE user_id = ? AND source_id = ?"#,
+        )
+        .bind(user_id)
+        .bind(source_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(row.get(0))?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn insert_user_source_preferences(
+        &self,
+        user_id: i64,
+        source_id: i64,
+        preferences: &Preferences,
+    ) -> Result<u64> {
+        let rows_affected = sqlx::query(
+            r#"INSERT OR REPLACE INTO user_source_preference(
+                user_id,
+                source_id,
+                preferences,
+                updated_at
+            ) VALUES (?, ?, ?, CURRENT_TIMESTAMP)"#,
+        )
+        .bind(user_id)
+        .bind(source_id)
+        .bind(serde_json::to_string(preferences)?)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected)
+    }
 }
@@ -1,6 +1,8 @@
-use crate::catalogue::Manga;
+use crate::catalogue::{self, Manga};
 use crate::context::GlobalContext;
+use crate::subscription::{Event, LibraryAction, LibraryChange};
 use crate::user;
+use crate::worker::Command as WorkerCommand;
 use async_graphql::connection::{query, Connection, Edge, EmptyFields};
 use async_graphql::{Context, Object, Result};
 use chrono::{Local, NaiveDateTime};
@@ -25,11 +27,23 @@ impl LibraryRoot {
         if refresh {
             let db = &ctx.mangadb;
             for favorite_manga in manga.iter() {
+                let session = catalogue::get_source_session(
+                    &ctx.userdb,
+                    &ctx.secret,
+                    user.sub,
+                    favorite_manga.source_id,
+                )
+                .await;
                 let mut m: crate::db::model::Manga = {
                     let extensions = ctx.extensions.clone();
                     extensions
-                        .get_manga_info(favorite_manga.source_id, favorite_manga.path.clone())
-                        .await?
+                        .get_manga_info(
+                            favorite_manga.source_id,
+                            favorite_manga.path.clone(),
+                            session,
+                        )
+                        .await
+                        .map_err(catalogue::extension_error)?
                         .into()
                 };
 
@@ -245,13 +259,16 @@ impl LibraryMutationRoot {
         #[graphql(desc = "manga id")] manga_id: i64,
     ) -> Result<u64> {
         let user = user::get_claims(ctx)?;
-        match ctx
-            .data_unchecked::<GlobalContext>()
-            .mangadb
-            .insert_user_library(user.sub, manga_id)
-            .await
-        {
-            Ok(rows) => Ok(rows),
+        let ctx = ctx.data_unchecked::<GlobalContext>();
+        match ctx.mangadb.insert_user_library(user.sub, manga_id).await {
+            Ok(rows) => {
+                let _ = ctx.event_tx.send(Event::LibraryChange(LibraryChange {
+                    user_id: user.sub,
+                    manga_id,
+                    action: LibraryAction::Added,
+                }));
+                Ok(rows)
+            }
             Err(err) => Err(format!("error add manga to library: {}", err).into()),
         }
     }
@@ -262,13 +279,16 @@ impl LibraryMutationRoot {
         #[graphql(desc = "manga id")] manga_id: i64,
     ) -> Result<u64> {
         let user = user::get_claims(ctx)?;
-        match ctx
-            .data_unchecked::<GlobalContext>()
-            .mangadb
-            .delete_user_library(user.sub, manga_id)
-            .await
-        {
-            Ok(rows) => Ok(rows),
+        let ctx = ctx.data_unchecked::<GlobalContext>();
+        match ctx.mangadb.delete_user_library(user.sub, manga_id).await {
+            Ok(rows) => {
+                let _ = ctx.event_tx.send(Event::LibraryChange(LibraryChange {
+                    user_id: user.sub,
+                    manga_id,
+                    action: LibraryAction::Removed,
+                }));
+                Ok(rows)
+            }
             Err(err) => Err(format!("error delete manga from library: {}", err).into()),
         }
     }
@@ -324,4 +344,18 @@ impl LibraryMutationRoot {
             Err(err) => Err(format!("error delete chapter read_at: {}", err).into()),
         }
     }
+
+    async fn download_chapters(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "chapter ids")] ids: Vec<i64>,
+    ) -> Result<u64> {
+        let user = user::get_claims(ctx)?;
+        let len = ids.len() as u64;
+        ctx.data::<GlobalContext>()?
+            .worker_tx
+            .send(WorkerCommand::DownloadChapters(user.sub, ids))?;
+
+        Ok(len)
+    }
 }
@@ -55,6 +55,7 @@ impl RecentChapter {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct RecentUpdate {
     pub manga_id: i64,
     pub chapter_id: i64,
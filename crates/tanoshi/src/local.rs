@@ -1,22 +1,87 @@
 use std::{
     ffi::OsStr,
-    fs::{DirEntry, ReadDir},
+    fs::DirEntry,
     path::{Path, PathBuf},
     time::UNIX_EPOCH,
 };
 
 use chrono::NaiveDateTime;
-use fancy_regex::Regex;
 use tanoshi_lib::prelude::{Chapter, Extension, ExtensionResult, Filters, Manga, Source, Version};
 
+mod epub;
+mod filename;
+mod metadata;
+mod pdf;
+use metadata::{ComicInfo, SeriesInfo};
+
 pub static ID: i64 = 1;
 // list of supported files, other archive may works but no tested
 static SUPPORTED_FILES: phf::Set<&'static str> = phf::phf_set! {
     "cbz",
     "cbr",
+    "cb7",
+    "cbt",
+    "pdf",
+    "epub",
+};
+
+/// Extensions of image listed as pages, anything else in a chapter is skipped
+static IMAGE_FILES: phf::Set<&'static str> = phf::phf_set! {
+    "jpg",
+    "jpeg",
+    "png",
+    "webp",
+    "gif",
+    "bmp",
 };
 
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .map(|ext| ext.to_lowercase())
+}
+
+/// Whether file in a chapter is a page, skipping `ComicInfo.xml`, `Thumbs.db` and such,
+/// as well as `__MACOSX` folder and `._` files macOS leaves in archives
+fn is_image(path: &Path) -> bool {
+    let is_junk = path.components().any(|component| {
+        component
+            .as_os_str()
+            .to_str()
+            .map_or(false, |name| name == "__MACOSX" || name.starts_with("._"))
+    });
+    !is_junk && extension(path).map_or(false, |ext| IMAGE_FILES.contains(ext.as_str()))
+}
+
+/// Content type and bytes of a page returned by `get_pages` which isn't a plain file,
+/// i.e. path of an archive or document joined with the page inside it
+pub fn read_page(page: &Path) -> Result<(String, Vec<u8>), Box<dyn std::error::Error>> {
+    let file = page
+        .ancestors()
+        .skip(1)
+        .find(|path| path.is_file())
+        .ok_or("page is not in an archive")?;
+    let name = page
+        .strip_prefix(file)?
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    if extension(file).as_deref() == Some("pdf") {
+        return pdf::page_image(file, name.parse()?);
+    }
+
+    let filename = file.to_str().ok_or("invalid path")?;
+    let bytes = libarchive_rs::extract_archive_file(filename, &name)?;
+    let content_type = mime_guess::from_path(&name)
+        .first_or_octet_stream()
+        .to_string();
+    Ok((content_type, bytes))
+}
+
 pub struct Local {
+    /// `local_path` as configured, manga and chapters are identified by their path relative to it
     path: PathBuf,
 }
 
@@ -26,6 +91,33 @@ impl Local {
         Self { path }
     }
 
+    /// Canonicalized `local_path`, resolved on every call as it may not exist yet on start
+    fn root(&self) -> Result<PathBuf, String> {
+        self.path
+            .canonicalize()
+            .map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+
+    /// Canonicalize path of manga or chapter from client, anything outside root is not found,
+    /// so paths of the server can't be probed through local source
+    fn resolve(root: &Path, path: &str) -> Result<PathBuf, String> {
+        root.join(path)
+            .canonicalize()
+            .ok()
+            .filter(|resolved| resolved.starts_with(root) && resolved != root)
+            .ok_or_else(|| format!("{} not found", path))
+    }
+
+    /// Path relative to root with `/` separator, used as path of manga and chapter
+    fn relative(root: &Path, path: &Path) -> String {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
     fn default_cover_url() -> String {
         "/images/cover-placeholder.jpg".to_string()
     }
@@ -37,61 +129,28 @@ impl Local {
             if entry.path().is_dir() {
                 Some(entry)
             } else {
-                entry
-                    .path()
-                    .extension()?
-                    .to_str()
-                    .map(|ext| SUPPORTED_FILES.contains(&ext.to_lowercase()))
+                extension(&entry.path())
+                    .map(|ext| SUPPORTED_FILES.contains(ext.as_str()))
                     .and_then(|supported| if supported { Some(entry) } else { None })
             }
         })
     }
 
-    // find first image from an archvie
-    fn find_cover_from_archive(path: &Path) -> String {
-        libarchive_rs::list_archive_files(path.display().to_string().as_str())
+    // find first page from an archive, document or directory
+    fn find_cover_from_chapter(path: &Path) -> String {
+        Self::list_pages(path)
             .ok()
-            .and_then(|files| files.first().cloned())
-            .map(|page| path.join(page).display().to_string())
+            .and_then(|pages| pages.into_iter().next())
             .unwrap_or_else(Self::default_cover_url)
     }
 
-    // find first image from a directory
-    fn find_cover_from_dir(path: &Path) -> String {
-        path.read_dir()
-            .ok()
-            .map(Self::sort_dir)
-            .and_then(|dir| dir.into_iter().next())
-            .map(|entry| entry.path().display().to_string())
-            .unwrap_or_else(Self::default_cover_url)
-    }
-
-    fn sort_dir(dir: ReadDir) -> Vec<DirEntry> {
-        Self::sort_read_dir_with_reverse(dir, false)
-    }
-
-    #[allow(dead_code)]
-    fn sort_dir_reverse(dir: ReadDir) -> Vec<DirEntry> {
-        Self::sort_read_dir_with_reverse(dir, true)
-    }
-
-    fn sort_read_dir_with_reverse(dir: ReadDir, reverse: bool) -> Vec<DirEntry> {
-        let mut dir: Vec<DirEntry> = dir.into_iter().filter_map(Result::ok).collect();
-        dir.sort_by(|a, b| {
-            human_sort::compare(
-                a.path().display().to_string().as_str(),
-                b.path().display().to_string().as_str(),
-            )
-        });
-        if reverse {
-            dir.reverse();
-        }
-        dir
-    }
-
     fn find_cover_url(entry: &Path) -> String {
         if entry.is_file() {
-            return Self::find_cover_from_archive(entry);
+            return Self::find_cover_from_chapter(entry);
+        }
+
+        if let Some(cover) = metadata::find_cover(entry) {
+            return cover.display().to_string();
         }
 
         let entry_read_dir = match entry.read_dir() {
@@ -111,29 +170,26 @@ impl Local {
             }
         };
 
-        if path.is_dir() {
-            Self::find_cover_from_dir(&path)
-        } else if path.is_file() {
-            Self::find_cover_from_archive(&path)
+        if path.is_dir() || path.is_file() {
+            Self::find_cover_from_chapter(&path)
         } else {
             Self::default_cover_url()
         }
     }
 
-    fn get_pages_from_archive(
-        path: &Path,
-        filename: String,
-    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        match libarchive_rs::list_archive_files(&filename) {
-            Ok(files) => {
-                let pages = files
-                    .into_iter()
-                    .map(|p| path.join(p).display().to_string())
-                    .collect();
-                Ok(pages)
-            }
-            Err(e) => Err(e),
-        }
+    fn sort_pages(mut pages: Vec<String>) -> Vec<String> {
+        pages.sort_by(|a, b| human_sort::compare(a, b));
+        pages
+    }
+
+    fn get_pages_from_archive(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let filename = path.to_str().ok_or("invalid path")?;
+        let pages = libarchive_rs::list_archive_files(filename)?
+            .into_iter()
+            .filter(|p| is_image(Path::new(p)))
+            .map(|p| path.join(p).display().to_string())
+            .collect();
+        Ok(Self::sort_pages(pages))
     }
 
     fn get_pages_from_dir(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
@@ -141,12 +197,54 @@ impl Local {
             .read_dir()?
             .into_iter()
             .filter_map(Result::ok)
-            .filter_map(|f| (f.path().is_file()).then(|| f.path().display().to_string()))
+            .map(|f| f.path())
+            .filter(|p| p.is_file() && is_image(p))
+            .map(|p| p.display().to_string())
+            .collect();
+        Ok(Self::sort_pages(pages))
+    }
+
+    // each page is the pdf path joined with page number, extracted by `read_page`
+    fn get_pages_from_pdf(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let pages = (1..=pdf::page_count(path)?)
+            .map(|number| path.join(number.to_string()).display().to_string())
             .collect();
         Ok(pages)
     }
 
-    fn map_entry_to_chapter(path: &Path) -> Option<Chapter> {
+    // images in reading order of the book, or sorted by name if it has no usable spine
+    fn get_pages_from_epub(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let pages: Vec<String> = match epub::list_images(path) {
+            Ok(images) => images
+                .into_iter()
+                .filter(|p| is_image(Path::new(p)))
+                .map(|p| path.join(p).display().to_string())
+                .collect(),
+            Err(e) => {
+                warn!("error read spine of {}: {}", path.display(), e);
+                vec![]
+            }
+        };
+        if pages.is_empty() {
+            Self::get_pages_from_archive(path)
+        } else {
+            Ok(pages)
+        }
+    }
+
+    fn list_pages(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        if path.is_dir() {
+            return Self::get_pages_from_dir(path);
+        }
+
+        match extension(path).as_deref() {
+            Some("pdf") => Self::get_pages_from_pdf(path),
+            Some("epub") => Self::get_pages_from_epub(path),
+            _ => Self::get_pages_from_archive(path),
+        }
+    }
+
+    fn map_entry_to_chapter(root: &Path, path: &Path) -> Option<Chapter> {
         let modified = match path
             .metadata()
             .ok()
@@ -158,34 +256,51 @@ impl Local {
                 return None;
             }
         };
-        let number_re = match Regex::new(
-            r"(?i)(?<=v)(\d+)|(?<=volume)\s*(\d+)|(?<=vol)\s*(\d+)|(?<=\s)(\d+)|(\d+)",
-        ) {
-            Ok(re) => re,
-            Err(_) => {
-                return None;
-            }
-        };
         let file_name = match path.file_stem().and_then(|file_stem| file_stem.to_str()) {
             Some(file_stem) => file_stem.to_string(),
             None => {
                 return None;
             }
         };
-        let number = match number_re.find(&file_name).ok().and_then(|m| m) {
-            Some(mat) => mat.as_str().parse().unwrap_or(0_f64),
-            None => 10000_f64,
-        };
+        let info = ComicInfo::read(path).unwrap_or_default();
+        let parsed = filename::parse(&file_name);
+        let volume = info.volume.or(parsed.volume);
+        // a volume without chapter number is ordered by its volume
+        let number = info
+            .number
+            .or(parsed.number)
+            .or_else(|| volume.map(|volume| volume as f64))
+            .unwrap_or(10000_f64);
+        let title = info.title.or(parsed.title).unwrap_or(file_name);
 
         Some(Chapter {
             source_id: ID,
-            title: file_name,
-            path: format!("{}", path.display()),
+            title,
+            path: Self::relative(root, path),
             number,
-            scanlator: "".to_string(),
+            volume,
+            scanlator: info.scan_information.unwrap_or_default(),
             uploaded: NaiveDateTime::from_timestamp(modified as i64, 0),
         })
     }
+
+    /// Archive or directory of the first chapter of a manga, the manga itself if it's an archive
+    fn find_first_chapter(path: &Path) -> Option<PathBuf> {
+        if path.is_file() {
+            return Some(path.to_path_buf());
+        }
+
+        path.read_dir()
+            .ok()?
+            .filter_map(Self::filter_supported_files_and_folders)
+            .map(|entry| entry.path())
+            .min_by(|a, b| {
+                human_sort::compare(
+                    a.display().to_string().as_str(),
+                    b.display().to_string().as_str(),
+                )
+            })
+    }
 }
 
 impl Extension for Local {
@@ -199,6 +314,8 @@ impl Extension for Local {
             lib_version: tanoshi_lib::VERSION.to_owned(),
             need_login: false,
             languages: vec![],
+            allowed_hosts: vec![],
+            preferences: vec![],
         }
     }
 
@@ -210,7 +327,11 @@ impl Extension for Local {
         let page = param.page.map(|p| p as usize).unwrap_or(1);
         let offset = (page - 1) * 20;
 
-        let read_dir = match std::fs::read_dir(&self.path) {
+        let root = match self.root() {
+            Ok(root) => root,
+            Err(e) => return ExtensionResult::err(&e),
+        };
+        let read_dir = match std::fs::read_dir(&root) {
             Ok(read_dir) => read_dir,
             Err(e) => {
                 return ExtensionResult::err(format!("{}", e).as_str());
@@ -233,23 +354,29 @@ impl Extension for Local {
             }));
         }
 
+        // only series metadata is read here, reading every archive would slow down listing
         let manga = data
             .skip(offset)
             .take(20)
-            .map(|entry| Manga {
-                source_id: ID,
-                title: entry
-                    .path()
-                    .file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or_default()
-                    .to_string(),
-                author: vec![],
-                genre: vec![],
-                status: None,
-                description: None,
-                path: entry.path().to_str().unwrap_or("").to_string(),
-                cover_url: Self::find_cover_url(&entry.path()),
+            .map(|entry| {
+                let series = SeriesInfo::read(&entry.path()).unwrap_or_default();
+                Manga {
+                    source_id: ID,
+                    title: series.title.unwrap_or_else(|| {
+                        entry
+                            .path()
+                            .file_stem()
+                            .and_then(|s| s.to_str())
+                            .unwrap_or_default()
+                            .to_string()
+                    }),
+                    author: series.author,
+                    genre: series.genre,
+                    status: series.status,
+                    description: series.description,
+                    path: Self::relative(&root, &entry.path()),
+                    cover_url: Self::find_cover_url(&entry.path()),
+                }
             })
             .collect::<Vec<_>>();
 
@@ -257,29 +384,58 @@ impl Extension for Local {
     }
 
     fn get_manga_info(&self, path: String) -> ExtensionResult<Manga> {
-        let path = PathBuf::from(path);
+        let root = match self.root() {
+            Ok(root) => root,
+            Err(e) => return ExtensionResult::err(&e),
+        };
+        let path = match Self::resolve(&root, &path) {
+            Ok(path) => path,
+            Err(e) => return ExtensionResult::err(&e),
+        };
 
-        let title = path
-            .file_stem()
-            .and_then(OsStr::to_str)
-            .unwrap_or("")
-            .to_string();
+        let series = SeriesInfo::read(&path).unwrap_or_default();
+        // what series metadata lacks is taken from first chapter
+        let info = Self::find_first_chapter(&path)
+            .and_then(|chapter| ComicInfo::read(&chapter))
+            .unwrap_or_default();
+
+        let title = series.title.or(info.series).unwrap_or_else(|| {
+            path.file_stem()
+                .and_then(OsStr::to_str)
+                .unwrap_or("")
+                .to_string()
+        });
         ExtensionResult::ok(Manga {
             source_id: ID,
-            title: title.clone(),
-            author: vec![],
-            genre: vec![],
-            status: Some("".to_string()),
-            description: Some(title),
-            path: path.display().to_string(),
+            title,
+            author: if series.author.is_empty() {
+                info.writer
+            } else {
+                series.author
+            },
+            genre: if series.genre.is_empty() {
+                info.genre
+            } else {
+                series.genre
+            },
+            status: series.status.or_else(|| Some("".to_string())),
+            description: series.description.or(info.summary),
+            path: Self::relative(&root, &path),
             cover_url: Self::find_cover_url(&path),
         })
     }
 
     fn get_chapters(&self, path: String) -> ExtensionResult<Vec<Chapter>> {
-        let path = PathBuf::from(path);
+        let root = match self.root() {
+            Ok(root) => root,
+            Err(e) => return ExtensionResult::err(&e),
+        };
+        let path = match Self::resolve(&root, &path) {
+            Ok(path) => path,
+            Err(e) => return ExtensionResult::err(&e),
+        };
         if path.is_file() {
-            if let Some(data) = Self::map_entry_to_chapter(&path) {
+            if let Some(data) = Self::map_entry_to_chapter(&root, &path) {
                 return ExtensionResult::ok(vec![data]);
             }
         }
@@ -291,36 +447,39 @@ impl Extension for Local {
             }
         };
 
+        // skip metadata and cover files next to chapters
         let mut data: Vec<Chapter> = read_dir
             .into_iter()
-            .filter_map(Result::ok)
-            .filter_map(|entry| Self::map_entry_to_chapter(&entry.path()))
+            .filter_map(Self::filter_supported_files_and_folders)
+            .filter_map(|entry| Self::map_entry_to_chapter(&root, &entry.path()))
             .collect();
 
-        data.sort_by(|a, b| a.number.partial_cmp(&b.number).unwrap());
+        data.sort_by(|a, b| {
+            a.number
+                .partial_cmp(&b.number)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
         data.reverse();
         ExtensionResult::ok(data)
     }
 
     fn get_pages(&self, filename: String) -> ExtensionResult<Vec<String>> {
-        let path = PathBuf::from(filename.clone());
-        let mut pages = if path.is_dir() {
-            match Self::get_pages_from_dir(&path) {
-                Ok(pages) => pages,
-                Err(e) => return ExtensionResult::err(format!("{}", e).as_str()),
-            }
-        } else if path.is_file() {
-            match Self::get_pages_from_archive(&path, filename) {
-                Ok(pages) => pages,
-                Err(e) => return ExtensionResult::err(format!("{}", e).as_str()),
-            }
-        } else {
-            return ExtensionResult::err("filename neither file or dir");
+        let root = match self.root() {
+            Ok(root) => root,
+            Err(e) => return ExtensionResult::err(&e),
         };
+        let path = match Self::resolve(&root, &filename) {
+            Ok(path) => path,
+            Err(e) => return ExtensionResult::err(&e),
+        };
+        if !path.is_dir() && !path.is_file() {
+            return ExtensionResult::err("filename neither file or dir");
+        }
 
-        pages.sort_by(|a, b| human_sort::compare(a, b));
-
-        ExtensionResult::ok(pages)
+        match Self::list_pages(&path) {
+            Ok(pages) => ExtensionResult::ok(pages),
+            Err(e) => ExtensionResult::err(format!("{}", e).as_str()),
+        }
     }
 }
 
@@ -332,6 +491,16 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_is_image() {
+        assert!(is_image(Path::new("SPA00401.JPG")));
+        assert!(is_image(Path::new("chapter/002.webp")));
+        assert!(!is_image(Path::new("ComicInfo.xml")));
+        assert!(!is_image(Path::new("Thumbs.db")));
+        assert!(!is_image(Path::new("__MACOSX/chapter/001.jpg")));
+        assert!(!is_image(Path::new("chapter/._001.jpg")));
+    }
+
     #[test]
     fn test_positive_get_manga_list() {
         let local = Local::new("../../test/data/manga");
@@ -344,17 +513,10 @@ mod test {
             assert_eq!(data.len(), 3);
 
             let path_set: HashSet<String> = HashSet::from_iter(data.iter().map(|a| a.path.clone()));
-            #[cfg(target_family = "windows")]
-            let want_path_set = HashSet::from_iter(vec![
-                "../../test/data/manga\\Space_Adventures_004__c2c__diff_ver.cbz".to_string(),
-                "../../test/data/manga\\Space Adventures".to_string(),
-                "../../test/data/manga\\Super Duck".to_string(),
-            ]);
-            #[cfg(target_family = "unix")]
             let want_path_set = HashSet::from_iter(vec![
-                "../../test/data/manga/Space_Adventures_004__c2c__diff_ver.cbz".to_string(),
-                "../../test/data/manga/Space Adventures".to_string(),
-                "../../test/data/manga/Super Duck".to_string(),
+                "Space_Adventures_004__c2c__diff_ver.cbz".to_string(),
+                "Space Adventures".to_string(),
+                "Super Duck".to_string(),
             ]);
 
             assert_eq!(path_set, want_path_set);
@@ -393,17 +555,40 @@ mod test {
         assert_eq!(manga.data.unwrap().len(), 0);
     }
 
+    #[test]
+    fn test_path_outside_local_path() {
+        let local = Local::new("../../test/data/manga");
+
+        for path in &["", "..", "../not_manga", "Super Duck/../..", "/etc", "/"] {
+            assert!(local.get_manga_info(path.to_string()).error.is_some());
+            assert!(local.get_chapters(path.to_string()).error.is_some());
+            assert!(local.get_pages(path.to_string()).error.is_some());
+        }
+
+        let manga = local.get_manga_info("Super Duck/../Super Duck".to_string());
+        assert_eq!(manga.data.map(|manga| manga.path).unwrap(), "Super Duck");
+    }
+
+    #[test]
+    fn test_local_path_created_after_start() {
+        let path = std::env::temp_dir().join("tanoshi_test_local_path_created_after_start");
+        let _ = std::fs::remove_dir_all(&path);
+        let local = Local::new(&path);
+        assert!(local.get_manga_list(Param::default()).error.is_some());
+
+        std::fs::create_dir_all(path.join("Manga").join("Chapter 1")).unwrap();
+        let manga = local.get_manga_list(Param::default());
+        assert_eq!(manga.data.unwrap()[0].path, "Manga");
+        let chapters = local.get_chapters("Manga".to_string());
+        assert_eq!(chapters.data.unwrap()[0].path, "Manga/Chapter 1");
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
     #[test]
     fn test_get_manga_info() {
         let local = Local::new("../../test/data/manga");
-        #[cfg(target_family = "windows")]
-        let manga = local.get_manga_info(
-            "../../test/data/manga\\Space_Adventures_004__c2c__diff_ver.cbz".to_string(),
-        );
-        #[cfg(target_family = "unix")]
-        let manga = local.get_manga_info(
-            "../../test/data/manga/Space_Adventures_004__c2c__diff_ver.cbz".to_string(),
-        );
+        let manga = local.get_manga_info("Space_Adventures_004__c2c__diff_ver.cbz".to_string());
 
         assert!(manga.data.is_some());
         assert!(manga.error.is_none());
@@ -411,15 +596,16 @@ mod test {
         if let Some(data) = manga.data {
             assert_eq!(data.source_id, 1);
             assert_eq!(data.title, "Space_Adventures_004__c2c__diff_ver");
-            #[cfg(target_family = "windows")]
-            assert_eq!(
-                data.cover_url,
-                "../../test/data/manga\\Space_Adventures_004__c2c__diff_ver.cbz\\SPA00401.JPG"
-            );
-            #[cfg(target_family = "unix")]
+            assert_eq!(data.path, "Space_Adventures_004__c2c__diff_ver.cbz");
             assert_eq!(
                 data.cover_url,
-                "../../test/data/manga/Space_Adventures_004__c2c__diff_ver.cbz/SPA00401.JPG"
+                local
+                    .root()
+                    .unwrap()
+                    .join("Space_Adventures_004__c2c__diff_ver.cbz")
+                    .join("SPA00401.JPG")
+                    .display()
+                    .to_string()
             );
         }
     }
@@ -427,14 +613,7 @@ mod test {
     #[test]
     fn test_single_chapter_manga_get_chapters() {
         let local = Local::new("../../test/data/manga");
-        #[cfg(target_family = "windows")]
-        let chapter = local.get_chapters(
-            "../../test/data/manga\\Space_Adventures_004__c2c__diff_ver.cbz".to_string(),
-        );
-        #[cfg(target_family = "unix")]
-        let chapter = local.get_chapters(
-            "../../test/data/manga/Space_Adventures_004__c2c__diff_ver.cbz".to_string(),
-        );
+        let chapter = local.get_chapters("Space_Adventures_004__c2c__diff_ver.cbz".to_string());
 
         assert!(chapter.data.is_some());
         assert!(chapter.error.is_none());
@@ -444,26 +623,14 @@ mod test {
 
             assert_eq!(data[0].source_id, 1);
             assert_eq!(data[0].title, "Space_Adventures_004__c2c__diff_ver");
-            #[cfg(target_family = "windows")]
-            assert_eq!(
-                data[0].path,
-                "../../test/data/manga\\Space_Adventures_004__c2c__diff_ver.cbz"
-            );
-            #[cfg(target_family = "unix")]
-            assert_eq!(
-                data[0].path,
-                "../../test/data/manga/Space_Adventures_004__c2c__diff_ver.cbz"
-            );
+            assert_eq!(data[0].path, "Space_Adventures_004__c2c__diff_ver.cbz");
         }
     }
 
     #[test]
     fn test_manga_get_chapters() {
         let local = Local::new("../../test/data/manga");
-        #[cfg(target_family = "windows")]
-        let chapter = local.get_chapters("../../test/data/manga\\Space Adventures".to_string());
-        #[cfg(target_family = "unix")]
-        let chapter = local.get_chapters("../../test/data/manga/Space Adventures".to_string());
+        let chapter = local.get_chapters("Space Adventures".to_string());
 
         assert!(chapter.data.is_some());
         assert!(chapter.error.is_none());
@@ -474,29 +641,17 @@ mod test {
             assert_eq!(data[0].source_id, 1);
             assert_eq!(data[0].number, 4.0_f64);
             assert_eq!(data[0].title, "Space_Adventures_004__c2c__diff_ver");
-            #[cfg(target_family = "windows")]
-            assert_eq!(
-                data[0].path,
-                "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver"
-            );
-            #[cfg(target_family = "unix")]
             assert_eq!(
                 data[0].path,
-                "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver"
+                "Space Adventures/Space_Adventures_004__c2c__diff_ver"
             );
 
             assert_eq!(data[1].source_id, 1);
             assert_eq!(data[1].number, 1.0_f64);
             assert_eq!(data[1].title, "Space_Adventures_001__c2c__diff_ver");
-            #[cfg(target_family = "windows")]
             assert_eq!(
                 data[1].path,
-                "../../test/data/manga\\Space Adventures\\Space_Adventures_001__c2c__diff_ver.cbz"
-            );
-            #[cfg(target_family = "unix")]
-            assert_eq!(
-                data[1].path,
-                "../../test/data/manga/Space Adventures/Space_Adventures_001__c2c__diff_ver.cbz"
+                "Space Adventures/Space_Adventures_001__c2c__diff_ver.cbz"
             );
         }
     }
@@ -504,258 +659,28 @@ mod test {
     #[test]
     fn test_archive_get_pages() {
         let local = Local::new("../../test/data/manga");
-        #[cfg(target_family = "windows")]
-        let pages = local.get_pages(
-            "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver"
-                .to_string(),
-        );
-        #[cfg(target_family = "unix")]
-        let pages = local.get_pages(
-            "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver"
-                .to_string(),
-        );
+        let pages =
+            local.get_pages("Space Adventures/Space_Adventures_004__c2c__diff_ver".to_string());
 
         assert!(pages.data.is_some());
         assert!(pages.error.is_none());
 
         if let Some(data) = pages.data {
-            assert_eq!(data.len(), 36);
-
-            #[cfg(target_family = "windows")]
-            {
-                assert_eq!(data[0], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00401.JPG");
-                assert_eq!(data[1], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00402.JPG");
-                assert_eq!(data[2], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00403.JPG");
-                assert_eq!(data[3], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00404.JPG");
-                assert_eq!(data[4], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00405.JPG");
-                assert_eq!(data[5], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00406.JPG");
-                assert_eq!(data[6], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00407.JPG");
-                assert_eq!(data[7], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00408.JPG");
-                assert_eq!(data[8], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00409.JPG");
-                assert_eq!(data[9], "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00410.JPG");
-                assert_eq!(
-                    data[10],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00411.JPG"
-                );
-                assert_eq!(
-                    data[11],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00412.JPG"
-                );
-                assert_eq!(
-                    data[12],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00413.JPG"
-                );
-                assert_eq!(
-                    data[13],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00414.JPG"
-                );
-                assert_eq!(
-                    data[14],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00415.JPG"
-                );
-                assert_eq!(
-                    data[15],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00416.JPG"
-                );
-                assert_eq!(
-                    data[16],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00417.JPG"
-                );
-                assert_eq!(
-                    data[17],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00418.JPG"
-                );
-                assert_eq!(
-                    data[18],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00419.JPG"
-                );
-                assert_eq!(
-                    data[19],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00420.JPG"
-                );
-                assert_eq!(
-                    data[20],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00421.JPG"
-                );
-                assert_eq!(
-                    data[21],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00422.JPG"
-                );
-                assert_eq!(
-                    data[22],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00423.JPG"
-                );
-                assert_eq!(
-                    data[23],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00424.JPG"
-                );
-                assert_eq!(
-                    data[24],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00425.JPG"
-                );
-                assert_eq!(
-                    data[25],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00426.JPG"
-                );
-                assert_eq!(
-                    data[26],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00427.JPG"
-                );
-                assert_eq!(
-                    data[27],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00428.JPG"
-                );
-                assert_eq!(
-                    data[28],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00429.JPG"
-                );
-                assert_eq!(
-                    data[29],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00430.JPG"
-                );
-                assert_eq!(
-                    data[30],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00431.JPG"
-                );
-                assert_eq!(
-                    data[31],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00432.JPG"
-                );
-                assert_eq!(
-                    data[32],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00433.JPG"
-                );
-                assert_eq!(
-                    data[33],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00434.JPG"
-                );
-                assert_eq!(
-                    data[34],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00435.JPG"
-                );
-                assert_eq!(
-                    data[35],
-                    "../../test/data/manga\\Space Adventures\\Space_Adventures_004__c2c__diff_ver\\SPA00436.JPG"
-                );
-            }
-
-            #[cfg(target_family = "unix")]
-            {
-                assert_eq!(data[0], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00401.JPG");
-                assert_eq!(data[1], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00402.JPG");
-                assert_eq!(data[2], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00403.JPG");
-                assert_eq!(data[3], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00404.JPG");
-                assert_eq!(data[4], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00405.JPG");
-                assert_eq!(data[5], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00406.JPG");
-                assert_eq!(data[6], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00407.JPG");
-                assert_eq!(data[7], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00408.JPG");
-                assert_eq!(data[8], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00409.JPG");
-                assert_eq!(data[9], "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00410.JPG");
-                assert_eq!(
-                    data[10],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00411.JPG"
-                );
-                assert_eq!(
-                    data[11],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00412.JPG"
-                );
-                assert_eq!(
-                    data[12],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00413.JPG"
-                );
-                assert_eq!(
-                    data[13],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00414.JPG"
-                );
-                assert_eq!(
-                    data[14],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00415.JPG"
-                );
-                assert_eq!(
-                    data[15],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00416.JPG"
-                );
-                assert_eq!(
-                    data[16],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00417.JPG"
-                );
-                assert_eq!(
-                    data[17],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00418.JPG"
-                );
-                assert_eq!(
-                    data[18],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00419.JPG"
-                );
-                assert_eq!(
-                    data[19],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00420.JPG"
-                );
-                assert_eq!(
-                    data[20],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00421.JPG"
-                );
-                assert_eq!(
-                    data[21],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00422.JPG"
-                );
-                assert_eq!(
-                    data[22],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00423.JPG"
-                );
-                assert_eq!(
-                    data[23],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00424.JPG"
-                );
-                assert_eq!(
-                    data[24],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00425.JPG"
-                );
-                assert_eq!(
-                    data[25],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00426.JPG"
-                );
-                assert_eq!(
-                    data[26],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00427.JPG"
-                );
-                assert_eq!(
-                    data[27],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00428.JPG"
-                );
-                assert_eq!(
-                    data[28],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00429.JPG"
-                );
-                assert_eq!(
-                    data[29],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00430.JPG"
-                );
-                assert_eq!(
-                    data[30],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00431.JPG"
-                );
-                assert_eq!(
-                    data[31],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00432.JPG"
-                );
-                assert_eq!(
-                    data[32],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00433.JPG"
-                );
-                assert_eq!(
-                    data[33],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00434.JPG"
-                );
-                assert_eq!(
-                    data[34],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00435.JPG"
-                );
-                assert_eq!(
-                    data[35],
-                    "../../test/data/manga/Space Adventures/Space_Adventures_004__c2c__diff_ver/SPA00436.JPG"
-                );
-            }
+            let chapter = local
+                .root()
+                .unwrap()
+                .join("Space Adventures")
+                .join("Space_Adventures_004__c2c__diff_ver");
+            let want: Vec<String> = (1..=36)
+                .map(|page| {
+                    chapter
+                        .join(format!("SPA004{:02}.JPG", page))
+                        .display()
+                        .to_string()
+                })
+                .collect();
+
+            assert_eq!(data, want);
         }
     }
 }
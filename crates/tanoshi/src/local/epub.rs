@@ -0,0 +1,120 @@
+use std::{collections::HashMap, error::Error, path::Path};
+
+use fancy_regex::Regex;
+
+/// Entry of the epub listing where its package document is
+const CONTAINER: &str = "META-INF/container.xml";
+
+lazy_static! {
+    /// Image referenced by a content document
+    static ref IMAGE_RE: Regex = Regex::new(
+        r#"(?i)<(?:img|image)\b[^>]*?\s(?:src|xlink:href|href)\s*=\s*["']([^"']+)["']"#,
+    )
+    .unwrap();
+}
+
+fn read_entry(filename: &str, name: &str) -> Result<String, Box<dyn Error>> {
+    let bytes = libarchive_rs::extract_archive_file(filename, name)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Resolve `href` relative to entry `base`, e.g. `../images/001.jpg` in `OEBPS/text/001.xhtml`
+fn resolve(base: &str, href: &str) -> String {
+    let href = href
+        .split('#')
+        .next()
+        .unwrap_or_default()
+        .replace("%20", " ");
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Entries of images in reading order, images of each spine item in the order they appear
+pub fn list_images(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let filename = path.to_str().ok_or("invalid path")?;
+
+    let container = read_entry(filename, CONTAINER)?;
+    let container = roxmltree::Document::parse(&container)?;
+    let package_path = container
+        .descendants()
+        .find(|node| node.has_tag_name("rootfile"))
+        .and_then(|node| node.attribute("full-path"))
+        .ok_or("no rootfile in container")?;
+
+    let package = read_entry(filename, package_path)?;
+    let package = roxmltree::Document::parse(&package)?;
+    let manifest: HashMap<&str, (&str, &str)> = package
+        .descendants()
+        .filter(|node| node.has_tag_name("item"))
+        .filter_map(|node| {
+            let href = node.attribute("href")?;
+            let media_type = node.attribute("media-type").unwrap_or_default();
+            Some((node.attribute("id")?, (href, media_type)))
+        })
+        .collect();
+
+    let mut images = vec![];
+    for idref in package
+        .descendants()
+        .filter(|node| node.has_tag_name("itemref"))
+        .filter_map(|node| node.attribute("idref"))
+    {
+        let (href, media_type) = match manifest.get(idref) {
+            Some(item) => *item,
+            None => continue,
+        };
+        let entry = resolve(package_path, href);
+        if media_type.starts_with("image/") {
+            images.push(entry);
+            continue;
+        }
+
+        let content = match read_entry(filename, &entry) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("error read {} of {}: {}", entry, path.display(), e);
+                continue;
+            }
+        };
+        for src in IMAGE_RE
+            .captures_iter(&content)
+            .filter_map(Result::ok)
+            .filter_map(|caps| caps.get(1))
+        {
+            let image = resolve(&entry, src.as_str());
+            if !images.contains(&image) {
+                images.push(image);
+            }
+        }
+    }
+
+    Ok(images)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve("OEBPS/text/001.xhtml", "../images/001.jpg"),
+            "OEBPS/images/001.jpg"
+        );
+        assert_eq!(
+            resolve("OEBPS/content.opf", "./images/cover%20art.png#page"),
+            "OEBPS/images/cover art.png"
+        );
+        assert_eq!(resolve("content.opf", "001.jpg"), "001.jpg");
+    }
+}
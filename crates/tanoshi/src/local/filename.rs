@@ -0,0 +1,166 @@
+use fancy_regex::Regex;
+
+lazy_static! {
+    static ref VOLUME_RE: Regex =
+        Regex::new(r"(?i)(?<![a-z])(?:volume|vol|v)\.?\s*(\d+)(?![\w.])").unwrap();
+    static ref CHAPTER_RE: Regex = Regex::new(
+        r"(?i)(?:(?<![a-z])(?:chapter|chap|ch|episode|ep|c)\.?|#)\s*(\d+(?:\.\d+)?)(?:\s*-\s*\d+(?:\.\d+)?)?(?![\w.])",
+    )
+    .unwrap();
+    static ref NUMBER_RE: Regex =
+        Regex::new(r"(?<![\w.])(\d+(?:\.\d+)?)(?:-\d+(?:\.\d+)?)?(?![\w.])").unwrap();
+    static ref SEPARATOR_RE: Regex = Regex::new(r"\s+-\s+(?![\d.])").unwrap();
+    static ref BRACKET_RE: Regex = Regex::new(r"\[[^\]]*\]|\([^)]*\)|\{[^}]*\}").unwrap();
+}
+
+/// Volume, chapter number and title parsed from file name of a chapter
+#[derive(Debug, Default, PartialEq)]
+pub struct ChapterName {
+    pub volume: Option<i64>,
+    /// First number of a range, e.g. `10` of `c10-11`
+    pub number: Option<f64>,
+    pub title: Option<String>,
+}
+
+/// Replace bytes in range with spaces, so offsets of the rest of text stay the same
+fn blank(text: &mut String, start: usize, end: usize) {
+    text.replace_range(start..end, &" ".repeat(end - start));
+}
+
+/// Trim separators around title, `None` if nothing left
+fn clean_title(text: &str) -> Option<String> {
+    let title = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| c.is_whitespace() || "-:.,#".contains(c))
+        .to_string();
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+/// Parse file name (without extension) of a chapter, e.g. `[Group] Series v01 c005.5 - Title [Digital]`.
+///
+/// Chapter number is taken from explicit marker (`Chapter`, `Ch.`, `c`, `Episode`, `#`), otherwise from
+/// last standalone number before ` - `, ignoring anything in brackets. Title is the text after chapter
+/// marker or the ` - ` separator
+pub fn parse(name: &str) -> ChapterName {
+    let name = name.replace('_', " ");
+
+    // group, year, source and such are in brackets
+    let mut text = name.clone();
+    let brackets: Vec<(usize, usize)> = BRACKET_RE
+        .find_iter(&name)
+        .filter_map(Result::ok)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    for (start, end) in brackets {
+        blank(&mut text, start, end);
+    }
+
+    let volume_match = VOLUME_RE.captures(&text).ok().flatten().map(|caps| {
+        let (start, end) = caps.get(0).map(|m| (m.start(), m.end())).unwrap();
+        let volume = caps.get(1).and_then(|m| m.as_str().parse().ok());
+        (start, end, volume)
+    });
+    let mut volume_end = None;
+    let volume = match volume_match {
+        Some((start, end, volume)) => {
+            blank(&mut text, start, end);
+            volume_end = Some(end);
+            volume
+        }
+        // volume in brackets, e.g. `(v01)`
+        None => VOLUME_RE
+            .captures(&name)
+            .ok()
+            .flatten()
+            .and_then(|caps| caps.get(1))
+            .and_then(|m| m.as_str().parse().ok()),
+    };
+
+    if let Some(caps) = CHAPTER_RE.captures(&text).ok().flatten() {
+        let end = caps.get(0).map(|m| m.end()).unwrap();
+        return ChapterName {
+            volume,
+            number: caps.get(1).and_then(|m| m.as_str().parse().ok()),
+            title: clean_title(&text[end..]),
+        };
+    }
+
+    let (head, tail) = match SEPARATOR_RE.find(&text).ok().flatten() {
+        Some(m) => (&text[..m.start()], Some(&text[m.end()..])),
+        None => (text.as_str(), None),
+    };
+
+    let number = NUMBER_RE
+        .captures_iter(head)
+        .filter_map(Result::ok)
+        .last()
+        .and_then(|caps| caps.get(1))
+        .and_then(|m| m.as_str().parse().ok());
+
+    let title = match (tail, number, volume_end) {
+        (Some(tail), _, _) => clean_title(tail),
+        (None, None, Some(end)) => clean_title(&text[end..]),
+        _ => None,
+    };
+
+    ChapterName {
+        volume,
+        number,
+        title,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// File name, expected volume, number and title
+    type Case = (&'static str, Option<i64>, Option<f64>, Option<&'static str>);
+
+    #[test]
+    fn test_parse() {
+        #[rustfmt::skip]
+        let cases: &[Case] = &[
+            ("Space_Adventures_004__c2c__diff_ver", None, Some(4.0), None),
+            ("Mob Psycho 100 012", None, Some(12.0), None),
+            ("One Piece - 1000", None, Some(1000.0), None),
+            ("Chapter 7", None, Some(7.0), None),
+            ("Ch. 12.5", None, Some(12.5), None),
+            ("ch012.5 - Omake", None, Some(12.5), Some("Omake")),
+            ("Episode 3: Rain", None, Some(3.0), Some("Rain")),
+            ("#42", None, Some(42.0), None),
+            ("c10-11", None, Some(10.0), None),
+            ("Series c010 - 011", None, Some(10.0), None),
+            ("v03 c027", Some(3), Some(27.0), None),
+            ("Vol.1 Ch.5 - The Beginning", Some(1), Some(5.0), Some("The Beginning")),
+            ("Volume 02 Chapter 10.5", Some(2), Some(10.5), None),
+            ("Vol.2 Extra", Some(2), None, Some("Extra")),
+            ("Series v01 (2015) (Digital)", Some(1), None, None),
+            ("[Group] Series - c001 (v01) [Digital]", Some(1), Some(1.0), None),
+            ("[Group] Series 2 - 015 [1080p]", None, Some(15.0), None),
+            ("Series 012 - Title 2", None, Some(12.0), Some("Title 2")),
+            ("Series 010-011", None, Some(10.0), None),
+            ("Series_v02_c008_A_New_Hope", Some(2), Some(8.0), Some("A New Hope")),
+            ("Oneshot", None, None, None),
+        ];
+
+        for (name, volume, number, title) in cases {
+            assert_eq!(
+                parse(name),
+                ChapterName {
+                    volume: *volume,
+                    number: *number,
+                    title: title.map(str::to_string),
+                },
+                "parse {:?}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+/// Name of file with metadata of a chapter, inside its archive or directory
+const COMIC_INFO: &str = "ComicInfo.xml";
+
+fn is_comic_info<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| name.eq_ignore_ascii_case(COMIC_INFO))
+}
+
+/// Split comma separated list, e.g. `Writer` or `Genre` of `ComicInfo.xml`
+fn split_list(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Image named `cover` in directory of a series, used as its cover instead of first page
+pub fn find_cover(dir: &Path) -> Option<PathBuf> {
+    dir.read_dir()
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| {
+            let is_cover = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .map_or(false, |stem| stem.eq_ignore_ascii_case("cover"));
+            is_cover && super::is_image(path) && path.is_file()
+        })
+}
+
+/// Metadata of a chapter from its `ComicInfo.xml`
+#[derive(Debug, Default, PartialEq)]
+pub struct ComicInfo {
+    pub series: Option<String>,
+    pub number: Option<f64>,
+    pub volume: Option<i64>,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub writer: Vec<String>,
+    pub genre: Vec<String>,
+    pub scan_information: Option<String>,
+}
+
+impl ComicInfo {
+    pub fn parse(xml: &str) -> Result<Self, roxmltree::Error> {
+        let doc = roxmltree::Document::parse(xml)?;
+        let text = |name: &str| {
+            doc.root_element()
+                .children()
+                .find(|node| node.has_tag_name(name))
+                .and_then(|node| node.text())
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+                .map(str::to_string)
+        };
+
+        Ok(Self {
+            series: text("Series"),
+            // `NaN` and `inf` parse too, but can't be ordered
+            number: text("Number")
+                .and_then(|number| number.parse().ok())
+                .filter(|number: &f64| number.is_finite()),
+            volume: text("Volume").and_then(|volume| volume.parse().ok()),
+            title: text("Title"),
+            summary: text("Summary"),
+            writer: text("Writer")
+                .map(|writer| split_list(&writer))
+                .unwrap_or_default(),
+            genre: text("Genre")
+                .map(|genre| split_list(&genre))
+                .unwrap_or_default(),
+            scan_information: text("ScanInformation"),
+        })
+    }
+
+    /// Read from archive or directory of a chapter, `None` if it has none or it can't be read
+    pub fn read(path: &Path) -> Option<Self> {
+        let xml = if path.is_dir() {
+            let file = path
+                .read_dir()
+                .ok()?
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .find(|path| is_comic_info(path))?;
+            fs::read_to_string(file).ok()?
+        } else {
+            let filename = path.to_str()?;
+            let file = libarchive_rs::list_archive_files(filename)
+                .ok()?
+                .into_iter()
+                .find(|file| is_comic_info(file))?;
+            let bytes = libarchive_rs::extract_archive_file(filename, &file).ok()?;
+            String::from_utf8(bytes).ok()?
+        };
+
+        match Self::parse(&xml) {
+            Ok(info) => Some(info),
+            Err(e) => {
+                warn!("error parse {} of {}: {}", COMIC_INFO, path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+/// `series.json` as written by Mylar, only fields used here
+#[derive(Debug, Deserialize)]
+struct SeriesJson {
+    metadata: SeriesJsonMetadata,
+}
+
+#[derive(Debug, Deserialize)]
+struct SeriesJsonMetadata {
+    name: Option<String>,
+    publisher: Option<String>,
+    description_text: Option<String>,
+    status: Option<String>,
+}
+
+/// `details.json` in format of Tachiyomi local source
+#[derive(Debug, Deserialize)]
+struct DetailsJson {
+    title: Option<String>,
+    author: Option<String>,
+    artist: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    genre: Vec<String>,
+    /// `0` unknown, `1` ongoing, `2` completed and `3` licensed, as string or number
+    status: Option<serde_json::Value>,
+}
+
+/// Metadata of a series from `series.json` or `details.json` in its directory
+#[derive(Debug, Default, PartialEq)]
+pub struct SeriesInfo {
+    pub title: Option<String>,
+    pub author: Vec<String>,
+    pub genre: Vec<String>,
+    pub status: Option<String>,
+    pub description: Option<String>,
+}
+
+impl SeriesInfo {
+    pub fn parse_series_json(json: &str) -> Result<Self, serde_json::Error> {
+        let metadata = serde_json::from_str::<SeriesJson>(json)?.metadata;
+        let status = metadata.status.map(|status| match status.as_str() {
+            "Continuing" => "Ongoing".to_string(),
+            "Ended" => "Completed".to_string(),
+            _ => status,
+        });
+
+        Ok(Self {
+            title: metadata.name,
+            author: metadata.publisher.into_iter().collect(),
+            genre: vec![],
+            status,
+            description: metadata.description_text,
+        })
+    }
+
+    pub fn parse_details_json(json: &str) -> Result<Self, serde_json::Error> {
+        let details = serde_json::from_str::<DetailsJson>(json)?;
+        let status = details
+            .status
+            .and_then(|status| match status {
+                serde_json::Value::String(status) => status.parse().ok(),
+                serde_json::Value::Number(status) => status.as_i64(),
+                _ => None,
+            })
+            .and_then(|status| match status {
+                1 => Some("Ongoing".to_string()),
+                2 => Some("Completed".to_string()),
+                3 => Some("Licensed".to_string()),
+                _ => None,
+            });
+
+        let mut author = vec![];
+        for name in [details.author, details.artist]
+            .iter()
+            .flatten()
+            .flat_map(|names| split_list(names))
+        {
+            if !author.contains(&name) {
+                author.push(name);
+            }
+        }
+
+        Ok(Self {
+            title: details.title,
+            author,
+            genre: details.genre,
+            status,
+            description: details.description,
+        })
+    }
+
+    /// Read from directory of a series, `series.json` is preferred over `details.json`
+    pub fn read(dir: &Path) -> Option<Self> {
+        let (path, res) = if let Ok(json) = fs::read_to_string(dir.join("series.json")) {
+            (dir.join("series.json"), Self::parse_series_json(&json))
+        } else if let Ok(json) = fs::read_to_string(dir.join("details.json")) {
+            (dir.join("details.json"), Self::parse_details_json(&json))
+        } else {
+            return None;
+        };
+
+        match res {
+            Ok(info) => Some(info),
+            Err(e) => {
+                warn!("error parse {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_comic_info() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Series>Space Adventures</Series>
+  <Number>4.5</Number>
+  <Volume>1</Volume>
+  <Title>The Lost Planet</Title>
+  <Summary>  Captain lands on a planet nobody returned from.  </Summary>
+  <Writer>Joe Gill, Steve Ditko</Writer>
+  <Genre>Science Fiction,Adventure,</Genre>
+  <ScanInformation>c2c diff ver</ScanInformation>
+  <PageCount>36</PageCount>
+</ComicInfo>"#;
+
+        assert_eq!(
+            ComicInfo::parse(xml).unwrap(),
+            ComicInfo {
+                series: Some("Space Adventures".to_string()),
+                number: Some(4.5),
+                volume: Some(1),
+                title: Some("The Lost Planet".to_string()),
+                summary: Some("Captain lands on a planet nobody returned from.".to_string()),
+                writer: vec!["Joe Gill".to_string(), "Steve Ditko".to_string()],
+                genre: vec!["Science Fiction".to_string(), "Adventure".to_string()],
+                scan_information: Some("c2c diff ver".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_comic_info_missing_fields() {
+        let xml = r#"<ComicInfo><Number>bonus</Number><Title></Title></ComicInfo>"#;
+
+        assert_eq!(ComicInfo::parse(xml).unwrap(), ComicInfo::default());
+        assert!(ComicInfo::parse("<ComicInfo>").is_err());
+    }
+
+    #[test]
+    fn test_parse_comic_info_non_finite_number() {
+        for number in &["NaN", "inf", "-inf", "infinity"] {
+            let xml = format!("<ComicInfo><Number>{}</Number></ComicInfo>", number);
+            assert_eq!(ComicInfo::parse(&xml).unwrap().number, None, "{}", number);
+        }
+    }
+
+    #[test]
+    fn test_parse_series_json() {
+        let json = r#"{
+            "version": "1.0.2",
+            "metadata": {
+                "type": "comicSeries",
+                "publisher": "Charlton",
+                "name": "Space Adventures",
+                "year": 1952,
+                "description_text": "Science fiction anthology.",
+                "status": "Ended"
+            }
+        }"#;
+
+        assert_eq!(
+            SeriesInfo::parse_series_json(json).unwrap(),
+            SeriesInfo {
+                title: Some("Space Adventures".to_string()),
+                author: vec!["Charlton".to_string()],
+                genre: vec![],
+                status: Some("Completed".to_string()),
+                description: Some("Science fiction anthology.".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_details_json() {
+        let json = r#"{
+            "title": "Super Duck",
+            "author": "Al Fagaly",
+            "artist": "Al Fagaly, Vic Lockman",
+            "description": "Funny animal comic.",
+            "genre": ["Comedy", "Funny Animal"],
+            "status": "1"
+        }"#;
+
+        assert_eq!(
+            SeriesInfo::parse_details_json(json).unwrap(),
+            SeriesInfo {
+                title: Some("Super Duck".to_string()),
+                author: vec!["Al Fagaly".to_string(), "Vic Lockman".to_string()],
+                genre: vec!["Comedy".to_string(), "Funny Animal".to_string()],
+                status: Some("Ongoing".to_string()),
+                description: Some("Funny animal comic.".to_string()),
+            }
+        );
+
+        let info = SeriesInfo::parse_details_json(r#"{"status": 2}"#).unwrap();
+        assert_eq!(info.status, Some("Completed".to_string()));
+    }
+}
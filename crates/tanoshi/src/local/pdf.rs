@@ -0,0 +1,318 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use lopdf::{Dictionary, Document, Object, Stream};
+
+/// Number of parsed documents kept, so pages of a chapter don't parse it again each
+const DOCUMENT_CACHE_SIZE: usize = 4;
+
+lazy_static! {
+    /// Recently read documents by path and modified time, least recently used first
+    static ref DOCUMENTS: Mutex<VecDeque<(PathBuf, SystemTime, Arc<Document>)>> =
+        Mutex::new(VecDeque::new());
+}
+
+/// Parsed document at `path`, it is parsed again only when the file is modified
+fn load(path: &Path) -> Result<Arc<Document>, Box<dyn Error>> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    if let Ok(mut documents) = DOCUMENTS.lock() {
+        if let Some(index) = documents
+            .iter()
+            .position(|(cached, at, _)| cached == path && *at == modified)
+        {
+            if let Some(entry) = documents.remove(index) {
+                let doc = entry.2.clone();
+                documents.push_back(entry);
+                return Ok(doc);
+            }
+        }
+    }
+
+    let doc = Arc::new(Document::load(path)?);
+    if let Ok(mut documents) = DOCUMENTS.lock() {
+        documents.retain(|(cached, _, _)| cached != path);
+        documents.push_back((path.to_path_buf(), modified, doc.clone()));
+        while documents.len() > DOCUMENT_CACHE_SIZE {
+            documents.pop_front();
+        }
+    }
+
+    Ok(doc)
+}
+
+pub fn page_count(path: &Path) -> Result<usize, Box<dyn Error>> {
+    Ok(load(path)?.get_pages().len())
+}
+
+fn dimension(image: &Stream, key: &[u8]) -> i64 {
+    image.dict.get(key).and_then(Object::as_i64).unwrap_or(0)
+}
+
+/// Image XObjects in resources of a page
+fn images<'a>(doc: &'a Document, resources: &'a Dictionary) -> Vec<&'a Stream> {
+    let xobjects = match resources
+        .get(b"XObject")
+        .and_then(|xobjects| doc.dereference(xobjects))
+        .and_then(|(_, xobjects)| xobjects.as_dict())
+    {
+        Ok(xobjects) => xobjects,
+        Err(_) => return vec![],
+    };
+
+    xobjects
+        .iter()
+        .filter_map(|(_, object)| {
+            doc.dereference(object)
+                .and_then(|(_, object)| object.as_stream())
+                .ok()
+        })
+        .filter(|stream| {
+            stream
+                .dict
+                .get(b"Subtype")
+                .and_then(Object::as_name_str)
+                .ok()
+                == Some("Image")
+        })
+        .collect()
+}
+
+/// Number of color components of `image`, only gray and RGB can be encoded as png
+fn components(doc: &Document, image: &Stream) -> Result<i64, Box<dyn Error>> {
+    let color_space = image
+        .dict
+        .get(b"ColorSpace")
+        .and_then(|color_space| doc.dereference(color_space))?
+        .1;
+    let components = match color_space {
+        Object::Name(name) if name == b"DeviceGray" => 1,
+        Object::Name(name) if name == b"DeviceRGB" => 3,
+        // e.g. [/ICCBased 5 0 R], where the profile stream tells number of components
+        Object::Array(array) => match array.as_slice() {
+            [Object::Name(family), profile] if family == b"ICCBased" => doc
+                .dereference(profile)
+                .and_then(|(_, profile)| profile.as_stream())
+                .and_then(|profile| profile.dict.get(b"N"))
+                .and_then(Object::as_i64)?,
+            _ => 0,
+        },
+        _ => 0,
+    };
+
+    Ok(components)
+}
+
+/// Decode image compressed with a filter browsers can't show, or not compressed at all,
+/// and encode it as png
+fn decode_to_png(doc: &Document, image: &Stream) -> Result<Vec<u8>, Box<dyn Error>> {
+    let color_type = match components(doc, image)? {
+        1 => png::ColorType::Grayscale,
+        3 => png::ColorType::Rgb,
+        _ => return Err("unsupported image color space".into()),
+    };
+    let bit_depth = match image
+        .dict
+        .get(b"BitsPerComponent")
+        .and_then(Object::as_i64)
+        .unwrap_or(8)
+    {
+        1 => png::BitDepth::One,
+        2 => png::BitDepth::Two,
+        4 => png::BitDepth::Four,
+        8 => png::BitDepth::Eight,
+        16 => png::BitDepth::Sixteen,
+        bits => return Err(format!("unsupported image bits per component {}", bits).into()),
+    };
+
+    let data = if image.dict.has(b"Filter") {
+        // lopdf refuses to decompress image, its other keys don't matter to decompression
+        let mut stream = image.clone();
+        stream.dict.remove(b"Subtype");
+        stream.decompressed_content()?
+    } else {
+        image.content.clone()
+    };
+
+    // rows of both are padded to whole bytes, so decoded samples are png image data as is
+    let mut bytes = vec![];
+    let mut encoder = png::Encoder::new(
+        &mut bytes,
+        dimension(image, b"Width") as u32,
+        dimension(image, b"Height") as u32,
+    );
+    encoder.set_color(color_type);
+    encoder.set_depth(bit_depth);
+    encoder.write_header()?.write_image_data(&data)?;
+
+    Ok(bytes)
+}
+
+/// Content type and bytes of page `number`, counted from 1.
+///
+/// Page is served as the largest image embedded in it, which is how scanned comics are made.
+/// JPEG and JPEG 2000 images are served as is, other gray or RGB images are encoded as png
+pub fn page_image(path: &Path, number: u32) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+    let doc = load(path)?;
+    let page_id = *doc.get_pages().get(&number).ok_or("page not found")?;
+
+    // resources may be inherited from page tree
+    let (resources, resource_ids) = doc.get_page_resources(page_id);
+    let image = resources
+        .into_iter()
+        .chain(
+            resource_ids
+                .into_iter()
+                .filter_map(|id| doc.get_dictionary(id).ok()),
+        )
+        .flat_map(|resources| images(&doc, resources))
+        .max_by_key(|image| dimension(image, b"Width") * dimension(image, b"Height"))
+        .ok_or("page has no image")?;
+
+    // image without filter isn't compressed
+    let filters = image.filters().unwrap_or_default();
+    match filters.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["DCTDecode"] => Ok(("image/jpeg".to_string(), image.content.clone())),
+        ["JPXDecode"] => Ok(("image/jp2".to_string(), image.content.clone())),
+        [] | ["FlateDecode"] | ["LZWDecode"] => {
+            Ok(("image/png".to_string(), decode_to_png(&doc, image)?))
+        }
+        _ => Err(format!("unsupported image filter {:?}", filters).into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lopdf::dictionary;
+
+    /// Save document with a page showing `images`
+    fn save(name: &str, images: Vec<Stream>) -> PathBuf {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let mut xobjects = Dictionary::new();
+        for (index, image) in images.into_iter().enumerate() {
+            xobjects.set(format!("Im{}", index), doc.add_object(image));
+        }
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Resources" => dictionary! {
+                "XObject" => xobjects,
+            },
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+
+        let path = std::env::temp_dir().join(name);
+        doc.save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_page_image() {
+        let jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0xFF, 0xD9];
+        let path = save(
+            "tanoshi_test_page_image.pdf",
+            vec![
+                Stream::new(
+                    dictionary! {
+                        "Type" => "XObject",
+                        "Subtype" => "Image",
+                        "Width" => 8,
+                        "Height" => 8,
+                        "Filter" => "DCTDecode",
+                    },
+                    vec![0xFF, 0xD8, 0xFF, 0xD9],
+                ),
+                Stream::new(
+                    dictionary! {
+                        "Type" => "XObject",
+                        "Subtype" => "Image",
+                        "Width" => 800,
+                        "Height" => 1200,
+                        "Filter" => "DCTDecode",
+                    },
+                    jpeg.clone(),
+                ),
+            ],
+        );
+
+        assert_eq!(page_count(&path).unwrap(), 1);
+        assert_eq!(
+            page_image(&path, 1).unwrap(),
+            ("image/jpeg".to_string(), jpeg)
+        );
+        assert!(page_image(&path, 2).is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_page_image_flate_to_png() {
+        let pixels: Vec<u8> = (0..16 * 16).flat_map(|i| vec![i as u8, 0, 255]).collect();
+        let mut image = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 16,
+                "Height" => 16,
+                "ColorSpace" => "DeviceRGB",
+                "BitsPerComponent" => 8,
+            },
+            pixels.clone(),
+        );
+        image.compress().unwrap();
+        assert_eq!(image.filters().unwrap(), vec!["FlateDecode".to_string()]);
+        let path = save("tanoshi_test_page_image_flate_to_png.pdf", vec![image]);
+
+        let (content_type, bytes) = page_image(&path, 1).unwrap();
+        assert_eq!(content_type, "image/png");
+
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut decoded).unwrap();
+        assert_eq!((info.width, info.height), (16, 16));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+        assert_eq!(decoded, pixels);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_document_cached() {
+        let path = save(
+            "tanoshi_test_document_cached.pdf",
+            vec![Stream::new(
+                dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => 8,
+                    "Height" => 8,
+                    "Filter" => "DCTDecode",
+                },
+                vec![0xFF, 0xD8, 0xFF, 0xD9],
+            )],
+        );
+
+        assert!(Arc::ptr_eq(&load(&path).unwrap(), &load(&path).unwrap()));
+
+        let _ = std::fs::remove_file(path);
+    }
+}
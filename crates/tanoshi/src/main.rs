@@ -1,5 +1,7 @@
 #[macro_use]
 extern crate log;
+#[macro_use]
+extern crate lazy_static;
 extern crate argon2;
 
 mod assets;
@@ -11,9 +13,11 @@ mod library;
 mod local;
 mod notifier;
 mod proxy;
+mod repository;
 mod routes;
 mod schema;
 mod status;
+mod subscription;
 mod user;
 mod utils;
 mod worker;
@@ -21,20 +25,30 @@ mod worker;
 use crate::{
     config::Config,
     context::GlobalContext,
+    notifier::{
+        discord::Discord, email::Email, telegram::Telegram, webhook::Webhook, NotifierKind,
+        Notifiers,
+    },
+    repository::Repositories,
     schema::{MutationRoot, QueryRoot, TanoshiSchema},
+    subscription::SubscriptionRoot,
 };
 use clap::Clap;
 use futures::future::OptionFuture;
-use tanoshi_vm::{bus::ExtensionBus, vm};
+use tanoshi_vm::{
+    bus::ExtensionBus,
+    logs::ExtensionLogs,
+    vm,
+    watcher::{self, LoadedSources, ReloadLog},
+};
 
 use async_graphql::{
     extensions::ApolloTracing,
     http::{playground_source, GraphQLPlaygroundConfig},
-    EmptySubscription, Schema,
+    Data, Schema,
 };
 use async_graphql_warp::{BadRequest, Response};
 use std::{convert::Infallible, sync::Arc};
-use teloxide::prelude::RequesterExt;
 use warp::{
     http::{Response as HttpResponse, StatusCode},
     Filter, Rejection,
@@ -67,46 +81,109 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let pool = db::establish_connection(&config.database_path).await?;
     let mangadb = db::MangaDatabase::new(pool.clone());
     let userdb = db::UserDatabase::new(pool.clone());
+    let storage = db::ExtensionStorage::new(pool.clone());
 
-    let (_, extension_tx) = vm::start();
-    vm::load(&config.plugin_path, extension_tx.clone()).await?;
+    let extension_logs = ExtensionLogs::default();
+    let (_, extension_tx) = vm::start(
+        config.extension_limits,
+        Arc::new(storage),
+        extension_logs.clone(),
+    );
+    let loaded = LoadedSources::new(vm::load(&config.plugin_path, extension_tx.clone()).await?);
 
-    let extension_bus = ExtensionBus::new(&config.plugin_path, extension_tx);
+    let extension_reloads = ReloadLog::default();
+    if let Err(e) = watcher::watch(
+        &config.plugin_path,
+        extension_tx.clone(),
+        loaded.clone(),
+        extension_reloads.clone(),
+    ) {
+        error!("failed to watch {}: {}", config.plugin_path, e);
+    }
+
+    let extension_bus = ExtensionBus::new(
+        &config.plugin_path,
+        extension_tx,
+        config.extension_timeouts,
+        loaded,
+    );
 
     extension_bus
         .insert(local::ID, Arc::new(local::Local::new(config.local_path)))
         .await?;
 
-    let mut telegram_bot = None;
+    for (source_id, preferences) in userdb.get_all_source_preferences().await? {
+        extension_bus
+            .set_preferences(source_id, preferences)
+            .await?;
+    }
+
+    let mut notifiers = Notifiers::default();
     let mut telegram_bot_fut: OptionFuture<_> = None.into();
     if let Some(telegram_config) = config.telegram {
-        let bot = teloxide::Bot::new(telegram_config.token)
-            .auto_send()
-            .parse_mode(teloxide::types::ParseMode::Html);
-        telegram_bot_fut = Some(notifier::telegram::run(telegram_config.name, bot.clone())).into();
-        telegram_bot = Some(bot);
+        let telegram = Telegram::new(telegram_config.token);
+        telegram_bot_fut = Some(notifier::telegram::run(
+            telegram_config.name,
+            telegram.bot(),
+        ))
+        .into();
+        notifiers.register(NotifierKind::Telegram, Arc::new(telegram));
+    }
+    if let Some(discord_config) = config.discord {
+        notifiers.register(
+            NotifierKind::Discord,
+            Arc::new(Discord::new(discord_config)),
+        );
     }
+    if let Some(webhook_config) = config.webhook {
+        notifiers.register(
+            NotifierKind::Webhook,
+            Arc::new(Webhook::new(webhook_config)),
+        );
+    }
+    if let Some(smtp_config) = config.smtp {
+        notifiers.register(NotifierKind::Email, Arc::new(Email::new(smtp_config)?));
+    }
+
+    let repositories = Repositories::new(config.repositories.clone(), &config.trusted_keys)?;
+
+    let (event_tx, _) = tokio::sync::broadcast::channel(100);
+
+    let image_cache = proxy::ImageCache::open(
+        &config.image_cache_path,
+        config.image_cache_size * 1024 * 1024,
+    )?;
 
     let (worker_handle, worker_tx) = worker::start(
         config.update_interval,
+        config.download_path.clone().into(),
         mangadb.clone(),
         userdb.clone(),
+        config.secret.clone(),
         extension_bus.clone(),
-        telegram_bot,
+        repositories.clone(),
+        notifiers.clone(),
+        event_tx.clone(),
     );
 
     let schema: TanoshiSchema = Schema::build(
         QueryRoot::default(),
         MutationRoot::default(),
-        EmptySubscription::default(),
+        SubscriptionRoot::default(),
     )
     .extension(ApolloTracing)
     .data(GlobalContext::new(
-        userdb,
+        userdb.clone(),
         mangadb,
         config.secret.clone(),
-        extension_bus,
+        extension_bus.clone(),
         worker_tx,
+        event_tx,
+        notifiers,
+        repositories,
+        image_cache.clone(),
+        extension_reloads,
+        extension_logs,
     ))
     .finish();
 
@@ -125,22 +202,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             },
         );
 
+    let graphql_subscription =
+        warp::path!("graphql").and(async_graphql_warp::graphql_subscription_with_data(
+            schema.clone(),
+            |payload: serde_json::Value| async move {
+                let mut data = Data::default();
+                if let Some(token) = payload
+                    .get("token")
+                    .and_then(|token| token.as_str())
+                    .map(|token| token.to_string())
+                {
+                    data.insert(token);
+                }
+                Ok(data)
+            },
+        ));
+
     let health_check = warp::path!("health").and(warp::get()).map(warp::reply);
 
     let static_files = assets::filter::static_files();
-    let image_proxy = proxy::proxy(config.secret.clone());
+    let image_proxy = proxy::proxy(
+        config.secret.clone(),
+        extension_bus.clone(),
+        image_cache,
+        userdb,
+    );
 
     let server_fut = if config.enable_playground {
         info!("enable graphql playground");
         let graphql_playground = warp::path!("graphql").and(warp::get()).map(|| {
             HttpResponse::builder()
                 .header("content-type", "text/html")
-                .body(playground_source(GraphQLPlaygroundConfig::new("/graphql")))
+                .body(playground_source(
+                    GraphQLPlaygroundConfig::new("/graphql").subscription_endpoint("/graphql"),
+                ))
         });
         bind_routes!(
             config.port,
             health_check,
             image_proxy,
+            graphql_subscription,
             graphql_playground,
             static_files,
             graphql_post
@@ -150,6 +251,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             config.port,
             health_check,
             image_proxy,
+            graphql_subscription,
             static_files,
             graphql_post
         )
@@ -0,0 +1,81 @@
+use super::{Notification, Notifier};
+use crate::{config::DiscordConfig, db::model::User};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use reqwest::Url;
+use serde_json::json;
+
+/// Check webhook set by user is a discord webhook
+pub fn validate_webhook(webhook: &str) -> anyhow::Result<Url> {
+    let url = Url::parse(webhook)?;
+    if url.scheme() != "https"
+        || url.host_str() != Some("discord.com")
+        || url.port().is_some()
+        || !url.path().starts_with("/api/webhooks/")
+    {
+        bail!("{} is not a discord webhook", webhook);
+    }
+
+    Ok(url)
+}
+
+pub struct Discord {
+    client: reqwest::Client,
+    config: DiscordConfig,
+}
+
+impl Discord {
+    pub fn new(config: DiscordConfig) -> Self {
+        Self {
+            client: super::client(),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Discord {
+    async fn send(&self, user: &User, notification: &Notification) -> anyhow::Result<()> {
+        let webhook = user
+            .discord_webhook
+            .as_ref()
+            .ok_or_else(|| anyhow!("discord webhook is not set"))?;
+        let webhook = validate_webhook(webhook)?;
+
+        let mut embed = json!({
+            "title": notification.title,
+            "description": notification.body,
+        });
+        if let Some(image_url) = notification.image_url.as_ref() {
+            embed["thumbnail"] = json!({ "url": image_url });
+        }
+
+        self.client
+            .post(webhook)
+            .json(&json!({
+                "username": self.config.username,
+                "avatar_url": self.config.avatar_url,
+                "embeds": [embed],
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_webhook() {
+        assert!(validate_webhook("https://discord.com/api/webhooks/1234/token").is_ok());
+        assert!(validate_webhook("http://discord.com/api/webhooks/1234/token").is_err());
+        assert!(validate_webhook("https://discord.com:8443/api/webhooks/1234/token").is_err());
+        assert!(validate_webhook("https://discord.com.evil.net/api/webhooks/1234").is_err());
+        assert!(validate_webhook("https://discord.com/api/users/@me").is_err());
+        assert!(validate_webhook("http://127.0.0.1/api/webhooks/1234").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use super::{Notification, Notifier};
+use crate::{config::SmtpConfig, db::model::User};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+pub struct Email {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl Email {
+    pub fn new(config: SmtpConfig) -> anyhow::Result<Self> {
+        let mut builder = if config.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+
+        let transport = builder
+            .credentials(Credentials::new(config.username, config.password))
+            .build();
+
+        Ok(Self {
+            from: config.from.parse()?,
+            transport,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for Email {
+    async fn send(&self, user: &User, notification: &Notification) -> anyhow::Result<()> {
+        let to = user
+            .email
+            .as_ref()
+            .ok_or_else(|| anyhow!("email is not set"))?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(notification.title.clone())
+            .body(notification.body.clone())?;
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+}
@@ -1,13 +1,167 @@
+pub mod discord;
+pub mod email;
 pub mod telegram;
+pub mod webhook;
 
-use crate::{context::GlobalContext, user, worker::Command as WorkerCommand};
-use async_graphql::{Context, Object, Result};
+use std::{collections::HashMap, fmt::Display, net::SocketAddr, str::FromStr, sync::Arc};
+
+use crate::{context::GlobalContext, db::model::User, user};
+use anyhow::{anyhow, bail};
+use async_graphql::{Context, Enum, Object, Result};
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::Serialize;
+use tanoshi_vm::limits::is_private_ip;
+
+/// A notification, each notifier format it for its own medium
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub image_url: Option<String>,
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Send notification to user, fails if user doesn't have target for this notifier
+    async fn send(&self, user: &User, notification: &Notification) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord, Enum)]
+pub enum NotifierKind {
+    Telegram,
+    Discord,
+    Webhook,
+    Email,
+}
+
+impl Display for NotifierKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            NotifierKind::Telegram => "telegram",
+            NotifierKind::Discord => "discord",
+            NotifierKind::Webhook => "webhook",
+            NotifierKind::Email => "email",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl FromStr for NotifierKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "telegram" => Ok(NotifierKind::Telegram),
+            "discord" => Ok(NotifierKind::Discord),
+            "webhook" => Ok(NotifierKind::Webhook),
+            "email" => Ok(NotifierKind::Email),
+            _ => Err(anyhow!("unknown notifier {}", s)),
+        }
+    }
+}
+
+/// Check url set by user is http(s) url of a host on internet, so notifier can't be used to
+/// reach the server itself or its local network
+pub async fn validate_url(url: &str) -> anyhow::Result<Url> {
+    resolve_public(url).await.map(|(url, _)| url)
+}
+
+/// Resolve host of url set by user, fails unless every address it resolves to is public
+async fn resolve_public(url: &str) -> anyhow::Result<(Url, SocketAddr)> {
+    let url = Url::parse(url)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("{} is not http url", url);
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("{} has no host", url))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+    if addrs.iter().any(|addr| is_private_ip(addr.ip())) {
+        bail!("{} is not reachable from internet", url);
+    }
+    let addr = *addrs
+        .first()
+        .ok_or_else(|| anyhow!("{} has no address", url))?;
+
+    Ok((url, addr))
+}
+
+/// Validate url set by user and return client that connects only to the address checked,
+/// as host may resolve to another address when client resolves it again
+pub async fn public_client(url: &str) -> anyhow::Result<(Url, reqwest::Client)> {
+    let (url, addr) = resolve_public(url).await?;
+    let mut builder = client_builder();
+    if let Some(domain) = url.domain() {
+        builder = builder.resolve(domain, addr);
+    }
+
+    Ok((url, builder.build()?))
+}
+
+/// Client for urls set by user, redirects are not followed as they aren't validated
+fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().redirect(reqwest::redirect::Policy::none())
+}
+
+fn client() -> reqwest::Client {
+    client_builder().build().unwrap_or_default()
+}
+
+/// Configured notifiers, user choose one of them in their settings
+#[derive(Clone, Default)]
+pub struct Notifiers {
+    notifiers: HashMap<NotifierKind, Arc<dyn Notifier>>,
+}
+
+impl Notifiers {
+    pub fn register(&mut self, kind: NotifierKind, notifier: Arc<dyn Notifier>) {
+        self.notifiers.insert(kind, notifier);
+    }
+
+    pub fn kinds(&self) -> Vec<NotifierKind> {
+        let mut kinds: Vec<NotifierKind> = self.notifiers.keys().cloned().collect();
+        kinds.sort();
+        kinds
+    }
+
+    pub async fn send_with(
+        &self,
+        kind: NotifierKind,
+        user: &User,
+        notification: &Notification,
+    ) -> anyhow::Result<()> {
+        self.notifiers
+            .get(&kind)
+            .ok_or_else(|| anyhow!("{} notifier is not configured", kind))?
+            .send(user, notification)
+            .await
+    }
+
+    /// Send notification with notifier chosen by user, do nothing if user has none
+    pub async fn send(&self, user: &User, notification: &Notification) -> anyhow::Result<()> {
+        match user.notifier {
+            Some(kind) => self.send_with(kind, user, notification).await,
+            None => Ok(()),
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct NotificationRoot;
 
 #[Object]
 impl NotificationRoot {
+    async fn notifiers(&self, ctx: &Context<'_>) -> Result<Vec<NotifierKind>> {
+        let _ = user::get_claims(ctx)?;
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        Ok(ctx.notifiers.kinds())
+    }
+
     async fn test_telegram(
         &self,
         ctx: &Context<'_>,
@@ -15,11 +169,63 @@ impl NotificationRoot {
     ) -> Result<bool> {
         let _ = user::get_claims(ctx)?;
         let ctx = ctx.data::<GlobalContext>()?;
-        ctx.worker_tx.send(WorkerCommand::TelegramMessage(
-            chat_id,
-            "Test Notification".to_string(),
-        ))?;
+
+        let user = User {
+            telegram_chat_id: Some(chat_id),
+            ..Default::default()
+        };
+        ctx.notifiers
+            .send_with(NotifierKind::Telegram, &user, &test_notification())
+            .await?;
 
         Ok(true)
     }
+
+    async fn test_notification(&self, ctx: &Context<'_>) -> Result<bool> {
+        let claims = user::get_claims(ctx)?;
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        let user = ctx.userdb.get_user_by_id(claims.sub).await?;
+        let kind = user.notifier.ok_or("no notifier selected")?;
+        // email address isn't verified, so server would send mail anywhere a user asks for
+        if kind == NotifierKind::Email && !claims.is_admin {
+            return Err("only admin can test email notification".into());
+        }
+        ctx.notifiers
+            .send_with(kind, &user, &test_notification())
+            .await?;
+
+        Ok(true)
+    }
+}
+
+fn test_notification() -> Notification {
+    Notification {
+        title: "Tanoshi".to_string(),
+        body: "Test Notification".to_string(),
+        image_url: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_validate_url() {
+        assert!(validate_url("https://93.184.216.34/hook").await.is_ok());
+        assert!(validate_url("ftp://93.184.216.34/hook").await.is_err());
+        assert!(validate_url("file:///etc/passwd").await.is_err());
+        assert!(validate_url("http://127.0.0.1:80/graphql").await.is_err());
+        assert!(validate_url("http://localhost/graphql").await.is_err());
+        assert!(validate_url("http://192.168.1.1/").await.is_err());
+        assert!(validate_url("http://[::1]/").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_public_client() {
+        let (url, _) = public_client("https://93.184.216.34/hook").await.unwrap();
+        assert_eq!(url.as_str(), "https://93.184.216.34/hook");
+        assert!(public_client("http://localhost/graphql").await.is_err());
+    }
 }
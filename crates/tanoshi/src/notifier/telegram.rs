@@ -1,3 +1,7 @@
+use super::{Notification, Notifier};
+use crate::db::model::User;
+use anyhow::anyhow;
+use async_trait::async_trait;
 use teloxide::{adaptors::DefaultParseMode, prelude::*, utils::command::BotCommand};
 
 #[derive(BotCommand)]
@@ -29,9 +33,40 @@ async fn answer(
 
 pub async fn run(name: String, bot: DefaultParseMode<AutoSend<Bot>>) {
     info!("start telegram bot");
-    teloxide::commands_repl(
-        bot,
-        name,
-        answer,
-    ).await;
+    teloxide::commands_repl(bot, name, answer).await;
+}
+
+pub struct Telegram {
+    bot: DefaultParseMode<AutoSend<Bot>>,
+}
+
+impl Telegram {
+    pub fn new(token: String) -> Self {
+        let bot = Bot::new(token)
+            .auto_send()
+            .parse_mode(teloxide::types::ParseMode::Html);
+
+        Self { bot }
+    }
+
+    pub fn bot(&self) -> DefaultParseMode<AutoSend<Bot>> {
+        self.bot.clone()
+    }
+}
+
+#[async_trait]
+impl Notifier for Telegram {
+    async fn send(&self, user: &User, notification: &Notification) -> anyhow::Result<()> {
+        let chat_id = user
+            .telegram_chat_id
+            .ok_or_else(|| anyhow!("telegram chat id is not set"))?;
+
+        let title = html_escape::encode_safe(&notification.title).to_string();
+        let body = html_escape::encode_safe(&notification.body).to_string();
+        self.bot
+            .send_message(chat_id, format!("<b>{}</b>\n{}", title, body))
+            .await?;
+
+        Ok(())
+    }
 }
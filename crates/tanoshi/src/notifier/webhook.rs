@@ -0,0 +1,35 @@
+use super::{public_client, Notification, Notifier};
+use crate::{config::WebhookConfig, db::model::User};
+use anyhow::anyhow;
+use async_trait::async_trait;
+
+/// Post notification as json to url set by user
+pub struct Webhook {
+    config: WebhookConfig,
+}
+
+impl Webhook {
+    pub fn new(config: WebhookConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    async fn send(&self, user: &User, notification: &Notification) -> anyhow::Result<()> {
+        let url = user
+            .webhook_url
+            .as_ref()
+            .ok_or_else(|| anyhow!("webhook url is not set"))?;
+        // client is bound to address url is validated against, so it can't be rebound to local one
+        let (url, client) = public_client(url).await?;
+
+        let mut req = client.post(url).json(notification);
+        for (name, value) in self.config.headers.iter() {
+            req = req.header(name, value);
+        }
+        req.send().await?.error_for_status()?;
+
+        Ok(())
+    }
+}
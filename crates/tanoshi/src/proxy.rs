@@ -1,19 +1,280 @@
+use async_graphql::{Context, Object, Result as GqlResult};
 use bytes::Bytes;
-use serde::Deserialize;
-use std::convert::Infallible;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use tanoshi_vm::prelude::{ExtensionBus, ExtensionError, Session};
 use warp::{filters::BoxedFilter, hyper::Response, Filter, Reply};
 
-use crate::utils;
+use crate::{catalogue, context::GlobalContext, db::UserDatabase, user, utils};
 
-#[derive(Deserialize)]
+/// Image served by proxy, `source_id` is the extension to fetch remote image with
+#[derive(Serialize, Deserialize)]
 pub struct Image {
+    pub source_id: Option<i64>,
     pub url: String,
+    /// User the image is shown to, extension fetches it with the user's login and cookies
+    #[serde(default)]
+    pub user_id: Option<i64>,
 }
 
-pub fn proxy(secret: String) -> BoxedFilter<(impl Reply,)> {
+impl Image {
+    pub fn new(source_id: i64, url: &str, user_id: Option<i64>) -> Self {
+        Self {
+            source_id: Some(source_id),
+            url: url.to_string(),
+            user_id,
+        }
+    }
+
+    pub fn encrypt(&self, secret: &str) -> Result<String, Box<dyn std::error::Error>> {
+        utils::encrypt_url(secret, &serde_json::to_string(self)?)
+    }
+
+    /// Decrypt image, plain url is accepted for image encrypted without source
+    pub fn decrypt(secret: &str, encrypted: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let decrypted = utils::decrypt_url(secret, encrypted)?;
+        Ok(serde_json::from_str(&decrypted).unwrap_or(Self {
+            source_id: None,
+            url: decrypted,
+            user_id: None,
+        }))
+    }
+}
+
+struct CacheEntry {
+    size: u64,
+    last_access: u64,
+}
+
+#[derive(Default)]
+struct CacheIndex {
+    entries: HashMap<String, CacheEntry>,
+    size: u64,
+    clock: u64,
+}
+
+impl CacheIndex {
+    fn touch(&mut self, key: &str) -> bool {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_access = self.clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, key: String, size: u64) {
+        self.clock += 1;
+        let entry = CacheEntry {
+            size,
+            last_access: self.clock,
+        };
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.size -= old.size;
+        }
+        self.size += size;
+    }
+
+    /// Remove least recently used entries until size is under `max_size`
+    fn evict(&mut self, max_size: u64) -> Vec<String> {
+        let mut evicted = vec![];
+        while self.size > max_size {
+            let key = match self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(key, _)| key.clone())
+            {
+                Some(key) => key,
+                None => break,
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.size -= entry.size;
+            }
+            evicted.push(key);
+        }
+        evicted
+    }
+}
+
+/// Image cached on disk, `etag` is hash of the content
+pub struct CachedImage {
+    pub content_type: String,
+    pub etag: String,
+    pub bytes: Bytes,
+}
+
+/// On disk cache of remote images keyed by url, least recently used images are removed
+/// when total size exceeds `max_size`
+#[derive(Clone)]
+pub struct ImageCache {
+    path: PathBuf,
+    max_size: u64,
+    index: Arc<Mutex<CacheIndex>>,
+}
+
+impl ImageCache {
+    /// Open cache at `path`, existing files are indexed by modified time. `max_size` is in bytes,
+    /// 0 disable the cache
+    pub fn open<P: AsRef<Path>>(path: P, max_size: u64) -> Result<Self, std::io::Error> {
+        let path = path.as_ref().to_path_buf();
+        std::fs::create_dir_all(&path)?;
+
+        let mut files = vec![];
+        for entry in std::fs::read_dir(&path)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((
+                modified,
+                entry.file_name().to_string_lossy().to_string(),
+                metadata.len(),
+            ));
+        }
+        files.sort();
+
+        let mut index = CacheIndex::default();
+        for (_, key, size) in files {
+            index.insert(key, size);
+        }
+
+        let cache = Self {
+            path,
+            max_size,
+            index: Arc::new(Mutex::new(index)),
+        };
+        cache.evict();
+
+        Ok(cache)
+    }
+
+    fn key(url: &str) -> String {
+        format!("{:x}", Sha256::digest(url.as_bytes()))
+    }
+
+    pub async fn get(&self, url: &str) -> Option<CachedImage> {
+        let key = Self::key(url);
+        if !self.index.lock().ok()?.touch(&key) {
+            return None;
+        }
+
+        let data = match tokio::fs::read(self.path.join(&key)).await {
+            Ok(data) => data,
+            Err(e) => {
+                error!("error read cached image {}: {}", key, e);
+                self.remove(&key);
+                return None;
+            }
+        };
+
+        // file is content type and etag line followed by the image
+        let mut parts = data.splitn(3, |c| *c == b'\n');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(content_type), Some(etag), Some(bytes)) => Some(CachedImage {
+                content_type: String::from_utf8_lossy(content_type).to_string(),
+                etag: String::from_utf8_lossy(etag).to_string(),
+                bytes: Bytes::copy_from_slice(bytes),
+            }),
+            _ => {
+                self.remove(&key);
+                None
+            }
+        }
+    }
+
+    /// Store image, returns its etag
+    pub async fn put(&self, url: &str, content_type: &str, bytes: &Bytes) -> String {
+        let etag = format!("\"{:x}\"", Sha256::digest(bytes));
+        if self.max_size == 0 {
+            return etag;
+        }
+
+        let key = Self::key(url);
+        let mut data = format!("{}\n{}\n", content_type, etag).into_bytes();
+        data.extend_from_slice(bytes);
+
+        let size = data.len() as u64;
+        if let Err(e) = tokio::fs::write(self.path.join(&key), data).await {
+            error!("error write cached image {}: {}", key, e);
+            return etag;
+        }
+
+        if let Ok(mut index) = self.index.lock() {
+            index.insert(key, size);
+        }
+        self.evict();
+
+        etag
+    }
+
+    fn remove(&self, key: &str) {
+        if let Ok(mut index) = self.index.lock() {
+            if let Some(entry) = index.entries.remove(key) {
+                index.size -= entry.size;
+            }
+        }
+        let _ = std::fs::remove_file(self.path.join(key));
+    }
+
+    fn evict(&self) {
+        let evicted = match self.index.lock() {
+            Ok(mut index) => index.evict(self.max_size),
+            Err(_) => return,
+        };
+        for key in evicted {
+            debug!("evict cached image {}", key);
+            if let Err(e) = std::fs::remove_file(self.path.join(&key)) {
+                error!("error remove cached image {}: {}", key, e);
+            }
+        }
+    }
+
+    /// Remove all cached images, returns number of bytes freed
+    pub async fn clear(&self) -> u64 {
+        let (keys, size) = match self.index.lock() {
+            Ok(mut index) => {
+                let size = index.size;
+                index.size = 0;
+                (index.entries.drain().map(|(key, _)| key).collect(), size)
+            }
+            Err(_) => return 0,
+        };
+
+        for key in keys {
+            if let Err(e) = tokio::fs::remove_file(self.path.join(&key)).await {
+                error!("error remove cached image {}: {}", key, e);
+            }
+        }
+
+        size
+    }
+}
+
+pub fn proxy(
+    secret: String,
+    extensions: ExtensionBus,
+    cache: ImageCache,
+    userdb: UserDatabase,
+) -> BoxedFilter<(impl Reply,)> {
     warp::path!("image" / String)
         .and(warp::get())
+        .and(warp::header::optional::<String>("if-none-match"))
         .and(with_secret(secret))
+        .and(with_extensions(extensions))
+        .and(with_cache(cache))
+        .and(with_userdb(userdb))
         .and_then(get_image)
         .boxed()
 }
@@ -24,20 +285,150 @@ fn with_secret(
     warp::any().map(move || secret.clone())
 }
 
-pub async fn get_image(url: String, secret: String) -> Result<impl warp::Reply, Infallible> {
-    debug!("encrypted image url: {}", url);
-    let url = match utils::decrypt_url(&secret, &url) {
-        Ok(url) => url,
+fn with_extensions(
+    extensions: ExtensionBus,
+) -> impl Filter<Extract = (ExtensionBus,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || extensions.clone())
+}
+
+fn with_cache(
+    cache: ImageCache,
+) -> impl Filter<Extract = (ImageCache,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || cache.clone())
+}
+
+fn with_userdb(
+    userdb: UserDatabase,
+) -> impl Filter<Extract = (UserDatabase,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || userdb.clone())
+}
+
+pub async fn get_image(
+    encrypted: String,
+    if_none_match: Option<String>,
+    secret: String,
+    extensions: ExtensionBus,
+    cache: ImageCache,
+    userdb: UserDatabase,
+) -> Result<impl warp::Reply, Infallible> {
+    debug!("encrypted image url: {}", encrypted);
+    let image = match Image::decrypt(&secret, &encrypted) {
+        Ok(image) => image,
         Err(e) => {
             error!("error validate url: {}", e);
-            "".to_string()
+            return Ok(empty_response(400));
         }
     };
-    debug!("get image from {}", url);
-    match url {
-        url if url.starts_with("http") => Ok(get_image_from_url(url).await?),
-        url if !url.is_empty() => Ok(get_image_from_file(url).await?),
-        _ => Ok(empty_response(400)),
+    debug!("get image from {}", image.url);
+    if !image.url.starts_with("http") {
+        return match image.url {
+            url if !url.is_empty() => Ok(get_image_from_file(url).await?),
+            _ => Ok(empty_response(400)),
+        };
+    }
+
+    if let Some(cached) = cache.get(&image.url).await {
+        debug!("serve {} from cache", image.url);
+        return Ok(cached_response(
+            cached.content_type,
+            cached.etag,
+            cached.bytes,
+            if_none_match,
+        ));
+    }
+
+    let url = image.url.clone();
+    let res = match image.source_id {
+        Some(source_id) if source_id != crate::local::ID => {
+            let session = match image.user_id {
+                Some(user_id) => {
+                    catalogue::get_source_session(&userdb, &secret, user_id, source_id).await
+                }
+                None => Session::default(),
+            };
+            get_image_from_source(&extensions, source_id, image.url, session).await?
+        }
+        _ => get_image_from_url(image.url).await?,
+    };
+
+    Ok(cache_response(&cache, &url, res, if_none_match).await)
+}
+
+/// Cache image of successful response, error response is returned as is so it is fetched again
+/// on next request
+async fn cache_response(
+    cache: &ImageCache,
+    url: &str,
+    res: Response<Bytes>,
+    if_none_match: Option<String>,
+) -> Response<Bytes> {
+    if !res.status().is_success() {
+        return res;
+    }
+
+    let content_type = res
+        .headers()
+        .get("content-type")
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let bytes = res.into_body();
+    let etag = cache.put(url, &content_type, &bytes).await;
+
+    cached_response(content_type, etag, bytes, if_none_match)
+}
+
+fn cached_response(
+    content_type: String,
+    etag: String,
+    bytes: Bytes,
+    if_none_match: Option<String>,
+) -> Response<Bytes> {
+    let is_match = if_none_match.map_or(false, |if_none_match| {
+        if_none_match
+            .split(',')
+            .any(|tag| tag.trim().trim_start_matches("W/") == etag || tag.trim() == "*")
+    });
+    if is_match {
+        return warp::http::Response::builder()
+            .status(304)
+            .header("ETag", etag)
+            .header("Cache-Control", "max-age=315360000")
+            .body(Bytes::new())
+            .unwrap_or_default();
+    }
+
+    let mut res = image_response(content_type, bytes);
+    if let Ok(etag) = etag.parse() {
+        res.headers_mut().insert("ETag", etag);
+    }
+    res
+}
+
+/// Fetch image with extension, falls back to plain request only if extension is built before
+/// `get_page` exists. Any other error is returned, as plain request isn't checked against hosts
+/// extension is allowed to reach
+pub async fn get_image_from_source(
+    extensions: &ExtensionBus,
+    source_id: i64,
+    url: String,
+    session: Session,
+) -> Result<Response<Bytes>, Infallible> {
+    match extensions.get_page(source_id, url.clone(), session).await {
+        Ok(image) => {
+            let content_type = image
+                .content_type
+                .unwrap_or_else(|| content_type_from_url(&url));
+            Ok(image_response(content_type, Bytes::from(image.bytes)))
+        }
+        Err(ExtensionError::Unsupported(_)) => {
+            debug!("extension doesn't support get_page, fetch {} directly", url);
+            get_image_from_url(url).await
+        }
+        Err(e) => {
+            error!("extension failed to fetch {}: {}", url, e);
+            Ok(empty_response(error_status(&e)))
+        }
     }
 }
 
@@ -53,20 +444,38 @@ pub async fn get_image_from_file(file: String) -> Result<Response<Bytes>, Infall
             Err(_) => Ok(empty_response(500)),
         }
     } else {
-        // else if its combination of archive files and path inside the archive
-        // extract the file from archive
-        let filename = file.parent().unwrap().to_str().unwrap();
-        let path = file.file_name().unwrap().to_str().unwrap();
-        match libarchive_rs::extract_archive_file(filename, path) {
-            Ok(buf) => Ok(warp::http::Response::builder()
-                .status(200)
-                .body(Bytes::from(buf))
-                .unwrap()),
-            Err(_) => Ok(empty_response(400)),
+        // else if its combination of archive or document and path of the page inside it
+        // extract the page from it
+        let page = tokio::task::spawn_blocking(move || {
+            crate::local::read_page(&file)
+                .map_err(|e| format!("error read page {}: {}", file.display(), e))
+        })
+        .await;
+        match page {
+            Ok(Ok((content_type, buf))) => Ok(image_response(content_type, Bytes::from(buf))),
+            Ok(Err(e)) => {
+                error!("{}", e);
+                Ok(empty_response(400))
+            }
+            Err(e) => {
+                error!("error read page: {}", e);
+                Ok(empty_response(500))
+            }
         }
     }
 }
 
+/// Status of proxy response for error of extension
+fn error_status(e: &ExtensionError) -> u16 {
+    match e {
+        ExtensionError::NotFound(_) => 404,
+        ExtensionError::Timeout => 504,
+        ExtensionError::Remote(status) if (400..600).contains(status) => *status as u16,
+        ExtensionError::Remote(_) => 502,
+        _ => 500,
+    }
+}
+
 fn empty_response(status: u16) -> Response<Bytes> {
     warp::http::Response::builder()
         .status(status)
@@ -87,6 +496,10 @@ pub async fn get_image_from_url(url: String) -> Result<Response<Bytes>, Infallib
             return Ok(empty_response(500));
         }
     };
+    if !res.status().is_success() {
+        error!("error fetch image {}, status: {}", url, res.status());
+        return Ok(empty_response(res.status().as_u16()));
+    }
 
     let content_type = res
         .headers()
@@ -101,10 +514,7 @@ pub async fn get_image_from_url(url: String) -> Result<Response<Bytes>, Infallib
 
     let content_type = match content_type {
         Some(content_type) => content_type.to_string(),
-        None => match url.split('.').rev().take(1).next() {
-            Some(ext) => ["image", ext].join("/"),
-            None => "application/octet-stream".to_string(),
-        },
+        None => content_type_from_url(&url),
     };
 
     let bytes = match res.bytes().await {
@@ -115,19 +525,121 @@ pub async fn get_image_from_url(url: String) -> Result<Response<Bytes>, Infallib
         }
     };
 
+    Ok(image_response(content_type, bytes))
+}
+
+fn content_type_from_url(url: &str) -> String {
+    match url.split('.').rev().take(1).next() {
+        Some(ext) => ["image", ext].join("/"),
+        None => "application/octet-stream".to_string(),
+    }
+}
+
+fn image_response(content_type: String, bytes: Bytes) -> Response<Bytes> {
     match warp::http::Response::builder()
         .header("Content-Type", content_type)
         .header("Content-Length", bytes.len())
         .header("Cache-Control", "max-age=315360000")
         .body(bytes)
     {
-        Ok(res) => Ok(res),
+        Ok(res) => res,
         Err(e) => {
             error!("error create response, reason: {}", e);
-            Ok(warp::http::Response::builder()
-                .status(500)
-                .body(bytes::Bytes::new())
-                .unwrap_or_default())
+            empty_response(500)
         }
     }
 }
+
+#[derive(Default)]
+pub struct ImageCacheMutationRoot;
+
+#[Object]
+impl ImageCacheMutationRoot {
+    /// Remove all cached images, returns number of bytes freed
+    async fn clear_image_cache(&self, ctx: &Context<'_>) -> GqlResult<i64> {
+        if !user::check_is_admin(ctx)? {
+            return Err("Forbidden".into());
+        }
+
+        let ctx = ctx.data::<GlobalContext>()?;
+        Ok(ctx.image_cache.clear().await as i64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn test_cache_index_evict_least_recently_used() {
+        let mut index = CacheIndex::default();
+        index.insert("a".to_string(), 10);
+        index.insert("b".to_string(), 10);
+        index.insert("c".to_string(), 10);
+        assert!(index.touch("a"));
+
+        assert_eq!(index.evict(20), vec!["b".to_string()]);
+        assert_eq!(index.size, 20);
+        assert!(!index.touch("b"));
+        assert!(index.touch("a"));
+        assert!(index.touch("c"));
+    }
+
+    #[tokio::test]
+    async fn test_image_cache_evict() {
+        let path = cache_dir("tanoshi_test_image_cache_evict");
+        // each entry is 11 bytes of content type, 67 of etag and 4 of image
+        let cache = ImageCache::open(&path, 200).unwrap();
+        let image = Bytes::from_static(b"\xFF\xD8\xFF\xD9");
+
+        cache.put("http://a/1.jpg", "image/jpeg", &image).await;
+        cache.put("http://a/2.jpg", "image/jpeg", &image).await;
+        assert!(cache.get("http://a/1.jpg").await.is_some());
+        cache.put("http://a/3.jpg", "image/jpeg", &image).await;
+
+        assert!(cache.get("http://a/1.jpg").await.is_some());
+        assert!(cache.get("http://a/2.jpg").await.is_none());
+        assert_eq!(
+            cache.get("http://a/3.jpg").await.map(|cached| cached.bytes),
+            Some(image)
+        );
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[tokio::test]
+    async fn test_error_response_not_cached() {
+        let path = cache_dir("tanoshi_test_error_response_not_cached");
+        let cache = ImageCache::open(&path, 1024 * 1024).unwrap();
+
+        let route = warp::path!("missing.jpg")
+            .map(|| warp::reply::with_status("not found", warp::http::StatusCode::NOT_FOUND));
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let url = format!("http://{}/missing.jpg", addr);
+        let res = get_image_from_url(url.clone()).await.unwrap();
+        assert_eq!(res.status(), 404);
+
+        let res = cache_response(&cache, &url, res, None).await;
+        assert_eq!(res.status(), 404);
+        assert!(cache.get(&url).await.is_none());
+
+        let image = Bytes::from_static(b"\xFF\xD8\xFF\xD9");
+        let res = image_response("image/jpeg".to_string(), image.clone());
+        let res = cache_response(&cache, &url, res, None).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            cache.get(&url).await.map(|cached| cached.bytes),
+            Some(image)
+        );
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    convert::TryFrom,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::RepositoryConfig;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceIndex {
+    pub id: i64,
+    pub name: String,
+    pub path: String,
+    pub version: String,
+    pub icon: String,
+    /// Version of tanoshi-lib the extension is built against, older index doesn't have it
+    #[serde(default)]
+    pub lib_version: Option<String>,
+    #[serde(default)]
+    pub sha256: String,
+    /// Base64 encoded ed25519 signature of extension file
+    pub signature: Option<String>,
+    /// Name of repository listing this source
+    #[serde(skip)]
+    pub repository: String,
+}
+
+/// Extension repositories, each is a base url of `http(s)://`, `file://`
+/// or a local directory containing `index.json` generated by `tanoshi-cli`
+#[derive(Debug, Clone)]
+pub struct Repositories {
+    client: reqwest::Client,
+    repositories: Vec<RepositoryConfig>,
+    trusted_keys: Vec<PublicKey>,
+}
+
+impl Repositories {
+    /// `trusted_keys` are base64 encoded ed25519 public keys, if not empty
+    /// only extension signed by one of them can be installed
+    pub fn new(repositories: Vec<RepositoryConfig>, trusted_keys: &[String]) -> Result<Self> {
+        let trusted_keys = trusted_keys
+            .iter()
+            .map(|key| Ok(PublicKey::from_bytes(&base64::decode(key)?)?))
+            .collect::<Result<Vec<PublicKey>>>()?;
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            repositories,
+            trusted_keys,
+        })
+    }
+
+    async fn read(&self, repository: &RepositoryConfig, path: &str) -> Result<Bytes> {
+        let url = repository.url.trim_end_matches('/');
+        if url.starts_with("http://") || url.starts_with("https://") {
+            let res = self
+                .client
+                .get(format!("{}/{}", url, path))
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(res.bytes().await?)
+        } else {
+            let dir = url.strip_prefix("file://").unwrap_or(url);
+            let raw = tokio::fs::read(PathBuf::from(dir).join(path)).await?;
+            Ok(Bytes::from(raw))
+        }
+    }
+
+    async fn read_index(&self, repository: &RepositoryConfig) -> Result<Vec<SourceIndex>> {
+        let raw = self.read(repository, "index.json").await?;
+        Ok(serde_json::from_slice(&raw)?)
+    }
+
+    /// Merged index of all repositories, if a source is listed in more than one
+    /// repository, the one from repository listed first in config is used
+    pub async fn index(&self) -> Result<Vec<SourceIndex>> {
+        let mut sources: Vec<SourceIndex> = vec![];
+        let mut last_error = None;
+        for repository in self.repositories.iter() {
+            let index = match self.read_index(repository).await {
+                Ok(index) => index,
+                Err(e) => {
+                    error!("failed to read {} repository: {}", repository.name, e);
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+
+            for mut source in index {
+                if sources.iter().any(|s| s.id == source.id) {
+                    continue;
+                }
+                source.repository = repository.name.clone();
+                sources.push(source);
+            }
+        }
+
+        match last_error {
+            Some(e) if sources.is_empty() => Err(e),
+            _ => Ok(sources),
+        }
+    }
+
+    pub async fn find(&self, source_id: i64) -> Result<SourceIndex> {
+        self.index()
+            .await?
+            .into_iter()
+            .find(|index| index.id == source_id)
+            .ok_or_else(|| anyhow!("source not found"))
+    }
+
+    /// Path of extension listed in index must be relative to its repository and stay in it,
+    /// e.g. `../` or an absolute url could make a repository serve any file or url
+    fn validate_path(path: &str) -> Result<()> {
+        let is_relative = !path.is_empty()
+            && !path.starts_with(&['/', '\\'][..])
+            && path
+                .split(&['/', '\\'][..])
+                .all(|part| part != ".." && !part.contains(':'))
+            && Path::new(path)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !is_relative {
+            bail!("invalid extension path {} in index", path);
+        }
+
+        Ok(())
+    }
+
+    fn verify(
+        &self,
+        repository: &RepositoryConfig,
+        source: &SourceIndex,
+        raw: &[u8],
+    ) -> Result<()> {
+        if source.sha256.is_empty() {
+            if repository.signed {
+                bail!("{} has no checksum", source.name);
+            }
+            warn!(
+                "{} has no checksum in {} repository",
+                source.name, repository.name
+            );
+        } else if !format!("{:x}", Sha256::digest(raw)).eq_ignore_ascii_case(&source.sha256) {
+            bail!("checksum of {} doesn't match", source.name);
+        }
+
+        if self.trusted_keys.is_empty() {
+            return Ok(());
+        }
+
+        let signature = source
+            .signature
+            .as_ref()
+            .ok_or_else(|| anyhow!("{} is not signed", source.name))?;
+        let signature = Signature::try_from(base64::decode(signature)?.as_slice())?;
+        if !self
+            .trusted_keys
+            .iter()
+            .any(|key| key.verify(raw, &signature).is_ok())
+        {
+            bail!("{} is not signed by trusted key", source.name);
+        }
+
+        Ok(())
+    }
+
+    /// Fetch extension file of a source from its repository, refuse file
+    /// that fail checksum or signature verification
+    pub async fn fetch(&self, source: &SourceIndex) -> Result<Bytes> {
+        let repository = self
+            .repositories
+            .iter()
+            .find(|repository| repository.name == source.repository)
+            .ok_or_else(|| anyhow!("repository {} not found", source.repository))?;
+
+        Self::validate_path(&source.path)?;
+        let raw = self.read(repository, &source.path).await?;
+        self.verify(repository, source, &raw)?;
+
+        Ok(raw)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+    fn keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = (&secret).into();
+        Keypair { secret, public }
+    }
+
+    fn repository(signed: bool) -> RepositoryConfig {
+        RepositoryConfig {
+            name: "test".to_string(),
+            url: "/tmp/repo".to_string(),
+            signed,
+        }
+    }
+
+    fn source(raw: &[u8], signature: Option<String>) -> SourceIndex {
+        SourceIndex {
+            id: 1,
+            name: "test".to_string(),
+            path: "library/test.wasm".to_string(),
+            version: "0.1.0".to_string(),
+            icon: String::new(),
+            lib_version: None,
+            sha256: format!("{:x}", Sha256::digest(raw)),
+            signature,
+            repository: "test".to_string(),
+        }
+    }
+
+    fn repositories(keys: &[&Keypair]) -> Repositories {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|key| base64::encode(key.public.as_bytes()))
+            .collect();
+        Repositories::new(vec![repository(true)], &keys).unwrap()
+    }
+
+    #[test]
+    fn test_validate_path() {
+        for path in &["test.wasm", "library/test.wasm", "./library/test.wasm"] {
+            assert!(Repositories::validate_path(path).is_ok(), "{}", path);
+        }
+
+        for path in &[
+            "",
+            "../test.wasm",
+            "library/../../test.wasm",
+            "library\\..\\..\\test.wasm",
+            "/etc/passwd",
+            "\\\\server\\share\\test.wasm",
+            "C:\\test.wasm",
+            "https://example.com/test.wasm",
+            "file:///etc/passwd",
+        ] {
+            assert!(Repositories::validate_path(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let raw = b"extension";
+        let repositories = repositories(&[]);
+
+        assert!(repositories
+            .verify(&repository(true), &source(raw, None), raw)
+            .is_ok());
+        assert!(repositories
+            .verify(&repository(true), &source(raw, None), b"tampered")
+            .is_err());
+    }
+
+    #[test]
+    fn test_verify_missing_checksum() {
+        let raw = b"extension";
+        let repositories = repositories(&[]);
+        let source = SourceIndex {
+            sha256: String::new(),
+            ..source(raw, None)
+        };
+
+        assert!(repositories
+            .verify(&repository(true), &source, raw)
+            .is_err());
+        assert!(repositories
+            .verify(&repository(false), &source, raw)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let raw = b"extension";
+        let trusted = keypair(1);
+        let untrusted = keypair(2);
+        let repositories = repositories(&[&trusted]);
+
+        let signed_by = |key: &Keypair| Some(base64::encode(key.sign(raw).to_bytes()));
+
+        assert!(repositories
+            .verify(&repository(true), &source(raw, signed_by(&trusted)), raw)
+            .is_ok());
+        assert!(repositories
+            .verify(&repository(true), &source(raw, signed_by(&untrusted)), raw)
+            .is_err());
+        assert!(repositories
+            .verify(&repository(true), &source(raw, None), raw)
+            .is_err());
+    }
+}
@@ -1,11 +1,13 @@
 use crate::catalogue::{CatalogueRoot, SourceMutationRoot, SourceRoot};
 use crate::library::{LibraryMutationRoot, LibraryRoot};
 use crate::notifier::NotificationRoot;
+use crate::proxy::ImageCacheMutationRoot;
 use crate::status::StatusRoot;
+use crate::subscription::SubscriptionRoot;
 use crate::user::{UserMutationRoot, UserRoot};
-use async_graphql::{EmptySubscription, MergedObject, Schema};
+use async_graphql::{MergedObject, Schema};
 
-pub type TanoshiSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+pub type TanoshiSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 
 #[derive(MergedObject, Default)]
 pub struct QueryRoot(
@@ -18,4 +20,9 @@ pub struct QueryRoot(
 );
 
 #[derive(MergedObject, Default)]
-pub struct MutationRoot(LibraryMutationRoot, UserMutationRoot, SourceMutationRoot);
+pub struct MutationRoot(
+    LibraryMutationRoot,
+    UserMutationRoot,
+    SourceMutationRoot,
+    ImageCacheMutationRoot,
+);
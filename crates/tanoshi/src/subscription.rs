@@ -0,0 +1,122 @@
+use crate::{context::GlobalContext, library::RecentUpdate, user};
+use async_graphql::{Context, Enum, Result, SimpleObject, Subscription};
+use futures::{Stream, StreamExt};
+use tokio::sync::broadcast::{error::RecvError, Sender};
+
+/// Event published to connected clients
+#[derive(Debug, Clone)]
+pub enum Event {
+    ChapterUpdate(RecentUpdate),
+    JobProgress(JobProgress),
+    LibraryChange(LibraryChange),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Enum)]
+pub enum Job {
+    LibraryUpdate,
+    Download,
+}
+
+/// Progress of a long running job, `id` is set for job working on a specific entity
+#[derive(Debug, Clone, SimpleObject)]
+pub struct JobProgress {
+    /// User the job works for, `None` for job of the whole server which only admins see
+    #[graphql(skip)]
+    pub user_id: Option<i64>,
+    pub job: Job,
+    pub id: Option<i64>,
+    pub current: i64,
+    pub total: i64,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Enum)]
+pub enum LibraryAction {
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct LibraryChange {
+    #[graphql(skip)]
+    pub user_id: i64,
+    pub manga_id: i64,
+    pub action: LibraryAction,
+}
+
+fn subscribe(tx: &Sender<Event>) -> impl Stream<Item = Event> {
+    futures::stream::unfold(tx.subscribe(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("subscriber lagged, {} events skipped", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+#[derive(Default)]
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    async fn chapter_updates(&self, ctx: &Context<'_>) -> Result<impl Stream<Item = RecentUpdate>> {
+        let user_id = user::get_claims(ctx)?.sub;
+        let ctx = ctx.data::<GlobalContext>()?;
+        let mangadb = ctx.mangadb.clone();
+
+        Ok(subscribe(&ctx.event_tx).filter_map(move |event| {
+            let mangadb = mangadb.clone();
+            async move {
+                if let Event::ChapterUpdate(update) = event {
+                    if mangadb
+                        .is_user_library(user_id, update.manga_id)
+                        .await
+                        .unwrap_or(false)
+                    {
+                        return Some(update);
+                    }
+                }
+                None
+            }
+        }))
+    }
+
+    async fn job_progress(&self, ctx: &Context<'_>) -> Result<impl Stream<Item = JobProgress>> {
+        let claims = user::get_claims(ctx)?;
+        let (user_id, is_admin) = (claims.sub, claims.is_admin);
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        Ok(
+            subscribe(&ctx.event_tx).filter_map(move |event| async move {
+                match event {
+                    Event::JobProgress(progress)
+                        if progress.user_id.map_or(is_admin, |id| id == user_id) =>
+                    {
+                        Some(progress)
+                    }
+                    _ => None,
+                }
+            }),
+        )
+    }
+
+    async fn library_changes(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<impl Stream<Item = LibraryChange>> {
+        let user_id = user::get_claims(ctx)?.sub;
+        let ctx = ctx.data::<GlobalContext>()?;
+
+        Ok(
+            subscribe(&ctx.event_tx).filter_map(move |event| async move {
+                match event {
+                    Event::LibraryChange(change) if change.user_id == user_id => Some(change),
+                    _ => None,
+                }
+            }),
+        )
+    }
+}
@@ -1,5 +1,5 @@
-use crate::context::GlobalContext;
-use async_graphql::{Context, Object, Result};
+use crate::{context::GlobalContext, notifier::NotifierKind};
+use async_graphql::{Context, MaybeUndefined, Object, Result};
 use rand::RngCore;
 
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
@@ -19,6 +19,10 @@ use async_graphql::SimpleObject;
 #[derive(Debug, Default, SimpleObject)]
 pub struct Settings {
     telegram_chat_id: Option<i64>,
+    notifier: Option<NotifierKind>,
+    discord_webhook: Option<String>,
+    webhook_url: Option<String>,
+    email: Option<String>,
 }
 
 #[derive(Debug, SimpleObject)]
@@ -40,6 +44,10 @@ impl From<crate::db::model::User> for User {
             is_admin: val.is_admin,
             settings: Settings {
                 telegram_chat_id: val.telegram_chat_id,
+                notifier: val.notifier,
+                discord_webhook: val.discord_webhook,
+                webhook_url: val.webhook_url,
+                email: val.email,
             },
         }
     }
@@ -197,16 +205,32 @@ impl UserMutationRoot {
     async fn update_profile(
         &self,
         ctx: &Context<'_>,
-        #[graphql(desc = "telegram chat id")] telegram_chat_id: Option<i64>,
+        #[graphql(desc = "telegram chat id")] telegram_chat_id: MaybeUndefined<i64>,
+        #[graphql(desc = "notifier used for notification")] notifier: MaybeUndefined<NotifierKind>,
+        #[graphql(desc = "discord webhook url")] discord_webhook: MaybeUndefined<String>,
+        #[graphql(desc = "generic webhook url")] webhook_url: MaybeUndefined<String>,
+        #[graphql(desc = "email address")] email: MaybeUndefined<String>,
     ) -> Result<u64> {
         debug!("update_profile");
         let claims = get_claims(ctx)?;
 
+        if let MaybeUndefined::Value(webhook) = &discord_webhook {
+            crate::notifier::discord::validate_webhook(webhook)?;
+        }
+        if let MaybeUndefined::Value(url) = &webhook_url {
+            crate::notifier::validate_url(url).await?;
+        }
+
         let userdb = &ctx.data::<GlobalContext>()?.userdb;
         let mut user = userdb.get_user_by_id(claims.sub).await?;
         debug!("update_profile");
 
-        user.telegram_chat_id = telegram_chat_id;
+        // omitted argument keeps its value, null clears it
+        update(&mut user.telegram_chat_id, telegram_chat_id);
+        update(&mut user.notifier, notifier);
+        update(&mut user.discord_webhook, discord_webhook);
+        update(&mut user.webhook_url, webhook_url);
+        update(&mut user.email, email);
 
         let row = userdb.update_user_setting(&user).await?;
 
@@ -216,6 +240,14 @@ impl UserMutationRoot {
     }
 }
 
+fn update<T>(value: &mut Option<T>, arg: MaybeUndefined<T>) {
+    match arg {
+        MaybeUndefined::Undefined => {}
+        MaybeUndefined::Null => *value = None,
+        MaybeUndefined::Value(arg) => *value = Some(arg),
+    }
+}
+
 pub fn get_claims(ctx: &Context<'_>) -> Result<Claims> {
     let token = ctx
         .data::<String>()
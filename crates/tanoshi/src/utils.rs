@@ -47,6 +47,28 @@ pub fn decrypt_url(key: &str, data: &str) -> Result<String, Box<dyn std::error::
     Ok(url)
 }
 
+/// Encrypt with random iv prepended to chipertext, for data stored in database
+pub fn encrypt(key: &str, data: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+    let iv: [u8; 16] = rand::thread_rng().gen();
+    let chiper = Aes128Cbc::new_from_slices(key.as_bytes(), &iv)?;
+    let mut encrypted = iv.to_vec();
+    encrypted.extend(chiper.encrypt_vec(data));
+
+    Ok(base64::encode(encrypted))
+}
+
+pub fn decrypt(key: &str, data: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let decoded = base64::decode(data)?;
+    if decoded.len() < 16 {
+        return Err("encrypted data too short".into());
+    }
+
+    let (iv, chipertext) = decoded.split_at(16);
+    let chiper = Aes128Cbc::new_from_slices(key.as_bytes(), iv)?;
+
+    Ok(chiper.decrypt_vec(chipertext)?)
+}
+
 /*
 use jsonwebtoken::{self, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
@@ -82,6 +104,23 @@ pub fn validate_url(secret: &str, data: &str) -> Result<String, Box<dyn std::err
 
 */
 
+/// Whether `url` is on host of `source_url` or its subdomain, ignoring `www.`
+pub fn is_source_url(source_url: &str, url: &str) -> bool {
+    let host = |url: &str| {
+        reqwest::Url::parse(url).ok().and_then(|url| {
+            url.host_str()
+                .map(|host| host.strip_prefix("www.").unwrap_or(host).to_lowercase())
+        })
+    };
+
+    match (host(source_url), host(url)) {
+        (Some(source_host), Some(host)) => {
+            host == source_host || host.ends_with(&format!(".{}", source_host))
+        }
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -97,6 +136,16 @@ mod test {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = "pdn8QwMUTDSVfKQf".to_string();
+        let data = b"secret token";
+
+        let encrypted = encrypt(&key, data).unwrap();
+        assert_ne!(encrypt(&key, data).unwrap(), encrypted);
+        assert_eq!(decrypt(&key, &encrypted).unwrap(), data.to_vec());
+    }
+
     #[test]
     fn test_decrypt_url() {
         let key = "pdn8QwMUTDSVfKQf".to_string();
@@ -112,4 +161,17 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_is_source_url() {
+        let source_url = "https://www.example.com";
+
+        assert!(is_source_url(source_url, "https://example.com/manga/1"));
+        assert!(is_source_url(source_url, "http://www.example.com/manga/1"));
+        assert!(is_source_url(source_url, "https://m.Example.com/manga/1"));
+        assert!(!is_source_url(source_url, "https://notexample.com/manga/1"));
+        assert!(!is_source_url(source_url, "https://example.com.evil.org/"));
+        assert!(!is_source_url(source_url, "example.com/manga/1"));
+        assert!(!is_source_url("", "https://example.com/manga/1"));
+    }
 }
@@ -1,24 +1,35 @@
-use std::{collections::HashMap, fmt::Display, str::FromStr};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    str::FromStr,
+};
 
+use futures::future::OptionFuture;
 use serde::Deserialize;
 use tanoshi_lib::prelude::Version;
 use tanoshi_vm::prelude::ExtensionBus;
-use teloxide::{
-    adaptors::{AutoSend, DefaultParseMode},
-    prelude::Requester,
-    Bot,
+use tokio::sync::{
+    broadcast,
+    mpsc::{UnboundedReceiver, UnboundedSender},
 };
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::{
     sync::mpsc::unbounded_channel,
     task::JoinHandle,
     time::{self, Instant},
 };
 
-use crate::db::{model::Chapter, MangaDatabase, UserDatabase};
+use crate::{
+    catalogue,
+    db::{model::Chapter, MangaDatabase, UserDatabase},
+    library::RecentUpdate,
+    notifier::{Notification, Notifiers},
+    repository::Repositories,
+    subscription::{Event, Job, JobProgress},
+};
 
 pub enum Command {
-    TelegramMessage(i64, String),
+    /// Download chapters for a user, pages are fetched with the user's login
+    DownloadChapters(i64, Vec<i64>),
 }
 
 #[derive(Debug, Clone)]
@@ -28,30 +39,42 @@ struct ChapterUpdate {
     title: String,
 }
 
-impl Display for ChapterUpdate {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let manga_title = html_escape::encode_safe(&self.manga_title).to_string();
-        let title = html_escape::encode_safe(&self.title).to_string();
-
-        write!(f, "<b>{}</b>\n{}", manga_title, title)
+impl From<ChapterUpdate> for Notification {
+    fn from(update: ChapterUpdate) -> Self {
+        // cover of local manga is a path on this server, which can't be shown outside of it
+        let image_url = Some(update.cover_url)
+            .filter(|url| url.starts_with("http://") || url.starts_with("https://"));
+        Self {
+            title: update.manga_title,
+            body: update.title,
+            image_url,
+        }
     }
 }
 
 struct Worker {
     period: u64,
+    downloader: Downloader,
     mangadb: MangaDatabase,
     userdb: UserDatabase,
+    secret: String,
     extension_bus: ExtensionBus,
-    telegram_bot: Option<DefaultParseMode<AutoSend<Bot>>>,
+    repositories: Repositories,
+    notifiers: Notifiers,
+    event_tx: broadcast::Sender<Event>,
 }
 
 impl Worker {
     fn new(
         period: u64,
+        download_path: PathBuf,
         mangadb: MangaDatabase,
         userdb: UserDatabase,
+        secret: String,
         extension_bus: ExtensionBus,
-        telegram_bot: Option<DefaultParseMode<AutoSend<Bot>>>,
+        repositories: Repositories,
+        notifiers: Notifiers,
+        event_tx: broadcast::Sender<Event>,
     ) -> Self {
         #[cfg(not(debug_assertions))]
         let period = if period > 0 && period < 3600 {
@@ -60,31 +83,55 @@ impl Worker {
             period
         };
         info!("periodic updates every {} secons", period);
+        let downloader = Downloader {
+            download_path,
+            mangadb: mangadb.clone(),
+            userdb: userdb.clone(),
+            secret: secret.clone(),
+            extension_bus: extension_bus.clone(),
+            event_tx: event_tx.clone(),
+        };
         Self {
             period,
+            downloader,
             mangadb,
             userdb,
+            secret,
             extension_bus,
-            telegram_bot,
+            repositories,
+            notifiers,
+            event_tx,
         }
     }
 
+    fn publish(&self, event: Event) {
+        // sending only fails when there is no subscriber
+        let _ = self.event_tx.send(event);
+    }
+
     async fn check_chapter_update(&self) -> Result<(), anyhow::Error> {
         let manga_in_library = self.mangadb.get_all_user_library().await?;
 
         let mut new_manga_chapter: HashMap<i64, Vec<ChapterUpdate>> = HashMap::new();
         let mut new_users_chapters: HashMap<i64, Vec<ChapterUpdate>> = HashMap::new();
 
-        for (telegram_chat_id, manga) in manga_in_library {
+        let total = manga_in_library.len() as i64;
+        for (index, (user_id, manga)) in manga_in_library.into_iter().enumerate() {
+            self.publish(Event::JobProgress(JobProgress {
+                user_id: None,
+                job: Job::LibraryUpdate,
+                id: None,
+                current: index as i64 + 1,
+                total,
+            }));
+
             if let Some(chapters) = new_manga_chapter.get(&manga.id) {
-                if let Some(telegram_chat_id) = telegram_chat_id {
-                    match new_users_chapters.get_mut(&telegram_chat_id) {
-                        Some(user_chapters) => {
-                            user_chapters.extend_from_slice(chapters);
-                        }
-                        None => {
-                            new_users_chapters.insert(telegram_chat_id, chapters.clone());
-                        }
+                match new_users_chapters.get_mut(&user_id) {
+                    Some(user_chapters) => {
+                        user_chapters.extend_from_slice(chapters);
+                    }
+                    None => {
+                        new_users_chapters.insert(user_id, chapters.clone());
                     }
                 }
                 continue;
@@ -94,9 +141,12 @@ impl Worker {
                 .mangadb
                 .get_last_uploaded_chapters_by_manga_id(manga.id)
                 .await;
+            let session =
+                catalogue::get_source_session(&self.userdb, &self.secret, user_id, manga.source_id)
+                    .await;
             let chapters = match self
                 .extension_bus
-                .get_chapters(manga.source_id, manga.path.clone())
+                .get_chapters(manga.source_id, manga.path.clone(), session)
                 .await
             {
                 Ok(chapters) => {
@@ -130,6 +180,23 @@ impl Worker {
                 chapters
             };
 
+            for chapter in chapters.iter() {
+                if let Some(chapter) = self
+                    .mangadb
+                    .get_chapter_by_source_path(chapter.source_id, &chapter.path)
+                    .await
+                {
+                    self.publish(Event::ChapterUpdate(RecentUpdate {
+                        manga_id: manga.id,
+                        chapter_id: chapter.id,
+                        manga_title: manga.title.clone(),
+                        cover_url: manga.cover_url.clone(),
+                        chapter_title: chapter.title,
+                        uploaded: chapter.uploaded,
+                    }));
+                }
+            }
+
             let chapters: Vec<ChapterUpdate> = chapters
                 .iter()
                 .map(|ch| ChapterUpdate {
@@ -140,14 +207,12 @@ impl Worker {
                 .collect();
 
             new_manga_chapter.insert(manga.id, chapters.clone());
-            if let Some(telegram_chat_id) = telegram_chat_id {
-                match new_users_chapters.get_mut(&telegram_chat_id) {
-                    Some(user_chapters) => {
-                        user_chapters.extend_from_slice(&chapters);
-                    }
-                    None => {
-                        new_users_chapters.insert(telegram_chat_id, chapters);
-                    }
+            match new_users_chapters.get_mut(&user_id) {
+                Some(user_chapters) => {
+                    user_chapters.extend_from_slice(&chapters);
+                }
+                None => {
+                    new_users_chapters.insert(user_id, chapters);
                 }
             }
 
@@ -156,34 +221,43 @@ impl Worker {
 
         info!("users' new chapters: {:?}", new_users_chapters);
 
-        if let Some(bot) = self.telegram_bot.as_ref() {
-            for (chat_id, chapters) in new_users_chapters.into_iter() {
-                for chapter in chapters {
-                    if let Err(e) = bot.send_message(chat_id, chapter.to_string()).await {
-                        error!("failed to send message, reason: {}", e);
-                    }
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        for (user_id, chapters) in new_users_chapters.into_iter() {
+            let user = match self.userdb.get_user_by_id(user_id).await {
+                Ok(user) => user,
+                Err(e) => {
+                    error!("failed to get user {}, reason: {}", user_id, e);
+                    continue;
+                }
+            };
+            if user.notifier.is_none() {
+                continue;
+            }
+
+            for chapter in chapters {
+                if let Err(e) = self.notifiers.send(&user, &chapter.into()).await {
+                    error!("failed to send notification, reason: {}", e);
                 }
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
             }
         }
 
         Ok(())
     }
 
-    async fn check_extension_update(&self) -> Result<(), anyhow::Error> {
-        #[derive(Debug, Clone, Deserialize)]
-        pub struct SourceIndex {
-            pub id: i64,
-            pub name: String,
-            pub path: String,
-            pub version: String,
-            pub icon: String,
+    async fn notify_admins(&self, notification: &Notification) -> Result<(), anyhow::Error> {
+        let admins = self.userdb.get_admins().await?;
+        for admin in admins {
+            if let Err(e) = self.notifiers.send(&admin, notification).await {
+                error!("failed to notify {}, reason: {}", admin.username, e);
+            }
         }
 
+        Ok(())
+    }
+
+    async fn check_extension_update(&self) -> Result<(), anyhow::Error> {
         let available_sources_map = {
-            let url = "https://raw.githubusercontent.com/faldez/tanoshi-extensions/repo/index.json"
-                .to_string();
-            let available_sources = reqwest::get(url).await?.json::<Vec<SourceIndex>>().await?;
+            let available_sources = self.repositories.index().await?;
             let mut available_sources_map = HashMap::new();
             for source in available_sources {
                 available_sources_map.insert(source.id, source);
@@ -198,11 +272,15 @@ impl Worker {
                 .await
                 .map_err(|e| anyhow::anyhow!("{}", e))?;
 
-            let mut updates: Vec<String> = vec![];
+            let mut updates: Vec<Notification> = vec![];
             for source in installed_sources {
                 if let Some(index) = available_sources_map.get(&source.id) {
                     if Version::from_str(&index.version)? > source.version {
-                        updates.push(format!("<b>{}</b> extension update available", source.name));
+                        updates.push(Notification {
+                            title: source.name,
+                            body: format!("extension update {} available", index.version),
+                            image_url: None,
+                        });
                     }
                 }
             }
@@ -216,14 +294,7 @@ impl Worker {
 
         for update in updates {
             info!("new extension update found!");
-            if let Some(bot) = self.telegram_bot.as_ref() {
-                let admins = self.userdb.get_admins().await?;
-                for admin in admins {
-                    if let Some(chat_id) = admin.telegram_chat_id {
-                        bot.send_message(chat_id, &update).await?;
-                    }
-                }
-            }
+            self.notify_admins(&update).await?;
         }
 
         Ok(())
@@ -252,21 +323,12 @@ impl Worker {
             > Version::from_str(env!("CARGO_PKG_VERSION"))?
         {
             info!("new server update found!");
-            if let Some(bot) = self.telegram_bot.as_ref() {
-                let admins = self.userdb.get_admins().await?;
-                for admin in admins {
-                    if let Some(chat_id) = admin.telegram_chat_id {
-                        bot.send_message(
-                            chat_id,
-                            format!(
-                                "<b>Tanoshi {} Released</b>\n{}",
-                                release.tag_name, release.body
-                            ),
-                        )
-                        .await?;
-                    }
-                }
-            }
+            self.notify_admins(&Notification {
+                title: format!("Tanoshi {} Released", release.tag_name),
+                body: release.body,
+                image_url: None,
+            })
+            .await?;
         } else {
             info!("no tanoshi update found");
         }
@@ -276,23 +338,46 @@ impl Worker {
 
     async fn run(&self, rx: UnboundedReceiver<Command>) {
         let mut rx = rx;
+        // user and chapter to download for
+        let mut download_queue: VecDeque<(i64, i64)> = VecDeque::new();
+        // chapter being downloaded, one at a time
+        let mut download: OptionFuture<JoinHandle<()>> = None.into();
+        let mut downloading = false;
         let period = if self.period == 0 { 3600 } else { self.period };
         let mut chapter_update_interval = time::interval(time::Duration::from_secs(period));
         let mut server_update_interval = time::interval(time::Duration::from_secs(86400));
 
         loop {
+            if !downloading {
+                if let Some((user_id, chapter_id)) = download_queue.pop_front() {
+                    let downloader = self.downloader.clone();
+                    download = Some(tokio::spawn(async move {
+                        info!("downloading chapter {}", chapter_id);
+                        if let Err(e) = downloader.download_chapter(user_id, chapter_id).await {
+                            error!("failed to download chapter {}: {}", chapter_id, e);
+                        }
+                    }))
+                    .into();
+                    downloading = true;
+                }
+            }
+
             tokio::select! {
                 Some(cmd) = rx.recv() => {
                     match cmd {
-                        Command::TelegramMessage(chat_id, message) => {
-                            if let Some(bot) = self.telegram_bot.as_ref() {
-                                if let Err(e) = bot.send_message(chat_id, message).await {
-                                    error!("failed to send TelegramMessage, reason: {}", e);
+                        Command::DownloadChapters(user_id, chapter_ids) => {
+                            for chapter_id in chapter_ids {
+                                if !download_queue.iter().any(|(_, id)| *id == chapter_id) {
+                                    download_queue.push_back((user_id, chapter_id));
                                 }
                             }
+                            info!("{} chapters in download queue", download_queue.len());
                         }
                     }
                 }
+                _ = &mut download, if downloading => {
+                    downloading = false;
+                }
                 start = chapter_update_interval.tick() => {
                     if self.period == 0 {
                         continue;
@@ -324,15 +409,150 @@ impl Worker {
     }
 }
 
+/// Download chapters for `Worker`, each in its own task so it doesn't hold back other jobs
+#[derive(Clone)]
+struct Downloader {
+    download_path: PathBuf,
+    mangadb: MangaDatabase,
+    userdb: UserDatabase,
+    secret: String,
+    extension_bus: ExtensionBus,
+    event_tx: broadcast::Sender<Event>,
+}
+
+impl Downloader {
+    fn publish(&self, event: Event) {
+        // sending only fails when there is no subscriber
+        let _ = self.event_tx.send(event);
+    }
+
+    async fn download_chapter(&self, user_id: i64, chapter_id: i64) -> Result<(), anyhow::Error> {
+        let chapter = self.mangadb.get_chapter_by_id(chapter_id).await?;
+        if chapter.source_id == crate::local::ID {
+            info!("chapter {} is from local source, skip download", chapter_id);
+            return Ok(());
+        }
+
+        let session =
+            catalogue::get_source_session(&self.userdb, &self.secret, user_id, chapter.source_id)
+                .await;
+        let mut pages = self.mangadb.get_pages_by_chapter_id(chapter.id).await?;
+        if pages.is_empty() {
+            let remote_pages = self
+                .extension_bus
+                .get_pages(chapter.source_id, chapter.path.clone(), session.clone())
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            self.mangadb.insert_pages(chapter.id, &remote_pages).await?;
+            pages = self.mangadb.get_pages_by_chapter_id(chapter.id).await?;
+        }
+
+        let manga = self.mangadb.get_manga_by_id(chapter.manga_id).await?;
+        let source = self
+            .extension_bus
+            .detail(chapter.source_id)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+        let chapter_path = self
+            .download_path
+            .join(sanitize_path_component(&source.name))
+            .join(sanitize_path_component(&manga.title))
+            .join(sanitize_path_component(&format!(
+                "{} - {}",
+                chapter.number, chapter.title
+            )));
+        tokio::fs::create_dir_all(&chapter_path).await?;
+
+        let total = pages.len() as i64;
+        for (index, page) in pages.into_iter().enumerate() {
+            self.publish(Event::JobProgress(JobProgress {
+                user_id: Some(user_id),
+                job: Job::Download,
+                id: Some(chapter.id),
+                current: index as i64 + 1,
+                total,
+            }));
+
+            if let Some(local_url) = page.local_url.as_ref() {
+                if PathBuf::from(local_url).is_file() {
+                    continue;
+                }
+            }
+
+            // fetched by extension with user's login, as the source may reject plain requests
+            let image = self
+                .extension_bus
+                .get_page(chapter.source_id, page.remote_url.clone(), session.clone())
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            let ext = image_extension(image.content_type.as_deref());
+
+            let file = chapter_path.join(format!("{:03}.{}", page.rank + 1, ext));
+            tokio::fs::write(&file, &image.bytes).await?;
+
+            let local_url = file.display().to_string();
+            self.mangadb
+                .update_page_local_url(page.chapter_id, page.rank, &local_url)
+                .await?;
+
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Extension of a downloaded page, content type is sent by the source so only known image types
+/// are mapped and anything else is saved as `jpg`
+fn image_extension(content_type: Option<&str>) -> &'static str {
+    let essence = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|essence| essence.trim().to_ascii_lowercase());
+    match essence.as_deref() {
+        Some("image/png") => "png",
+        Some("image/gif") => "gif",
+        Some("image/webp") => "webp",
+        Some("image/avif") => "avif",
+        _ => "jpg",
+    }
+}
+
+fn sanitize_path_component(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .trim_end_matches('.')
+        .to_string()
+}
+
 pub fn start(
     period: u64,
+    download_path: PathBuf,
     mangadb: MangaDatabase,
     userdb: UserDatabase,
+    secret: String,
     extension_bus: ExtensionBus,
-    telegram_bot: Option<DefaultParseMode<AutoSend<Bot>>>,
+    repositories: Repositories,
+    notifiers: Notifiers,
+    event_tx: broadcast::Sender<Event>,
 ) -> (JoinHandle<()>, UnboundedSender<Command>) {
     let (tx, rx) = unbounded_channel();
-    let worker = Worker::new(period, mangadb, userdb, extension_bus, telegram_bot);
+    let worker = Worker::new(
+        period,
+        download_path,
+        mangadb,
+        userdb,
+        secret,
+        extension_bus,
+        repositories,
+        notifiers,
+        event_tx,
+    );
 
     let handle = tokio::spawn(async move {
         worker.run(rx).await;
@@ -340,3 +560,183 @@ pub fn start(
 
     (handle, tx)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tanoshi_lib::prelude::{
+        Chapter as SourceChapter, Extension, ExtensionResult, Filters, Manga as SourceManga,
+        PageImage, Param, Source,
+    };
+    use tanoshi_vm::{
+        bus::Timeouts, limits::Limits, logs::ExtensionLogs, storage::MemoryStorage, vm,
+        watcher::LoadedSources,
+    };
+
+    use crate::db::{self, model::Manga};
+
+    const SOURCE_ID: i64 = 100;
+
+    /// Source with a chapter of two png pages, counting pages fetched
+    struct FakeSource {
+        fetched: Arc<AtomicUsize>,
+    }
+
+    impl Extension for FakeSource {
+        fn detail(&self) -> Source {
+            Source {
+                id: SOURCE_ID,
+                name: "fake".to_string(),
+                url: "http://example.com".to_string(),
+                version: Version::default(),
+                lib_version: Version::default(),
+                icon: "".to_string(),
+                need_login: false,
+                languages: vec![],
+                allowed_hosts: vec![],
+                preferences: vec![],
+            }
+        }
+
+        fn filters(&self) -> ExtensionResult<Option<Filters>> {
+            ExtensionResult::ok(None)
+        }
+
+        fn get_manga_list(&self, _param: Param) -> ExtensionResult<Vec<SourceManga>> {
+            ExtensionResult::ok(vec![])
+        }
+
+        fn get_manga_info(&self, _path: String) -> ExtensionResult<SourceManga> {
+            ExtensionResult::err("not found")
+        }
+
+        fn get_chapters(&self, _path: String) -> ExtensionResult<Vec<SourceChapter>> {
+            ExtensionResult::ok(vec![])
+        }
+
+        fn get_pages(&self, _path: String) -> ExtensionResult<Vec<String>> {
+            ExtensionResult::ok(vec![
+                "http://example.com/1.png".to_string(),
+                "http://example.com/2.png".to_string(),
+            ])
+        }
+
+        fn get_page(&self, url: String) -> ExtensionResult<PageImage> {
+            self.fetched.fetch_add(1, Ordering::SeqCst);
+            ExtensionResult::ok(PageImage {
+                content_type: Some("image/png".to_string()),
+                bytes: url.into_bytes(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_chapter() {
+        let path = std::env::temp_dir().join("tanoshi_test_download_chapter");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        let pool = db::establish_connection(path.join("tanoshi.db").to_str().unwrap())
+            .await
+            .unwrap();
+        let mangadb = MangaDatabase::new(pool.clone());
+        let userdb = UserDatabase::new(pool);
+
+        let (_, extension_tx) = vm::start(
+            Limits::default(),
+            Arc::new(MemoryStorage::default()),
+            ExtensionLogs::default(),
+        );
+        let extension_bus = ExtensionBus::new(
+            path.join("plugins"),
+            extension_tx,
+            Timeouts::default(),
+            LoadedSources::default(),
+        );
+        let fetched = Arc::new(AtomicUsize::new(0));
+        extension_bus
+            .insert(
+                SOURCE_ID,
+                Arc::new(FakeSource {
+                    fetched: fetched.clone(),
+                }),
+            )
+            .await
+            .unwrap();
+
+        let mut manga = Manga {
+            source_id: SOURCE_ID,
+            title: "Manga".to_string(),
+            path: "/manga".to_string(),
+            ..Default::default()
+        };
+        mangadb.insert_manga(&mut manga).await.unwrap();
+        let chapter_id = mangadb
+            .insert_chapter(&Chapter {
+                source_id: SOURCE_ID,
+                manga_id: manga.id,
+                title: "Chapter".to_string(),
+                path: "/manga/1".to_string(),
+                number: 1.0,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let (event_tx, _) = broadcast::channel(10);
+        let downloader = Downloader {
+            download_path: path.join("downloads"),
+            mangadb: mangadb.clone(),
+            userdb,
+            secret: "secret".to_string(),
+            extension_bus,
+            event_tx,
+        };
+        downloader.download_chapter(1, chapter_id).await.unwrap();
+        assert_eq!(fetched.load(Ordering::SeqCst), 2);
+
+        let chapter_path = path.join("downloads/fake/Manga/1 - Chapter");
+        let pages = mangadb.get_pages_by_chapter_id(chapter_id).await.unwrap();
+        assert_eq!(pages.len(), 2);
+        for page in &pages {
+            let file = chapter_path.join(format!("{:03}.png", page.rank + 1));
+            assert_eq!(page.local_url, Some(file.display().to_string()));
+            assert_eq!(std::fs::read(&file).unwrap(), page.remote_url.as_bytes());
+        }
+
+        // only page whose local file is missing is fetched again
+        std::fs::remove_file(chapter_path.join("002.png")).unwrap();
+        downloader.download_chapter(1, chapter_id).await.unwrap();
+        assert_eq!(fetched.load(Ordering::SeqCst), 3);
+        assert!(chapter_path.join("002.png").is_file());
+
+        // page whose remote url changed is no longer downloaded
+        mangadb
+            .insert_pages(
+                chapter_id,
+                &[
+                    "http://example.com/1.png".to_string(),
+                    "http://example.com/3.png".to_string(),
+                ],
+            )
+            .await
+            .unwrap();
+        let pages = mangadb.get_pages_by_chapter_id(chapter_id).await.unwrap();
+        assert!(pages[0].local_url.is_some());
+        assert_eq!(pages[1].local_url, None);
+    }
+
+    #[test]
+    fn test_image_extension() {
+        assert_eq!(image_extension(Some("image/png")), "png");
+        assert_eq!(image_extension(Some("IMAGE/WEBP; charset=utf-8")), "webp");
+        assert_eq!(image_extension(Some("image/jpeg")), "jpg");
+        assert_eq!(image_extension(Some("image/svg+xml; charset=utf-8")), "jpg");
+        assert_eq!(image_extension(Some("image/../../../x")), "jpg");
+        assert_eq!(image_extension(None), "jpg");
+    }
+}